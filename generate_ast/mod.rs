@@ -1,3 +1,6 @@
+#[cfg(test)]
+use std::fmt;
+use std::fs;
 use std::fs::File;
 use std::io;
 use std::io::Write;
@@ -9,52 +12,106 @@ struct TreeType {
   fields: Vec<String>,
 }
 
+/// 生成的节点派生的 trait
+const DERIVES: &[&str] = &["Debug", "Clone", "PartialEq", "Serialize", "Deserialize"];
+
 pub fn generate_ast(output_dir: &str) -> io::Result<()> {
   define_ast(
     output_dir,
     "Expr",
-    &["error", "token", "object", "rc"],
+    &["error", "token", "object", "stmt", "rc"],
+    DERIVES,
     &[
       "Assign    : Token name, Rc<Expr> value",
       "Binary    : Rc<Expr> left, Token operator, Rc<Expr> right",
       "Call      : Rc<Expr> callee, Token paren, Vec<Rc<Expr>> arguments",
       "Get       : Rc<Expr> object, Token name",
       "Grouping  : Rc<Expr> expression",
+      "Interpolation : Vec<Rc<Expr>> parts",
+      "Lambda    : Token keyword, Rc<FunctionStmt> function",
       "Literal   : Option<Object> value",
       "Logical   : Rc<Expr> left, Token operator, Rc<Expr> right",
-      "Set       : Rc<Expr> object, Token name, Rc<Expr> value",
+      "Postfix   : Token name, Token operator",
+      "Set       : Rc<Expr> object, Token name, Option<Token> operator, Rc<Expr> value",
+      "Super     : Token keyword, Token method",
+      "This      : Token keyword",
       "Unary     : Token operator, Rc<Expr> right",
       "Variable  : Token name",
     ],
+    false,
   )?;
 
   define_ast(
     output_dir,
     "Stmt",
     &["error", "token", "expr", "rc"],
+    DERIVES,
     &[
+      "Assert     : Token keyword, Rc<Expr> condition",
       "Block      : Rc<Vec<Rc<Stmt>>> statements",
-      "Class      : Token name, Rc<Vec<Rc<Stmt>>> methods",
-      "Break      : Token token",
+      "Class      : Token name, Option<Rc<Expr>> superclass, Rc<Vec<Rc<Stmt>>> methods, Rc<Vec<Rc<Stmt>>> statics",
+      "Break      : Token token, Option<Token> label",
+      "Continue   : Token token, Option<Token> label",
+      "Const      : Token name, Rc<Expr> initializer",
+      "Do         : Token keyword, Rc<Stmt> body, Rc<Expr> condition, Option<Token> label",
       "Expression : Rc<Expr> expression",
-      "Function   : Token name, Rc<Vec<Token>> params, Rc<Vec<Rc<Stmt>>> body",
-      "If         : Rc<Expr> condition, Rc<Stmt> then_branch, Option<Rc<Stmt>> else_branch",
+      "ForEach    : Token name, Rc<Expr> iterable, Rc<Stmt> body, Option<Token> label",
+      "Function   : Token name, Rc<Vec<Token>> params, Rc<Vec<Option<Rc<Expr>>>> defaults, Option<Token> rest, Rc<Vec<Rc<Stmt>>> body, bool getter",
+      "Global     : Token keyword, Token name",
+      "If         : Token keyword, Rc<Expr> condition, Rc<Stmt> then_branch, Option<Rc<Stmt>> else_branch",
+      "Import     : Token keyword, Token path, Option<Token> alias",
       "Print      : Rc<Expr> expression",
+      "Repeat     : Token keyword, Rc<Expr> count, Rc<Stmt> body, Option<Token> label",
       "Return     : Token keyword, Option<Rc<Expr>> value",
+      "Throw      : Token keyword, Rc<Expr> value",
+      "Try        : Rc<Vec<Rc<Stmt>>> body, Token name, Rc<Vec<Rc<Stmt>>> handler",
       "Def        : Token name, Option<Rc<Expr>> initializer",
-      "While      : Rc<Expr> condition, Rc<Stmt> body",
+      "Undef      : Token keyword, Token name",
+      "While      : Token keyword, Rc<Expr> condition, Rc<Stmt> body, Option<Rc<Expr>> increment, Option<Token> label",
     ],
+    false,
   )?;
   Ok(())
 }
 
+impl TreeType {
+  /// 拆分模式下节点所在的模块名，如 `binary_expr`，避免与关键字冲突
+  fn module_name(&self, base_name: &str) -> String {
+    format!(
+      "{}_{}",
+      self.base_class_name.to_lowercase(),
+      base_name.to_lowercase()
+    )
+  }
+}
+
+fn define_type(file: &mut impl Write, derives: &[&str], t: &TreeType) -> io::Result<()> {
+  if !derives.is_empty() {
+    writeln!(file, "#[derive({})]", derives.join(", "))?;
+  }
+  writeln!(file, "pub struct {} {{", t.class_name)?;
+  for f in &t.fields {
+    writeln!(file, "  pub {},", f)?;
+  }
+  writeln!(file, "}}\n")
+}
+
 fn define_ast(
   output_dir: &str,
   base_name: &str,
   imports: &[&str],
+  derives: &[&str],
   types: &[&str],
+  split: bool,
 ) -> io::Result<()> {
-  let path = format!("{output_dir}/{}.rs", base_name.to_lowercase());
+  // 拆分模式下写入同名目录，每个节点一个文件，由 mod.rs 重新导出
+  let dir = format!("{output_dir}/{}", base_name.to_lowercase());
+  let path = if split {
+    fs::create_dir_all(&dir)?;
+    format!("{dir}/mod.rs")
+  } else {
+    format!("{dir}.rs")
+  };
   let mut file = File::create(path)?;
   let mut tree_types = Vec::new();
 
@@ -84,6 +141,24 @@ fn define_ast(
     });
   }
 
+  let serde: Vec<&str> = derives
+    .iter()
+    .copied()
+    .filter(|derive| matches!(*derive, "Deserialize" | "Serialize"))
+    .collect();
+  if !serde.is_empty() {
+    writeln!(file, "use serde::{{{}}};", serde.join(", "))?;
+  }
+
+  // 基础枚举按指针实现 PartialEq 和 Hash，作为 locals 表的键，不能派生
+  let base_derives: Vec<&str> = derives
+    .iter()
+    .copied()
+    .filter(|derive| *derive != "PartialEq")
+    .collect();
+  if !base_derives.is_empty() {
+    writeln!(file, "#[derive({})]", base_derives.join(", "))?;
+  }
   writeln!(file, "pub enum {base_name} {{")?;
   for t in &tree_types {
     writeln!(file, "  {}(Rc<{}>),", t.base_class_name, t.class_name)?;
@@ -138,11 +213,16 @@ fn define_ast(
   writeln!(file, "}}\n")?;
 
   for t in &tree_types {
-    writeln!(file, "pub struct {} {{", t.class_name)?;
-    for f in &t.fields {
-      writeln!(file, "  pub {},", f)?;
+    if split {
+      let module = t.module_name(base_name);
+      writeln!(file, "mod {module};\npub use {module}::*;\n")?;
+
+      let mut node = File::create(format!("{dir}/{module}.rs"))?;
+      writeln!(node, "use super::*;\n")?;
+      define_type(&mut node, derives, t)?;
+    } else {
+      define_type(&mut file, derives, t)?;
     }
-    writeln!(file, "}}\n")?;
   }
 
   writeln!(file, "pub trait {}Visitor<T> {{", base_name)?;
@@ -177,3 +257,135 @@ fn define_ast(
   */
   Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn assert_derives<T: fmt::Debug + Clone + PartialEq>() {}
+
+  #[test]
+  fn generated_nodes_carry_derives() {
+    let dir = std::env::temp_dir().join("saturday_generate_ast_derives");
+    std::fs::create_dir_all(&dir).unwrap();
+    generate_ast(dir.to_str().unwrap()).unwrap();
+
+    let expr = std::fs::read_to_string(dir.join("expr.rs")).unwrap();
+    assert!(expr.contains("#[derive(Debug, Clone, Serialize, Deserialize)]\npub enum Expr {"));
+    assert!(expr.contains(
+      "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\npub struct BinaryExpr {"
+    ));
+
+    let stmt = std::fs::read_to_string(dir.join("stmt.rs")).unwrap();
+    assert!(stmt.contains("#[derive(Debug, Clone, Serialize, Deserialize)]\npub enum Stmt {"));
+    assert!(stmt.contains(
+      "#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]\npub struct FunctionStmt {"
+    ));
+
+    // 生成到 src 下的同一份代码随本 crate 编译，派生必须成立
+    assert_derives::<crate::expr::BinaryExpr>();
+    assert_derives::<crate::stmt::FunctionStmt>();
+  }
+
+  #[test]
+  fn generated_variants_match_the_crate() {
+    let dir = std::env::temp_dir().join("saturday_generate_ast_variants");
+    std::fs::create_dir_all(&dir).unwrap();
+    generate_ast(dir.to_str().unwrap()).unwrap();
+
+    let expr = std::fs::read_to_string(dir.join("expr.rs")).unwrap();
+    for variant in [
+      "Assign",
+      "Binary",
+      "Call",
+      "Get",
+      "Grouping",
+      "Interpolation",
+      "Lambda",
+      "Literal",
+      "Logical",
+      "Postfix",
+      "Set",
+      "Super",
+      "This",
+      "Unary",
+      "Variable",
+    ] {
+      assert!(
+        expr.contains(&format!("  {variant}(Rc<{variant}Expr>),")),
+        "missing Expr::{variant}"
+      );
+    }
+
+    let stmt = std::fs::read_to_string(dir.join("stmt.rs")).unwrap();
+    for variant in [
+      "Assert",
+      "Block",
+      "Break",
+      "Class",
+      "Const",
+      "Continue",
+      "Def",
+      "Do",
+      "Expression",
+      "ForEach",
+      "Function",
+      "Global",
+      "If",
+      "Import",
+      "Print",
+      "Repeat",
+      "Return",
+      "Throw",
+      "Try",
+      "Undef",
+      "While",
+    ] {
+      assert!(
+        stmt.contains(&format!("  {variant}(Rc<{variant}Stmt>),")),
+        "missing Stmt::{variant}"
+      );
+    }
+    assert!(stmt.contains("  pub body: Rc<Vec<Rc<Stmt>>>,"));
+  }
+
+  #[test]
+  fn split_mode_writes_one_file_per_node() {
+    let dir = std::env::temp_dir().join("saturday_generate_ast_split");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    define_ast(
+      dir.to_str().unwrap(),
+      "Expr",
+      &["token", "object", "rc"],
+      DERIVES,
+      &[
+        "Literal   : Option<Object> value",
+        "Unary     : Token operator, Rc<Expr> right",
+      ],
+      true,
+    )
+    .unwrap();
+
+    let mut files: Vec<String> = std::fs::read_dir(dir.join("expr"))
+      .unwrap()
+      .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+      .collect();
+    files.sort();
+    assert_eq!(files, ["literal_expr.rs", "mod.rs", "unary_expr.rs"]);
+    assert!(!dir.join("expr.rs").exists());
+
+    let module = std::fs::read_to_string(dir.join("expr").join("mod.rs")).unwrap();
+    for node in ["literal_expr", "unary_expr"] {
+      assert!(module.contains(&format!("mod {node};\npub use {node}::*;")));
+    }
+    assert!(module.contains("pub enum Expr {"));
+    assert!(!module.contains("pub struct"));
+
+    let unary = std::fs::read_to_string(dir.join("expr").join("unary_expr.rs")).unwrap();
+    assert!(unary.starts_with("use super::*;"));
+    assert!(
+      unary.contains("pub struct UnaryExpr {\n  pub operator: Token,\n  pub right: Rc<Expr>,\n}")
+    );
+  }
+}
@@ -12,13 +12,13 @@ pub struct Callable {
 
 impl Debug for Callable {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    write!(f, "<Callable>")
+    write!(f, "{}", self.func.describe())
   }
 }
 
 impl Display for Callable {
   fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-    write!(f, "<Callable>")
+    write!(f, "{}", self.func.describe())
   }
 }
 
@@ -38,4 +38,22 @@ pub trait SaturdayCallable {
     arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult>;
   fn arity(&self) -> usize;
+
+  /// 声明时的名字，内置函数为注册的全局名
+  fn name(&self) -> String;
+
+  /// 打印时的形式，默认视为内置函数
+  fn describe(&self) -> String {
+    format!("<native fn {}>", self.name())
+  }
+
+  /// 最少需要的参数个数，有默认值的参数可以省略
+  fn min_arity(&self) -> usize {
+    self.arity()
+  }
+
+  /// 是否接收任意多个额外参数
+  fn is_variadic(&self) -> bool {
+    false
+  }
 }
@@ -1,14 +1,20 @@
 use crate::object::Object;
+use crate::symbol::Symbol;
 use crate::token::Token;
 use crate::SaturdayResult;
 use std::cell::RefCell;
 use std::collections::hash_map::Entry;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+use std::fmt::Formatter;
 use std::rc::Rc;
 
-#[derive(Debug)]
+/// 每个名字对应 `slots` 中的一个位置，重新定义时覆盖原来的位置，
+/// 所以缓存下来的位置在环境存在期间一直有效
 pub struct Environment {
-  values: HashMap<String, Object>,
+  values: HashMap<Symbol, usize>,
+  slots: Vec<Object>,
+  constants: HashSet<Symbol>,
   enclosing: Option<Rc<RefCell<Environment>>>,
 }
 
@@ -16,24 +22,103 @@ impl Environment {
   pub fn new() -> Self {
     Self {
       values: HashMap::new(),
+      slots: Vec::new(),
+      constants: HashSet::new(),
       enclosing: None,
     }
   }
 
   pub fn new_with_enclosing(enclosing: Rc<RefCell<Self>>) -> Self {
     Self {
-      values: HashMap::new(),
       enclosing: Some(enclosing),
+      ..Environment::new()
     }
   }
 
-  pub fn define(&mut self, name: &str, value: Object) {
-    self.values.insert(name.to_string(), value);
+  /// 作用域链最外层的环境，也就是代码所在文件的全局环境
+  pub fn globals_of(environment: &Rc<RefCell<Environment>>) -> Rc<RefCell<Environment>> {
+    let mut current = Rc::clone(environment);
+    loop {
+      let enclosing = current.borrow().enclosing.clone();
+      match enclosing {
+        Some(enclosing) => current = enclosing,
+        None => return current,
+      }
+    }
+  }
+
+  pub fn define(&mut self, name: impl Into<Symbol>, value: Object) {
+    let name = name.into();
+    self.constants.remove(&name);
+    self.insert(name, value);
+  }
+
+  /// 定义常量，之后对它的赋值会报错
+  pub fn define_const(&mut self, name: impl Into<Symbol>, value: Object) {
+    let name = name.into();
+    self.insert(name, value);
+    self.constants.insert(name);
+  }
+
+  fn insert(&mut self, name: Symbol, value: Object) {
+    match self.values.entry(name) {
+      Entry::Occupied(slot) => self.slots[*slot.get()] = value,
+      Entry::Vacant(entry) => {
+        entry.insert(self.slots.len());
+        self.slots.push(value);
+      }
+    }
   }
 
-  pub fn get_at(&self, distance: usize, name: &str) -> Result<Object, SaturdayResult> {
+  fn lookup(&self, name: Symbol) -> Option<&Object> {
+    self.values.get(&name).map(|&slot| &self.slots[slot])
+  }
+
+  /// 当前作用域中的所有绑定，按名称排序
+  pub fn bindings(&self) -> Vec<(String, Object)> {
+    let mut bindings: Vec<(String, Object)> = self
+      .values
+      .iter()
+      .map(|(name, &slot)| (name.to_string(), self.slots[slot].clone()))
+      .collect();
+    bindings.sort_by(|a, b| a.0.cmp(&b.0));
+    bindings
+  }
+
+  /// 当前作用域中定义的名字，按名称排序
+  pub fn names(&self) -> Vec<String> {
+    let mut names: Vec<String> = self.values.keys().map(|name| name.to_string()).collect();
+    names.sort();
+    names
+  }
+
+  /// 按名字查找，找不到时依次查找外层作用域
+  pub fn get_opt(&self, name: &str) -> Option<Object> {
+    match self.lookup(Symbol::intern(name)) {
+      Some(object) => Some(object.clone()),
+      None => self.enclosing.as_ref()?.borrow().get_opt(name),
+    }
+  }
+
+  /// 名字在当前作用域中的位置，不查找外层作用域
+  pub fn slot_of(&self, name: &Token) -> Option<usize> {
+    self.values.get(&name.symbol()).copied()
+  }
+
+  pub fn get_slot(&self, slot: usize) -> Object {
+    self.slots[slot].clone()
+  }
+
+  fn constant_error(name: &Token) -> SaturdayResult {
+    SaturdayResult::runtime_error(
+      name,
+      &format!("Can't assign to constant '{}'.", name.as_string()),
+    )
+  }
+
+  pub fn get_at(&self, distance: usize, name: impl Into<Symbol>) -> Result<Object, SaturdayResult> {
     if distance == 0 {
-      Ok(self.values.get(name).unwrap().clone())
+      Ok(self.lookup(name.into()).unwrap().clone())
     } else {
       self
         .enclosing
@@ -45,7 +130,7 @@ impl Environment {
   }
 
   pub fn get(&self, name: &Token) -> Result<Object, SaturdayResult> {
-    if let Some(object) = self.values.get(&name.as_string()) {
+    if let Some(object) = self.lookup(name.symbol()) {
       Ok(object.clone())
     } else if let Some(enclosing) = &self.enclosing {
       enclosing.borrow().get(name)
@@ -64,7 +149,11 @@ impl Environment {
     value: Object,
   ) -> Result<(), SaturdayResult> {
     if distance == 0 {
-      self.values.insert(name.as_string(), value);
+      if self.constants.contains(&name.symbol()) {
+        return Err(Environment::constant_error(name));
+      }
+
+      self.insert(name.symbol(), value);
       Ok(())
     } else {
       self
@@ -76,9 +165,27 @@ impl Environment {
     }
   }
 
+  /// 删除当前作用域中的绑定，原来的位置不再复用
+  pub fn remove(&mut self, name: &Token) -> Result<Object, SaturdayResult> {
+    match self.values.remove(&name.symbol()) {
+      Some(slot) => {
+        self.constants.remove(&name.symbol());
+        Ok(std::mem::replace(&mut self.slots[slot], Object::Nil))
+      }
+      None => Err(SaturdayResult::runtime_error(
+        name,
+        &format!("Undefined variable '{}'.", name.as_string()),
+      )),
+    }
+  }
+
   pub fn assign(&mut self, name: &Token, value: Object) -> Result<(), SaturdayResult> {
-    if let Entry::Occupied(mut object) = self.values.entry(name.as_string()) {
-      object.insert(value);
+    if let Some(&slot) = self.values.get(&name.symbol()) {
+      if self.constants.contains(&name.symbol()) {
+        return Err(Environment::constant_error(name));
+      }
+
+      self.slots[slot] = value;
       Ok(())
     } else if let Some(enclosing) = &self.enclosing {
       enclosing.borrow_mut().assign(name, value)
@@ -91,6 +198,25 @@ impl Environment {
   }
 }
 
+impl Default for Environment {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+/// 只输出当前作用域，外层作用域可能很深，也可能经由闭包引用回来
+impl fmt::Debug for Environment {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let mut constants: Vec<String> = self.constants.iter().map(|name| name.to_string()).collect();
+    constants.sort();
+    f.debug_struct("Environment")
+      .field("bindings", &self.bindings())
+      .field("constants", &constants)
+      .field("enclosed", &self.enclosing.is_some())
+      .finish()
+  }
+}
+
 #[cfg(test)]
 mod tests {
   use super::*;
@@ -100,8 +226,11 @@ mod tests {
   fn can_define_a_variable() {
     let mut e = Environment::new();
     e.define("One", Object::Bool(true));
-    assert!(e.values.contains_key("One"));
-    assert_eq!(*e.values.get("One").unwrap(), Object::Bool(true));
+    assert!(e.lookup(Symbol::intern("One")).is_some());
+    assert_eq!(
+      *e.lookup(Symbol::intern("One")).unwrap(),
+      Object::Bool(true)
+    );
   }
 
   #[test]
@@ -109,44 +238,45 @@ mod tests {
     let mut e = Environment::new();
     e.define("Two", Object::Bool(true));
     e.define("Two", Object::Num(12.0));
-    assert_eq!(*e.values.get("Two").unwrap(), Object::Num(12.0));
+    assert_eq!(*e.lookup(Symbol::intern("Two")).unwrap(), Object::Num(12.0));
   }
 
   #[test]
   fn can_look_up_a_variable() {
     let mut e = Environment::new();
-    e.define("Three", Object::Str("foo".to_string()));
+    e.define("Three", Object::Str("foo".into()));
     assert_eq!(
       e.get(&Token::new(
         TokenType::Identifier,
         "Three".to_string(),
         None,
         123,
+        0,
       ))
       .ok()
       .unwrap(),
-      Object::Str("foo".to_string())
+      Object::Str("foo".into())
     );
   }
 
   #[test]
   fn error_when_variable_undefined() {
     let e = Environment::new();
-    let three_tok = Token::new(TokenType::Identifier, "Three".to_string(), None, 0);
+    let three_tok = Token::new(TokenType::Identifier, "Three".to_string(), None, 0, 0);
     assert!(e.get(&three_tok).is_err());
   }
 
   #[test]
   fn error_when_assigning_to_undefined_variable() {
     let mut e = Environment::new();
-    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0);
+    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0, 0);
     assert!(e.assign(&four_tok, Object::Nil).is_err());
   }
 
   #[test]
   fn can_reassign_existing_variable() {
     let mut e = Environment::new();
-    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0);
+    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0, 0);
     e.define("Four", Object::Num(73.1));
     assert!(e.assign(&four_tok, Object::Num(89.5)).is_ok());
     assert_eq!(e.get(&four_tok).ok(), Some(Object::Num(89.5)));
@@ -162,7 +292,7 @@ mod tests {
   #[test]
   fn can_read_from_enclosed_environment() {
     let e = Rc::new(RefCell::new(Environment::new()));
-    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0);
+    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0, 0);
     e.borrow_mut().define("Four", Object::Num(73.1));
     let f = Environment::new_with_enclosing(Rc::clone(&e));
     assert_eq!(f.get(&four_tok).ok(), Some(Object::Num(73.1)));
@@ -173,8 +303,70 @@ mod tests {
     let e = Rc::new(RefCell::new(Environment::new()));
     e.borrow_mut().define("Four", Object::Num(73.1));
     let mut f = Environment::new_with_enclosing(Rc::clone(&e));
-    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0);
+    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0, 0);
     assert!(f.assign(&four_tok, Object::Num(91.2)).is_ok());
     assert_eq!(f.get(&four_tok).ok(), Some(Object::Num(91.2)));
   }
+
+  #[test]
+  fn error_when_assigning_to_constant() {
+    let e = Rc::new(RefCell::new(Environment::new()));
+    let five_tok = Token::new(TokenType::Identifier, "Five".to_string(), None, 0, 0);
+    e.borrow_mut().define_const("Five", Object::Num(5.0));
+    assert_eq!(e.borrow().get(&five_tok).ok(), Some(Object::Num(5.0)));
+    assert!(e.borrow_mut().assign(&five_tok, Object::Nil).is_err());
+    assert!(e.borrow_mut().assign_at(0, &five_tok, Object::Nil).is_err());
+
+    let mut f = Environment::new_with_enclosing(Rc::clone(&e));
+    assert!(f.assign(&five_tok, Object::Nil).is_err());
+  }
+
+  #[test]
+  fn can_redefine_a_constant_as_variable() {
+    let mut e = Environment::new();
+    let five_tok = Token::new(TokenType::Identifier, "Five".to_string(), None, 0, 0);
+    e.define_const("Five", Object::Num(5.0));
+    e.define("Five", Object::Num(6.0));
+    assert!(e.assign(&five_tok, Object::Nil).is_ok());
+  }
+
+  #[test]
+  fn names_and_get_opt() {
+    let e = Rc::new(RefCell::new(Environment::new()));
+    e.borrow_mut().define("outer", Object::Num(1.0));
+    let mut f = Environment::new_with_enclosing(Rc::clone(&e));
+    f.define("b", Object::Nil);
+    f.define_const("a", Object::Bool(true));
+    assert_eq!(f.names(), vec!["a", "b"]);
+    assert_eq!(f.get_opt("a"), Some(Object::Bool(true)));
+    assert_eq!(f.get_opt("outer"), Some(Object::Num(1.0)));
+    assert_eq!(f.get_opt("missing"), None);
+  }
+
+  #[test]
+  fn debug_prints_only_the_local_frame() {
+    let mut e = Rc::new(RefCell::new(Environment::new()));
+    for depth in 0..1000 {
+      let mut inner = Environment::new_with_enclosing(Rc::clone(&e));
+      inner.define("depth", Object::Num(depth as f64));
+      e = Rc::new(RefCell::new(inner));
+    }
+    assert_eq!(
+      format!("{:?}", e.borrow()),
+      "Environment { bindings: [(\"depth\", Num(999.0))], constants: [], enclosed: true }"
+    );
+  }
+
+  #[test]
+  fn remove_a_binding() {
+    let mut e = Environment::new();
+    let four_tok = Token::new(TokenType::Identifier, "Four".to_string(), None, 0, 0);
+    e.define_const("Four", Object::Num(4.0));
+    assert_eq!(e.remove(&four_tok).ok(), Some(Object::Num(4.0)));
+    assert!(e.get(&four_tok).is_err());
+    assert!(e.remove(&four_tok).is_err());
+
+    e.define("Four", Object::Nil);
+    assert!(e.assign(&four_tok, Object::Num(4.0)).is_ok());
+  }
 }
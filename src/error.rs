@@ -1,14 +1,110 @@
 use crate::object::Object;
 use crate::token::Token;
 use crate::token_type::TokenType;
+use serde::Serialize;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::fmt::Formatter;
 
+/// 错误输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorFormat {
+  /// 直接打印到 stderr 的可读格式
+  Human,
+  /// 先收集，运行结束后以 JSON 数组输出
+  Json,
+  /// 以可读格式收集，由调用方取出，不写 stderr
+  Captured,
+}
+
+thread_local! {
+  static SOURCE: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+  static FORMAT: Cell<ErrorFormat> = const { Cell::new(ErrorFormat::Human) };
+  static COLLECTED: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+}
+
+pub fn set_error_format(format: ErrorFormat) {
+  FORMAT.with(|current| current.set(format));
+}
+
+pub fn error_format() -> ErrorFormat {
+  FORMAT.with(|current| current.get())
+}
+
+/// # 取出收集的错误
+/// JSON 模式下收集到的所有错误组成的 JSON 数组，取出后清空
+pub fn take_json_errors() -> String {
+  let errors = COLLECTED.with(|collected| collected.take());
+  format!("[{}]", errors.join(","))
+}
+
+/// Captured 模式下收集到的错误文本，取出后清空
+pub fn take_captured_errors() -> String {
+  COLLECTED.with(|collected| collected.take()).concat()
+}
+
+/// JSON 模式下一条错误的字段，按声明顺序输出
+#[derive(Serialize)]
+struct JsonError<'a> {
+  kind: &'a str,
+  line: usize,
+  column: Option<usize>,
+  lexeme: Option<&'a str>,
+  message: &'a str,
+}
+
+/// # 设置源码
+/// 错误报告会据此打印出错的那一行源码
+pub fn set_source(source: &str) {
+  SOURCE.with(|lines| *lines.borrow_mut() = source.lines().map(String::from).collect());
+}
+
+/// 当前用于显示源码片段的源码，导入其他文件后用来恢复
+pub fn current_source() -> String {
+  SOURCE.with(|lines| lines.borrow().join("\n"))
+}
+
+#[derive(Debug)]
 pub enum SaturdayResult {
-  ParseError { token: Token, message: String },
-  RuntimeError { token: Token, message: String },
-  Error { line: usize, message: String },
-  SystemError { message: String },
-  ReturnValue { value: Object },
-  Break,
+  ParseError {
+    token: Token,
+    message: String,
+  },
+  RuntimeError {
+    token: Token,
+    message: String,
+  },
+  Warning {
+    token: Token,
+    message: String,
+  },
+  Error {
+    line: usize,
+    message: String,
+  },
+  SystemError {
+    message: String,
+  },
+  ReturnValue {
+    value: Object,
+  },
+  /// `throw` 抛出的值，直到被 `try/catch` 捕获
+  Thrown {
+    token: Token,
+    value: Object,
+  },
+  /// 内置函数的错误，在调用处转换为带位置的运行时错误
+  NativeError {
+    message: String,
+  },
+  /// 结束循环，带标签时结束同名的外层循环
+  Break {
+    label: Option<String>,
+  },
+  /// 进入下一次迭代，带标签时作用于同名的外层循环
+  Continue {
+    label: Option<String>,
+  },
 }
 
 impl SaturdayResult {
@@ -21,7 +117,7 @@ impl SaturdayResult {
       line,
       message: message.to_string(),
     };
-    err.report("");
+    err.report();
     err
   }
 
@@ -30,7 +126,7 @@ impl SaturdayResult {
       token: token.dup(),
       message: message.to_string(),
     };
-    err.report("");
+    err.report();
     err
   }
 
@@ -39,34 +135,323 @@ impl SaturdayResult {
       token: token.dup(),
       message: message.to_string(),
     };
-    err.report("");
+    err.report();
     err
   }
 
+  /// 非致命的警告，只做报告不中断流程
+  pub fn warning(token: &Token, message: &str) -> Self {
+    let warn = Self::Warning {
+      token: token.dup(),
+      message: message.to_string(),
+    };
+    warn.report();
+    warn
+  }
+
+  /// 内置函数中的错误，此时还不知道调用位置，所以不立即报告
+  pub fn native_error(message: &str) -> Self {
+    Self::NativeError {
+      message: message.to_string(),
+    }
+  }
+
   pub fn system_error(message: &str) -> Self {
     let err = SaturdayResult::SystemError {
       message: message.to_string(),
     };
-    err.report("");
+    err.report();
     err
   }
 
-  fn report(&self, loc: &str) {
+  fn report(&self) {
+    if matches!(
+      self,
+      Self::Break { .. }
+        | Self::Continue { .. }
+        | Self::ReturnValue { .. }
+        | Self::Thrown { .. }
+        | Self::NativeError { .. }
+    ) {
+      return;
+    }
+
+    match error_format() {
+      ErrorFormat::Human => {
+        eprintln!("{}", self);
+        if let Some(snippet) = self.snippet() {
+          eprint!("{}", snippet);
+        }
+      }
+      ErrorFormat::Json => {
+        if let Some(json) = self.to_json() {
+          COLLECTED.with(|collected| collected.borrow_mut().push(json));
+        }
+      }
+      ErrorFormat::Captured => {
+        let text = format!("{}\n{}", self, self.snippet().unwrap_or_default());
+        COLLECTED.with(|collected| collected.borrow_mut().push(text));
+      }
+    }
+  }
+
+  /// 以 JSON 对象表示错误，控制流不算错误，返回 `None`
+  pub fn to_json(&self) -> Option<String> {
+    let (kind, token, line, message) = match self {
+      Self::ParseError { token, message } => ("parse", Some(token), token.line, message),
+      Self::RuntimeError { token, message } => ("runtime", Some(token), token.line, message),
+      Self::Warning { token, message } => ("warning", Some(token), token.line, message),
+      Self::Error { line, message } => ("error", None, *line, message),
+      Self::SystemError { message } => ("system", None, 0, message),
+      Self::Break { .. }
+      | Self::Continue { .. }
+      | Self::ReturnValue { .. }
+      | Self::Thrown { .. }
+      | Self::NativeError { .. } => return None,
+    };
+
+    let error = JsonError {
+      kind,
+      line,
+      column: token.map(|token| token.column).filter(|&column| column > 0),
+      lexeme: token.map(|token| &*token.lexeme),
+      message,
+    };
+    serde_json::to_string(&error).ok()
+  }
+
+  /// # 源码片段
+  /// 出错的那一行以及标记出错 token 的 `^`，
+  /// 没有源码或列号未知时返回 `None`
+  pub fn snippet(&self) -> Option<String> {
+    let token = match self {
+      Self::ParseError { token, .. }
+      | Self::RuntimeError { token, .. }
+      | Self::Warning { token, .. } => token,
+      _ => return None,
+    };
+
+    if token.column == 0 || token.line == 0 {
+      return None;
+    }
+
+    let text = SOURCE.with(|lines| lines.borrow().get(token.line - 1).cloned())?;
+    let width = token.line.to_string().len() + 2;
+    Some(format!(
+      "{:>width$} | {}\n{:>width$} | {}{}\n",
+      token.line,
+      text,
+      "",
+      " ".repeat(token.column - 1),
+      "^".repeat(token.len.max(1)),
+    ))
+  }
+}
+
+impl fmt::Display for SaturdayResult {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     match self {
+      // 词素不能说明 token 种类时（标识符、数字、字符串）一并给出种类
+      Self::ParseError { token, message }
+        if !token.is(TokenType::Eof) && token.t_type.to_string() != *token.lexeme =>
+      {
+        write!(
+          f,
+          "{} at {} '{}' {}",
+          Location(token),
+          token.t_type,
+          token.as_string(),
+          message
+        )
+      }
       Self::ParseError { token, message } | Self::RuntimeError { token, message } => {
         if token.is(TokenType::Eof) {
-          eprintln!("{} at end {}", token.line, message);
+          write!(f, "line {} at end {}", token.line, message)
         } else {
-          eprintln!("line {} at '{}' {}", token.line, token.as_string(), message);
+          write!(
+            f,
+            "{} at '{}' {}",
+            Location(token),
+            token.as_string(),
+            message
+          )
         }
       }
-      Self::SystemError { message } => {
-        eprintln!("System Error: {message}");
-      }
-      Self::Error { line, message } => {
-        eprintln!("[line {}] Error{}: {}", line, loc, message);
+      Self::Warning { token, message } => write!(
+        f,
+        "{} at '{}' Warning: {}",
+        Location(token),
+        token.as_string(),
+        message
+      ),
+      Self::SystemError { message } => write!(f, "System Error: {message}"),
+      Self::NativeError { message } => write!(f, "{message}"),
+      Self::Error { line, message } => write!(f, "[line {}] Error: {}", line, message),
+      Self::ReturnValue { value } => write!(f, "Unexpected return of '{}'.", value),
+      Self::Thrown { token, value } => {
+        write!(f, "{} Uncaught exception '{}'.", Location(token), value)
       }
-      Self::Break | Self::ReturnValue { .. } => {}
+      Self::Break { .. } => write!(f, "Unexpected 'break' outside of a loop."),
+      Self::Continue { .. } => write!(f, "Unexpected 'continue' outside of a loop."),
+    }
+  }
+}
+
+impl std::error::Error for SaturdayResult {}
+
+/// `line 3:9`，列号未知时只显示行号
+struct Location<'a>(&'a Token);
+
+impl fmt::Display for Location<'_> {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    if self.0.column == 0 {
+      write!(f, "line {}", self.0.line)
+    } else {
+      write!(f, "line {}:{}", self.0.line, self.0.column)
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn token(t_type: TokenType, lexeme: &str, line: usize) -> Token {
+    Token::new(t_type, lexeme.to_string(), None, line, 0)
+  }
+
+  #[test]
+  fn parse_errors_name_the_token_type() {
+    let parse = |t_type, lexeme| SaturdayResult::ParseError {
+      token: token(t_type, lexeme, 2),
+      message: "Expect ';' after value.".to_string(),
     };
+    assert_eq!(
+      parse(TokenType::Identifier, "x").to_string(),
+      "line 2 at identifier 'x' Expect ';' after value."
+    );
+    assert_eq!(
+      parse(TokenType::Number, "12").to_string(),
+      "line 2 at number '12' Expect ';' after value."
+    );
+    assert_eq!(
+      parse(TokenType::RightBrace, "}").to_string(),
+      "line 2 at '}' Expect ';' after value."
+    );
+    assert_eq!(
+      parse(TokenType::Print, "print").to_string(),
+      "line 2 at 'print' Expect ';' after value."
+    );
+  }
+
+  #[test]
+  fn display_token_errors() {
+    let plus = token(TokenType::Plus, "+", 3);
+    let parse = SaturdayResult::ParseError {
+      token: plus.dup(),
+      message: "Expect expression.".to_string(),
+    };
+    assert_eq!(parse.to_string(), "line 3 at '+' Expect expression.");
+
+    let runtime = SaturdayResult::RuntimeError {
+      token: plus.dup(),
+      message: "Operands must be numbers.".to_string(),
+    };
+    assert_eq!(
+      runtime.to_string(),
+      "line 3 at '+' Operands must be numbers."
+    );
+
+    let at_end = SaturdayResult::ParseError {
+      token: Token::eof(4),
+      message: "Expect ';'.".to_string(),
+    };
+    assert_eq!(at_end.to_string(), "line 4 at end Expect ';'.");
+
+    let warning = SaturdayResult::Warning {
+      token: token(TokenType::Identifier, "unused", 5),
+      message: "Local variable is never used.".to_string(),
+    };
+    assert_eq!(
+      warning.to_string(),
+      "line 5 at 'unused' Warning: Local variable is never used."
+    );
+  }
+
+  #[test]
+  fn display_other_variants() {
+    let error = SaturdayResult::Error {
+      line: 7,
+      message: "Unexpected character.".to_string(),
+    };
+    assert_eq!(error.to_string(), "[line 7] Error: Unexpected character.");
+
+    let value = SaturdayResult::ReturnValue {
+      value: Object::Num(1.0),
+    };
+    assert_eq!(value.to_string(), "Unexpected return of '1'.");
+    assert_eq!(
+      SaturdayResult::Break { label: None }.to_string(),
+      "Unexpected 'break' outside of a loop."
+    );
+  }
+
+  #[test]
+  fn flows_through_question_mark() {
+    fn fails() -> Result<(), Box<dyn std::error::Error>> {
+      Err(SaturdayResult::Break { label: None })?;
+      Ok(())
+    }
+
+    assert!(fails().is_err());
+  }
+
+  #[test]
+  fn system_error_has_no_token() {
+    let err = SaturdayResult::system_error("clock may have gone backwards");
+    assert!(matches!(err, SaturdayResult::SystemError { .. }));
+    assert_eq!(
+      err.to_string(),
+      "System Error: clock may have gone backwards"
+    );
+  }
+
+  #[test]
+  fn snippet_marks_the_offending_token() {
+    set_source("def a = 1;\n\ndef x = ;");
+    let semicolon = Token::new(TokenType::SemiColon, ";".to_string(), None, 3, 9);
+    let err = SaturdayResult::ParseError {
+      token: semicolon,
+      message: "Expect expression.".to_string(),
+    };
+    assert_eq!(err.to_string(), "line 3:9 at ';' Expect expression.");
+    assert_eq!(
+      err.snippet(),
+      Some("  3 | def x = ;\n    |         ^\n".to_string())
+    );
+
+    let unknown = SaturdayResult::ParseError {
+      token: token(TokenType::SemiColon, ";", 3),
+      message: "Expect expression.".to_string(),
+    };
+    assert_eq!(unknown.snippet(), None);
+  }
+
+  #[test]
+  fn json_errors_are_collected() {
+    set_error_format(ErrorFormat::Json);
+    let quote = Token::new(TokenType::Identifier, "say\"".to_string(), None, 2, 4);
+    SaturdayResult::runtime_error(&quote, "Undefined variable.");
+    SaturdayResult::error(3, "Unexpected character");
+    set_error_format(ErrorFormat::Human);
+
+    assert_eq!(
+      take_json_errors(),
+      concat!(
+        r#"[{"kind":"runtime","line":2,"column":4,"lexeme":"say\"","message":"Undefined variable."},"#,
+        r#"{"kind":"error","line":3,"column":null,"lexeme":null,"message":"Unexpected character"}]"#
+      )
+    );
+    assert_eq!(take_json_errors(), "[]");
   }
 }
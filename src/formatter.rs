@@ -0,0 +1,655 @@
+use crate::error::SaturdayResult;
+use crate::expr::{
+  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr, InterpolationExpr,
+  LambdaExpr, LiteralExpr, LogicalExpr, PostfixExpr, SetExpr, SuperExpr, ThisExpr, UnaryExpr,
+  VariableExpr,
+};
+use crate::object::Object;
+use crate::parser::Parser;
+use crate::scanner::Scanner;
+use crate::stmt::{
+  AssertStmt, BlockStmt, BreakStmt, ClassStmt, ConstStmt, ContinueStmt, DefStmt, DoStmt,
+  ExpressionStmt, ForEachStmt, FunctionStmt, GlobalStmt, IfStmt, ImportStmt, PrintStmt, RepeatStmt,
+  ReturnStmt, Stmt, StmtVisitor, ThrowStmt, TryStmt, UndefStmt, WhileStmt,
+};
+use crate::token::Token;
+use crate::token_type::TokenType;
+use std::cell::Cell;
+use std::ops::Deref;
+use std::rc::Rc;
+
+/// 赋值的优先级最低，调用和字面量最高
+const ASSIGNMENT: u8 = 1;
+const UNARY: u8 = 13;
+const CALL: u8 = 15;
+
+/// 解析并格式化源码，有词法或语法错误时返回 `None`
+pub fn format_source(source: &str) -> Option<String> {
+  let mut scanner = Scanner::new(source.to_string());
+  let tokens = scanner.scan_tokens().ok()?;
+  let mut parser = Parser::new(tokens);
+  let statements = parser.parse().ok()?;
+  if !parser.success() {
+    return None;
+  }
+
+  SourceFormatter::new().format(&statements).ok()
+}
+
+/// # 源码格式化
+/// 把 AST 重新输出为统一风格的源码：两个空格缩进，运算符两边各一个空格，
+/// `{` 与语句头在同一行。
+/// 扫描时注释已经丢弃，所以格式化后的源码不含注释。
+/// 解析时展开的 `for` 循环会被还原，复合赋值输出为普通赋值。
+/// 只根据 AST 输出，格式化结果再次格式化不会改变
+pub struct SourceFormatter {
+  depth: Cell<usize>,
+}
+
+impl Default for SourceFormatter {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl SourceFormatter {
+  pub fn new() -> Self {
+    Self {
+      depth: Cell::new(0),
+    }
+  }
+
+  /// 每条顶层语句一行，以换行结尾
+  pub fn format(&self, statements: &[Rc<Stmt>]) -> Result<String, SaturdayResult> {
+    let mut source = String::new();
+    for statement in statements {
+      source.push_str(&self.stmt(statement)?);
+      source.push('\n');
+    }
+    Ok(source)
+  }
+
+  fn stmt(&self, stmt: &Rc<Stmt>) -> Result<String, SaturdayResult> {
+    stmt.accept(Rc::clone(stmt), self)
+  }
+
+  fn expr(&self, expr: &Rc<Expr>) -> Result<String, SaturdayResult> {
+    expr.accept(Rc::clone(expr), self)
+  }
+
+  /// 优先级低于 `min` 的子表达式加上括号，解析时合成的节点没有 `Grouping`
+  fn operand(&self, expr: &Rc<Expr>, min: u8) -> Result<String, SaturdayResult> {
+    let text = self.expr(expr)?;
+    if SourceFormatter::precedence(expr) < min {
+      Ok(format!("({text})"))
+    } else {
+      Ok(text)
+    }
+  }
+
+  fn precedence(expr: &Expr) -> u8 {
+    match expr {
+      Expr::Assign(_) | Expr::Set(_) => ASSIGNMENT,
+      Expr::Logical(logical) => SourceFormatter::logical_precedence(&logical.operator),
+      Expr::Binary(binary) => SourceFormatter::binary_precedence(&binary.operator),
+      Expr::Unary(_) => UNARY,
+      Expr::Literal(literal) if matches!(literal.value, Some(Object::Num(n)) if n < 0.0) => UNARY,
+      _ => CALL,
+    }
+  }
+
+  fn logical_precedence(operator: &Token) -> u8 {
+    match operator.token_type() {
+      TokenType::QuestionQuestion => 2,
+      TokenType::Or => 3,
+      _ => 4,
+    }
+  }
+
+  fn binary_precedence(operator: &Token) -> u8 {
+    match operator.token_type() {
+      TokenType::Pipe => 5,
+      TokenType::Caret => 6,
+      TokenType::Ampersand => 7,
+      TokenType::Equal | TokenType::BangEqual => 8,
+      TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual => 9,
+      TokenType::LessLess | TokenType::GreaterGreater => 10,
+      TokenType::Plus | TokenType::Minus => 11,
+      TokenType::StarStar => 14,
+      _ => 12,
+    }
+  }
+
+  fn indent(depth: usize) -> String {
+    "  ".repeat(depth)
+  }
+
+  /// 语句块，内部每条语句多缩进一层，空块输出为 `{}`
+  fn block(&self, statements: &[Rc<Stmt>]) -> Result<String, SaturdayResult> {
+    if statements.is_empty() {
+      return Ok("{}".to_string());
+    }
+
+    let depth = self.depth.get();
+    self.depth.set(depth + 1);
+    let mut text = String::from("{\n");
+    for statement in statements {
+      let line = self.stmt(statement);
+      let line = match line {
+        Ok(line) => line,
+        Err(e) => {
+          self.depth.set(depth);
+          return Err(e);
+        }
+      };
+      text.push_str(&SourceFormatter::indent(depth + 1));
+      text.push_str(&line);
+      text.push('\n');
+    }
+    self.depth.set(depth);
+    text.push_str(&SourceFormatter::indent(depth));
+    text.push('}');
+    Ok(text)
+  }
+
+  /// 循环和分支的主体总是语句块
+  fn body(&self, stmt: &Rc<Stmt>) -> Result<String, SaturdayResult> {
+    match stmt.deref() {
+      Stmt::Block(block) => self.block(&block.statements),
+      _ => self.block(std::slice::from_ref(stmt)),
+    }
+  }
+
+  fn label(label: &Option<Token>) -> String {
+    match label {
+      Some(label) => format!("{}: ", label.lexeme),
+      None => String::new(),
+    }
+  }
+
+  /// 参数列表和函数体，具名函数、方法与匿名函数共用
+  fn function(&self, function: &FunctionStmt) -> Result<String, SaturdayResult> {
+    let mut params = Vec::new();
+    for (param, default) in function.params.iter().zip(function.defaults.iter()) {
+      match default {
+        Some(default) => params.push(format!("{} = {}", param.lexeme, self.expr(default)?)),
+        None => params.push(param.lexeme.to_string()),
+      }
+    }
+    if let Some(rest) = &function.rest {
+      params.push(format!("...{}", rest.lexeme));
+    }
+
+    Ok(format!(
+      "({}) {}",
+      params.join(", "),
+      self.block(&function.body)?
+    ))
+  }
+
+  fn method(&self, method: &Rc<Stmt>, prefix: &str) -> Result<String, SaturdayResult> {
+    match method.deref() {
+      Stmt::Function(function) if function.getter => Ok(format!(
+        "{prefix}get {} {}",
+        function.name.lexeme,
+        self.block(&function.body)?
+      )),
+      Stmt::Function(function) => Ok(format!(
+        "{prefix}{}{}",
+        function.name.lexeme,
+        self.function(function)?
+      )),
+      _ => self.stmt(method),
+    }
+  }
+
+  /// 字符串字面量的内容，需要转义的字符写成转义序列
+  fn escape(text: &str) -> String {
+    let mut escaped = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(ch) = chars.next() {
+      match ch {
+        '"' => escaped.push_str("\\\""),
+        '\\' => escaped.push_str("\\\\"),
+        '\n' => escaped.push_str("\\n"),
+        '\t' => escaped.push_str("\\t"),
+        '\r' => escaped.push_str("\\r"),
+        '\0' => escaped.push_str("\\0"),
+        '$' if chars.peek() == Some(&'{') => escaped.push_str("\\$"),
+        ch => escaped.push(ch),
+      }
+    }
+    escaped
+  }
+
+  fn literal(value: &Option<Object>) -> String {
+    match value {
+      Some(Object::Str(s)) => format!("\"{}\"", SourceFormatter::escape(s)),
+      Some(value) => value.to_string(),
+      None => "nil".to_string(),
+    }
+  }
+}
+
+impl StmtVisitor<String> for SourceFormatter {
+  fn visit_assert_stmt(&self, _: Rc<Stmt>, stmt: &AssertStmt) -> Result<String, SaturdayResult> {
+    Ok(format!("assert {};", self.expr(&stmt.condition)?))
+  }
+
+  /// `for` 带初始化语句时被展开为语句块，这里还原
+  fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<String, SaturdayResult> {
+    if let [init, while_stmt] = &stmt.statements[..] {
+      if let (Stmt::Def(_) | Stmt::Expression(_), Stmt::While(while_stmt)) =
+        (init.deref(), while_stmt.deref())
+      {
+        if &*while_stmt.keyword.lexeme == "for" {
+          let init = self.stmt(init)?;
+          return Ok(format!(
+            "{}for {init} {}",
+            SourceFormatter::label(&while_stmt.label),
+            self.for_header(while_stmt)?
+          ));
+        }
+      }
+    }
+
+    self.block(&stmt.statements)
+  }
+
+  fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<String, SaturdayResult> {
+    match &stmt.label {
+      Some(label) => Ok(format!("break {};", label.lexeme)),
+      None => Ok("break;".to_string()),
+    }
+  }
+
+  fn visit_class_stmt(&self, _: Rc<Stmt>, stmt: &ClassStmt) -> Result<String, SaturdayResult> {
+    let mut header = format!("class {}", stmt.name.lexeme);
+    if let Some(superclass) = &stmt.superclass {
+      header.push_str(&format!(" < {}", self.expr(superclass)?));
+    }
+    if stmt.methods.is_empty() && stmt.statics.is_empty() {
+      return Ok(format!("{header} {{}}"));
+    }
+
+    let depth = self.depth.get();
+    self.depth.set(depth + 1);
+    let methods: Result<Vec<String>, SaturdayResult> = stmt
+      .methods
+      .iter()
+      .map(|method| self.method(method, ""))
+      .chain(
+        stmt
+          .statics
+          .iter()
+          .map(|method| self.method(method, "static ")),
+      )
+      .collect();
+    self.depth.set(depth);
+
+    let mut text = format!("{header} {{\n");
+    for method in methods? {
+      text.push_str(&SourceFormatter::indent(depth + 1));
+      text.push_str(&method);
+      text.push('\n');
+    }
+    text.push_str(&SourceFormatter::indent(depth));
+    text.push('}');
+    Ok(text)
+  }
+
+  fn visit_const_stmt(&self, _: Rc<Stmt>, stmt: &ConstStmt) -> Result<String, SaturdayResult> {
+    Ok(format!(
+      "const {} = {};",
+      stmt.name.lexeme,
+      self.expr(&stmt.initializer)?
+    ))
+  }
+
+  fn visit_continue_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &ContinueStmt,
+  ) -> Result<String, SaturdayResult> {
+    match &stmt.label {
+      Some(label) => Ok(format!("continue {};", label.lexeme)),
+      None => Ok("continue;".to_string()),
+    }
+  }
+
+  fn visit_def_stmt(&self, _: Rc<Stmt>, stmt: &DefStmt) -> Result<String, SaturdayResult> {
+    match &stmt.initializer {
+      Some(initializer) => Ok(format!(
+        "def {} = {};",
+        stmt.name.lexeme,
+        self.expr(initializer)?
+      )),
+      None => Ok(format!("def {};", stmt.name.lexeme)),
+    }
+  }
+
+  fn visit_do_stmt(&self, _: Rc<Stmt>, stmt: &DoStmt) -> Result<String, SaturdayResult> {
+    Ok(format!(
+      "{}do {} while {};",
+      SourceFormatter::label(&stmt.label),
+      self.body(&stmt.body)?,
+      self.expr(&stmt.condition)?
+    ))
+  }
+
+  fn visit_expression_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &ExpressionStmt,
+  ) -> Result<String, SaturdayResult> {
+    Ok(format!("{};", self.expr(&stmt.expression)?))
+  }
+
+  fn visit_foreach_stmt(&self, _: Rc<Stmt>, stmt: &ForEachStmt) -> Result<String, SaturdayResult> {
+    Ok(format!(
+      "{}for {} in {} {}",
+      SourceFormatter::label(&stmt.label),
+      stmt.name.lexeme,
+      self.expr(&stmt.iterable)?,
+      self.body(&stmt.body)?
+    ))
+  }
+
+  fn visit_function_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &FunctionStmt,
+  ) -> Result<String, SaturdayResult> {
+    Ok(format!("fun {}{}", stmt.name.lexeme, self.function(stmt)?))
+  }
+
+  fn visit_global_stmt(&self, _: Rc<Stmt>, stmt: &GlobalStmt) -> Result<String, SaturdayResult> {
+    Ok(format!("global {};", stmt.name.lexeme))
+  }
+
+  fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<String, SaturdayResult> {
+    let mut text = format!(
+      "if {} {}",
+      self.expr(&stmt.condition)?,
+      self.body(&stmt.then_branch)?
+    );
+    if let Some(else_branch) = &stmt.else_branch {
+      text.push_str(&format!(" else {}", self.body(else_branch)?));
+    }
+    Ok(text)
+  }
+
+  fn visit_import_stmt(&self, _: Rc<Stmt>, stmt: &ImportStmt) -> Result<String, SaturdayResult> {
+    let mut text = format!("import {}", SourceFormatter::literal(&stmt.path.literal));
+    if let Some(alias) = &stmt.alias {
+      text.push_str(&format!(" as {}", alias.lexeme));
+    }
+    text.push(';');
+    Ok(text)
+  }
+
+  fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<String, SaturdayResult> {
+    Ok(format!("print {};", self.expr(&stmt.expression)?))
+  }
+
+  fn visit_repeat_stmt(&self, _: Rc<Stmt>, stmt: &RepeatStmt) -> Result<String, SaturdayResult> {
+    Ok(format!(
+      "{}repeat {} {}",
+      SourceFormatter::label(&stmt.label),
+      self.expr(&stmt.count)?,
+      self.body(&stmt.body)?
+    ))
+  }
+
+  fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<String, SaturdayResult> {
+    match &stmt.value {
+      Some(value) => Ok(format!("return {};", self.expr(value)?)),
+      None => Ok("return;".to_string()),
+    }
+  }
+
+  fn visit_throw_stmt(&self, _: Rc<Stmt>, stmt: &ThrowStmt) -> Result<String, SaturdayResult> {
+    Ok(format!("throw {};", self.expr(&stmt.value)?))
+  }
+
+  fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<String, SaturdayResult> {
+    Ok(format!(
+      "try {} catch ({}) {}",
+      self.block(&stmt.body)?,
+      stmt.name.lexeme,
+      self.block(&stmt.handler)?
+    ))
+  }
+
+  fn visit_undef_stmt(&self, _: Rc<Stmt>, stmt: &UndefStmt) -> Result<String, SaturdayResult> {
+    Ok(format!("undef {};", stmt.name.lexeme))
+  }
+
+  /// `for` 循环被展开为 `while`，关键字仍然是 `for`
+  fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<String, SaturdayResult> {
+    let label = SourceFormatter::label(&stmt.label);
+    if &*stmt.keyword.lexeme == "for" {
+      return Ok(format!("{label}for ; {}", self.for_header(stmt)?));
+    }
+
+    Ok(format!(
+      "{label}while {} {}",
+      self.expr(&stmt.condition)?,
+      self.body(&stmt.body)?
+    ))
+  }
+}
+
+impl SourceFormatter {
+  /// `for` 的条件、步进和循环体，初始化部分由调用方输出
+  fn for_header(&self, stmt: &WhileStmt) -> Result<String, SaturdayResult> {
+    let increment = match &stmt.increment {
+      Some(increment) => format!(" {}", self.expr(increment)?),
+      None => String::new(),
+    };
+    Ok(format!(
+      "{};{increment} {}",
+      self.expr(&stmt.condition)?,
+      self.body(&stmt.body)?
+    ))
+  }
+}
+
+impl ExprVisitor<String> for SourceFormatter {
+  fn visit_assign_expr(&self, _: Rc<Expr>, expr: &AssignExpr) -> Result<String, SaturdayResult> {
+    Ok(format!(
+      "{} = {}",
+      expr.name.lexeme,
+      self.operand(&expr.value, ASSIGNMENT)?
+    ))
+  }
+
+  fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<String, SaturdayResult> {
+    let precedence = SourceFormatter::binary_precedence(&expr.operator);
+    // `**` 右结合，左边只能是调用或更紧的表达式，右边允许一元运算
+    let (left, right) = if expr.operator.is(TokenType::StarStar) {
+      (CALL, UNARY)
+    } else {
+      (precedence, precedence + 1)
+    };
+    Ok(format!(
+      "{} {} {}",
+      self.operand(&expr.left, left)?,
+      expr.operator.lexeme,
+      self.operand(&expr.right, right)?
+    ))
+  }
+
+  fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<String, SaturdayResult> {
+    let arguments: Result<Vec<String>, SaturdayResult> = expr
+      .arguments
+      .iter()
+      .map(|argument| self.operand(argument, ASSIGNMENT))
+      .collect();
+    Ok(format!(
+      "{}({})",
+      self.operand(&expr.callee, CALL)?,
+      arguments?.join(", ")
+    ))
+  }
+
+  fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<String, SaturdayResult> {
+    Ok(format!(
+      "{}.{}",
+      self.operand(&expr.object, CALL)?,
+      expr.name.lexeme
+    ))
+  }
+
+  fn visit_grouping_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &GroupingExpr,
+  ) -> Result<String, SaturdayResult> {
+    Ok(format!("({})", self.expr(&expr.expression)?))
+  }
+
+  /// 字符串片段和 `${}` 中的表达式交替出现
+  fn visit_interpolation_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &InterpolationExpr,
+  ) -> Result<String, SaturdayResult> {
+    let mut text = String::from("\"");
+    for part in expr.parts.iter() {
+      match part.deref() {
+        Expr::Literal(literal) => match &literal.value {
+          Some(Object::Str(s)) => text.push_str(&SourceFormatter::escape(s)),
+          _ => text.push_str(&format!("${{{}}}", self.expr(part)?)),
+        },
+        _ => text.push_str(&format!("${{{}}}", self.expr(part)?)),
+      }
+    }
+    text.push('"');
+    Ok(text)
+  }
+
+  fn visit_lambda_expr(&self, _: Rc<Expr>, expr: &LambdaExpr) -> Result<String, SaturdayResult> {
+    Ok(format!("fun {}", self.function(&expr.function)?))
+  }
+
+  fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<String, SaturdayResult> {
+    Ok(SourceFormatter::literal(&expr.value))
+  }
+
+  fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<String, SaturdayResult> {
+    let precedence = SourceFormatter::logical_precedence(&expr.operator);
+    Ok(format!(
+      "{} {} {}",
+      self.operand(&expr.left, precedence)?,
+      expr.operator.lexeme,
+      self.operand(&expr.right, precedence + 1)?
+    ))
+  }
+
+  fn visit_postfix_expr(&self, _: Rc<Expr>, expr: &PostfixExpr) -> Result<String, SaturdayResult> {
+    Ok(format!("{}{}", expr.name.lexeme, expr.operator.lexeme))
+  }
+
+  fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<String, SaturdayResult> {
+    let operator = match &expr.operator {
+      Some(operator) => operator.lexeme.to_string(),
+      None => String::new(),
+    };
+    Ok(format!(
+      "{}.{} {operator}= {}",
+      self.operand(&expr.object, CALL)?,
+      expr.name.lexeme,
+      self.operand(&expr.value, ASSIGNMENT)?
+    ))
+  }
+
+  fn visit_super_expr(&self, _: Rc<Expr>, expr: &SuperExpr) -> Result<String, SaturdayResult> {
+    Ok(format!("super.{}", expr.method.lexeme))
+  }
+
+  fn visit_this_expr(&self, _: Rc<Expr>, _: &ThisExpr) -> Result<String, SaturdayResult> {
+    Ok("this".to_string())
+  }
+
+  fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<String, SaturdayResult> {
+    let right = self.operand(&expr.right, UNARY)?;
+    // `- -x` 不能写成 `--x`，否则会被扫描成自减
+    if expr.operator.is(TokenType::Minus) && right.starts_with('-') {
+      Ok(format!("- {right}"))
+    } else {
+      Ok(format!("{}{right}", expr.operator.lexeme))
+    }
+  }
+
+  fn visit_variable_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &VariableExpr,
+  ) -> Result<String, SaturdayResult> {
+    Ok(expr.name.lexeme.to_string())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn messy_source_formats_canonically() {
+    let messy = r#"def   x=1+2*3; // dropped
+fun add(a,b=2,...rest){return a+b;}
+class P<Base{init(x){this.x=x;} get len{return   this.x;} static zero(){return P(0);}}
+for def i=0;i<3;i++{ if i==1{continue;}else{print "i=${i}\n";} }
+outer: while x>0 { x -= 1; }
+try{throw "bad";}catch e{print e;}
+print (1+2)*-3 ** 2 ?? a or b and c;
+def f=fun(a){};
+"#;
+    let expected = r#"def x = 1 + 2 * 3;
+fun add(a, b = 2, ...rest) {
+  return a + b;
+}
+class P < Base {
+  init(x) {
+    this.x = x;
+  }
+  get len {
+    return this.x;
+  }
+  static zero() {
+    return P(0);
+  }
+}
+for def i = 0; i < 3; i++ {
+  if i == 1 {
+    continue;
+  } else {
+    print "i=${i}\n";
+  }
+}
+outer: while x > 0 {
+  x = x - 1;
+}
+try {
+  throw "bad";
+} catch (e) {
+  print e;
+}
+print (1 + 2) * -3 ** 2 ?? a or b and c;
+def f = fun (a) {};
+"#;
+    let formatted = format_source(messy).unwrap();
+    assert_eq!(formatted, expected);
+    assert_eq!(format_source(&formatted).unwrap(), formatted);
+  }
+
+  #[test]
+  fn desugared_nodes_keep_their_meaning() {
+    let source =
+      "print (a = b) + 1;\nprint a - (b - c);\nprint - -x;\nmake().count += 1;\nprint \"\\${no} \\\"q\\\"\";\n";
+    let formatted = format_source(source).unwrap();
+    assert_eq!(formatted, source);
+    assert!(format_source("print (1;").is_none());
+  }
+}
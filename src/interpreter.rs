@@ -1,26 +1,103 @@
 use crate::callable::{Callable, SaturdayCallable};
 use crate::environment::Environment;
-use crate::error::SaturdayResult;
+use crate::error::{current_source, set_source, SaturdayResult};
 use crate::expr::*;
-use crate::native_functions::NativeClock;
+use crate::module::Module;
+use crate::native_functions::{
+  natives, Capabilities, Clock, FileSystem, NativeClosure, NativeFn, StdFileSystem, SystemClock,
+};
 use crate::object::*;
+use crate::parser::Parser;
+use crate::resolver::Resolver;
 use crate::saturday_class::SaturdayClass;
 use crate::saturday_function::SaturdayFunction;
+use crate::scanner::Scanner;
 use crate::stmt::{
-  BlockStmt, BreakStmt, ClassStmt, DefStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt,
-  ReturnStmt, Stmt, StmtVisitor, WhileStmt,
+  AssertStmt, BlockStmt, BreakStmt, ClassStmt, ConstStmt, ContinueStmt, DefStmt, DoStmt,
+  ExpressionStmt, ForEachStmt, FunctionStmt, GlobalStmt, IfStmt, ImportStmt, PrintStmt, RepeatStmt,
+  ReturnStmt, Stmt, StmtVisitor, ThrowStmt, TryStmt, UndefStmt, WhileStmt,
 };
 use crate::token::Token;
 use crate::token_type::TokenType;
 use std::cell::RefCell;
 use std::collections::HashMap;
+use std::io::Write;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
 
 pub struct Interpreter {
   pub globals: Rc<RefCell<Environment>>,
   environment: RefCell<Rc<RefCell<Environment>>>,
   locals: RefCell<HashMap<Rc<Expr>, usize>>,
+  /// 读取全局变量的表达式缓存下变量在全局环境中的位置
+  global_slots: RefCell<HashMap<Rc<Expr>, usize>>,
+  call_depth: RefCell<usize>,
+  max_call_depth: RefCell<usize>,
+  /// `print` 等输出的目标，默认是标准输出
+  out: RefCell<Box<dyn Write>>,
+  clock: RefCell<Box<dyn Clock>>,
+  /// 全局环境中注册哪些内置函数
+  capabilities: Capabilities,
+  /// `None` 表示禁止访问文件系统
+  file_system: RefCell<Option<Rc<dyn FileSystem>>>,
+  steps: RefCell<u64>,
+  /// 最多执行的语句与表达式数量，`None` 表示不限制
+  step_limit: RefCell<Option<u64>>,
+  /// 为 true 时 `and`、`or` 的结果总是布尔值，默认返回操作数本身
+  strict_logical: RefCell<bool>,
+  /// 数字 `==`、`!=` 使用的容差，`None` 表示精确比较
+  float_epsilon: RefCell<Option<f64>>,
+  /// 为 true 时 `if`、`while`、`do`、`for` 的条件必须是布尔值
+  strict_conditions: RefCell<bool>,
+  /// 正在执行的文件，由外到内，最后一个是当前文件
+  importing: RefCell<Vec<PathBuf>>,
+}
+
+/// # 默认的最大调用深度
+/// 超过后报告运行时错误而不是让进程栈溢出。
+/// 调试构建中每层调用要用十几 KB 的栈，这个深度在 2MB 的普通线程上也放得下
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 100;
+
+/// # 解释器线程的栈大小
+/// 命令行在这么大栈的线程上运行解释器，以便使用更深的调用
+pub const INTERPRETER_STACK_SIZE: usize = 64 << 20;
+
+/// 在 `with_interpreter_stack` 的线程上使用的最大调用深度，相对栈大小留有数倍余量
+pub const INTERPRETER_STACK_MAX_CALL_DEPTH: usize = 1000;
+
+/// 在栈大小为 `INTERPRETER_STACK_SIZE` 的线程上运行，等待它结束，panic 会继续传出
+pub fn with_interpreter_stack<T: Send + 'static>(run: impl FnOnce() -> T + Send + 'static) -> T {
+  let thread = std::thread::Builder::new()
+    .stack_size(INTERPRETER_STACK_SIZE)
+    .spawn(run)
+    .expect("Could not start interpreter thread");
+  match thread.join() {
+    Ok(value) => value,
+    Err(panic) => std::panic::resume_unwind(panic),
+  }
+}
+
+/// # 共享的输出缓冲
+/// 交给 [`Interpreter::with_writer`] 后仍可以通过克隆读取程序输出
+#[derive(Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+  /// 目前写入的全部内容，无效的 UTF-8 用替换字符代替
+  pub fn contents(&self) -> String {
+    String::from_utf8_lossy(&self.0.borrow()).into_owned()
+  }
+}
+
+impl Write for SharedBuffer {
+  fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+    self.0.borrow_mut().write(buf)
+  }
+
+  fn flush(&mut self) -> std::io::Result<()> {
+    Ok(())
+  }
 }
 
 impl StmtVisitor<()> for Interpreter {
@@ -30,25 +107,77 @@ impl StmtVisitor<()> for Interpreter {
   }
 
   fn visit_class_stmt(&self, _: Rc<Stmt>, stmt: &ClassStmt) -> Result<(), SaturdayResult> {
+    let superclass = match &stmt.superclass {
+      Some(superclass) => match self.evaluate(Rc::clone(superclass))? {
+        Object::Class(class) => Some(class),
+        _ => {
+          return Err(SaturdayResult::runtime_error(
+            &stmt.name,
+            "Superclass must be a class.",
+          ))
+        }
+      },
+      None => None,
+    };
+
     self
       .environment
       .borrow()
       .borrow_mut()
-      .define(&stmt.name.as_string(), Object::Nil);
+      .define(stmt.name.symbol(), Object::Nil);
 
-    let mut methods = HashMap::new();
-    for method in stmt.methods.deref() {
+    // 静态方法在类所在的环境中解析，收集完再为方法加上 `super` 所在的一层
+    let mut statics = HashMap::new();
+    for method in stmt.statics.deref() {
       if let Stmt::Function(func) = method.deref() {
         let function = Object::Func(Callable {
-          func: Rc::new(SaturdayFunction::new(func, &self.environment.borrow())),
+          func: Rc::new(SaturdayFunction::new(
+            func,
+            &self.environment.borrow(),
+            false,
+          )),
         });
-        methods.insert(func.name.as_string(), function);
+        statics.insert(func.name.as_string(), function);
+      }
+    }
+
+    let enclosing = superclass.as_ref().map(|superclass| {
+      let mut environment = Environment::new_with_enclosing(self.environment.borrow().clone());
+      environment.define("super", Object::Class(Rc::clone(superclass)));
+      self.environment.replace(Rc::new(RefCell::new(environment)))
+    });
+
+    let mut methods = HashMap::new();
+    let mut getters = HashMap::new();
+    for method in stmt.methods.deref() {
+      if let Stmt::Function(func) = method.deref() {
+        let is_initializer = &*func.name.lexeme == "init";
+        let function = Rc::new(SaturdayFunction::new(
+          func,
+          &self.environment.borrow(),
+          is_initializer,
+        ));
+        if func.getter {
+          getters.insert(func.name.as_string(), function);
+        } else {
+          methods.insert(func.name.as_string(), function);
+        }
       } else {
         panic!("non-function method in class");
       };
     }
 
-    let class = Object::Class(Rc::new(SaturdayClass::new(stmt.name.as_string(), methods)));
+    if let Some(enclosing) = enclosing {
+      self.environment.replace(enclosing);
+    }
+
+    let class = Object::Class(Rc::new(SaturdayClass::new(
+      stmt.name.as_string(),
+      superclass,
+      methods,
+      getters,
+      statics,
+    )));
     self
       .environment
       .borrow()
@@ -58,8 +187,40 @@ impl StmtVisitor<()> for Interpreter {
     Ok(())
   }
 
-  fn visit_break_stmt(&self, _: Rc<Stmt>, _: &BreakStmt) -> Result<(), SaturdayResult> {
-    Err(SaturdayResult::Break)
+  fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<(), SaturdayResult> {
+    Err(SaturdayResult::Break {
+      label: stmt.label.as_ref().map(Token::as_string),
+    })
+  }
+
+  fn visit_continue_stmt(&self, _: Rc<Stmt>, stmt: &ContinueStmt) -> Result<(), SaturdayResult> {
+    Err(SaturdayResult::Continue {
+      label: stmt.label.as_ref().map(Token::as_string),
+    })
+  }
+
+  fn visit_const_stmt(&self, _: Rc<Stmt>, stmt: &ConstStmt) -> Result<(), SaturdayResult> {
+    let value = self.evaluate(stmt.initializer.clone())?;
+    self
+      .environment
+      .borrow()
+      .borrow_mut()
+      .define_const(stmt.name.symbol(), value);
+    Ok(())
+  }
+
+  fn visit_do_stmt(&self, _: Rc<Stmt>, stmt: &DoStmt) -> Result<(), SaturdayResult> {
+    loop {
+      if self.loop_control(self.execute(stmt.body.clone()), &stmt.label)? {
+        break;
+      }
+
+      if !self.condition(&stmt.keyword, &stmt.condition)? {
+        break;
+      }
+    }
+
+    Ok(())
   }
 
   fn visit_expression_stmt(
@@ -71,10 +232,34 @@ impl StmtVisitor<()> for Interpreter {
     Ok(())
   }
 
+  fn visit_foreach_stmt(&self, _: Rc<Stmt>, stmt: &ForEachStmt) -> Result<(), SaturdayResult> {
+    // 遍历开始时的快照，循环体中修改列表不影响本次遍历
+    let items = match self.evaluate(stmt.iterable.clone())? {
+      Object::List(list) => list.borrow().items.clone(),
+      _ => {
+        return Err(SaturdayResult::runtime_error(
+          &stmt.name,
+          "Can only iterate over a list.",
+        ))
+      }
+    };
+
+    let body = Rc::new(vec![stmt.body.clone()]);
+    for item in items {
+      let mut e = Environment::new_with_enclosing(self.environment.borrow().clone());
+      e.define(stmt.name.symbol(), item);
+      if self.loop_control(self.execute_block(&body, e), &stmt.label)? {
+        break;
+      }
+    }
+
+    Ok(())
+  }
+
   fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), SaturdayResult> {
-    let function = SaturdayFunction::new(&Rc::new(stmt), &self.environment.borrow());
+    let function = SaturdayFunction::new(stmt, &self.environment.borrow(), false);
     self.environment.borrow().borrow_mut().define(
-      &stmt.name.as_string(),
+      stmt.name.symbol(),
       Object::Func(Callable {
         func: Rc::new(function),
       }),
@@ -83,7 +268,7 @@ impl StmtVisitor<()> for Interpreter {
   }
 
   fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<(), SaturdayResult> {
-    if self.is_truthy(&self.evaluate(stmt.condition.clone())?) {
+    if self.condition(&stmt.keyword, &stmt.condition)? {
       self.execute(stmt.then_branch.clone())
     } else if let Some(else_branch) = stmt.else_branch.clone() {
       self.execute(else_branch)
@@ -94,10 +279,98 @@ impl StmtVisitor<()> for Interpreter {
 
   fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<(), SaturdayResult> {
     let value = self.evaluate(stmt.expression.clone())?;
-    println!("{value}");
+    self.write_output(&format!("{}\n", self.stringify(&value)?))
+  }
+
+  /// 执行另一个文件，正在导入中的文件不能再次导入。
+  /// 没有别名时定义进入当前全局环境，有别名时在新的全局环境中执行并绑定为模块
+  fn visit_import_stmt(&self, _: Rc<Stmt>, stmt: &ImportStmt) -> Result<(), SaturdayResult> {
+    let path = self.import_path(&stmt.path)?;
+    if self.importing.borrow().contains(&path) {
+      return Err(SaturdayResult::runtime_error(
+        &stmt.path,
+        &format!("Circular import of '{}'.", path.display()),
+      ));
+    }
+
+    let source = self
+      .import_file_system(&stmt.path)?
+      .read(&path.to_string_lossy())
+      .map_err(|e| {
+        SaturdayResult::runtime_error(
+          &stmt.path,
+          &format!("Can't import '{}': {e}.", path.display()),
+        )
+      })?;
+
+    self.importing.borrow_mut().push(path);
+    let previous_source = current_source();
+    let result = match &stmt.alias {
+      Some(alias) => {
+        let globals = Rc::new(RefCell::new(Interpreter::global_environment(
+          self.capabilities,
+        )));
+        self.run_module(&source, Rc::clone(&globals)).map(|_| {
+          let module = Module::new(alias.as_string(), globals);
+          self
+            .environment
+            .borrow()
+            .borrow_mut()
+            .define(alias.symbol(), Object::Module(Rc::new(module)));
+        })
+      }
+      None => self.run_module(&source, self.current_globals()),
+    };
+    set_source(&previous_source);
+    self.importing.borrow_mut().pop();
+    result
+  }
+
+  fn visit_assert_stmt(&self, _: Rc<Stmt>, stmt: &AssertStmt) -> Result<(), SaturdayResult> {
+    let condition = self.evaluate(stmt.condition.clone())?;
+    if self.is_truthy(&condition) {
+      Ok(())
+    } else {
+      Err(SaturdayResult::runtime_error(
+        &stmt.keyword,
+        "Assertion failed.",
+      ))
+    }
+  }
+
+  /// 解析器已经让后续的访问指向全局变量，执行时无事可做
+  fn visit_global_stmt(&self, _: Rc<Stmt>, _: &GlobalStmt) -> Result<(), SaturdayResult> {
+    Ok(())
+  }
+
+  /// 缓存的全局位置可能指向被删除的绑定，所以一并清空
+  fn visit_undef_stmt(&self, _: Rc<Stmt>, stmt: &UndefStmt) -> Result<(), SaturdayResult> {
+    self.environment.borrow().borrow_mut().remove(&stmt.name)?;
+    self.global_slots.borrow_mut().clear();
     Ok(())
   }
 
+  fn visit_throw_stmt(&self, _: Rc<Stmt>, stmt: &ThrowStmt) -> Result<(), SaturdayResult> {
+    let value = self.evaluate(stmt.value.clone())?;
+    Err(SaturdayResult::Thrown {
+      token: stmt.keyword.dup(),
+      value,
+    })
+  }
+
+  /// 只捕获 `throw` 抛出的值，运行时错误与 break/return 照常向外传播
+  fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<(), SaturdayResult> {
+    let e = Environment::new_with_enclosing(self.environment.borrow().clone());
+    match self.execute_block(&stmt.body, e) {
+      Err(SaturdayResult::Thrown { value, .. }) => {
+        let mut e = Environment::new_with_enclosing(self.environment.borrow().clone());
+        e.define(stmt.name.symbol(), value);
+        self.execute_block(&stmt.handler, e)
+      }
+      result => result,
+    }
+  }
+
   fn visit_return_stmt(&self, _wrapper: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), SaturdayResult> {
     if let Some(value) = stmt.value.clone() {
       Err(SaturdayResult::return_value(self.evaluate(value)?))
@@ -117,16 +390,39 @@ impl StmtVisitor<()> for Interpreter {
       .environment
       .borrow()
       .borrow_mut()
-      .define(&stmt.name.as_string(), value);
+      .define(stmt.name.symbol(), value);
+    Ok(())
+  }
+
+  /// 次数在循环开始前求值一次，小数部分被舍去
+  fn visit_repeat_stmt(&self, _: Rc<Stmt>, stmt: &RepeatStmt) -> Result<(), SaturdayResult> {
+    let count = match self.evaluate(stmt.count.clone())? {
+      Object::Num(count) if count >= 0.0 => count as u64,
+      _ => {
+        return Err(SaturdayResult::runtime_error(
+          &stmt.keyword,
+          "Repeat count must be a non-negative number.",
+        ))
+      }
+    };
+
+    for _ in 0..count {
+      if self.loop_control(self.execute(stmt.body.clone()), &stmt.label)? {
+        break;
+      }
+    }
+
     Ok(())
   }
 
   fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<(), SaturdayResult> {
-    while self.is_truthy(&self.evaluate(stmt.condition.clone())?) {
-      match self.execute(stmt.body.clone()) {
-        Err(SaturdayResult::Break) => break,
-        Err(e) => return Err(e),
-        Ok(_) => {}
+    while self.condition(&stmt.keyword, &stmt.condition)? {
+      if self.loop_control(self.execute(stmt.body.clone()), &stmt.label)? {
+        break;
+      }
+
+      if let Some(increment) = stmt.increment.clone() {
+        self.evaluate(increment)?;
       }
     }
 
@@ -141,83 +437,35 @@ impl ExprVisitor<Object> for Interpreter {
     expr: &AssignExpr,
   ) -> Result<Object, SaturdayResult> {
     let value = self.evaluate(expr.value.clone())?;
-    if let Some(distance) = self.locals.borrow().get(&wrapper) {
-      self
-        .environment
-        .borrow()
-        .borrow_mut()
-        .assign_at(*distance, &expr.name, value.clone())?;
-    } else {
-      self
-        .globals
-        .borrow_mut()
-        .assign(&expr.name, value.clone())?;
-    }
-
+    self.assign_variable(&wrapper, &expr.name, value.clone())?;
     Ok(value)
   }
 
+  /// `x++`、`x--` 修改变量，结果是修改前的值
+  fn visit_postfix_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &PostfixExpr,
+  ) -> Result<Object, SaturdayResult> {
+    let Object::Num(old) = self.look_up_variable(&expr.name, Rc::clone(&wrapper))? else {
+      return Err(SaturdayResult::runtime_error(
+        &expr.operator,
+        &format!("Operand of '{}' must be a number.", expr.operator.lexeme),
+      ));
+    };
+    let new = if expr.operator.is(TokenType::PlusPlus) {
+      old + 1.0
+    } else {
+      old - 1.0
+    };
+    self.assign_variable(&wrapper, &expr.name, Object::Num(new))?;
+    Ok(Object::Num(old))
+  }
+
   fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<Object, SaturdayResult> {
     let left = self.evaluate(expr.left.clone())?;
     let right = self.evaluate(expr.right.clone())?;
-    let op = expr.operator.token_type();
-
-    let result = match (left, right) {
-      (Object::Num(left), Object::Num(right)) => match op {
-        TokenType::Minus => Object::Num(left - right),
-        TokenType::Slash => Object::Num(left / right),
-        TokenType::Star => Object::Num(left * right),
-        TokenType::Plus => Object::Num(left + right),
-        TokenType::Greater => Object::Bool(left > right),
-        TokenType::GreaterEqual => Object::Bool(left >= right),
-        TokenType::Less => Object::Bool(left < right),
-        TokenType::LessEqual => Object::Bool(left <= right),
-        TokenType::BangEqual => Object::Bool(left != right),
-        TokenType::Equal => Object::Bool(left == right),
-        _ => {
-          todo!("need to work on your code dude")
-        }
-      },
-      (Object::Num(left), Object::Str(right)) => match op {
-        TokenType::Plus => Object::Str(format!("{left}{right}")),
-        _ => Object::ArithmeticError,
-      },
-      (Object::Str(left), Object::Num(right)) => match op {
-        TokenType::Plus => Object::Str(format!("{left}{right}")),
-        _ => Object::ArithmeticError,
-      },
-      (Object::Str(left), Object::Str(right)) => match op {
-        TokenType::Plus => Object::Str(format!("{left}{right}")),
-        TokenType::BangEqual => Object::Bool(left != right),
-        TokenType::Equal => Object::Bool(left == right),
-        _ => Object::ArithmeticError,
-      },
-      (Object::Bool(left), Object::Bool(right)) => match op {
-        TokenType::BangEqual => Object::Bool(left != right),
-        TokenType::Equal => Object::Bool(left == right),
-        _ => Object::ArithmeticError,
-      },
-      (Object::Nil, Object::Nil) => match op {
-        TokenType::BangEqual => Object::Bool(false),
-        TokenType::Equal => Object::Bool(true),
-        _ => Object::ArithmeticError,
-      },
-      (Object::Nil, _) => match op {
-        TokenType::BangEqual => Object::Bool(true),
-        TokenType::Equal => Object::Bool(false),
-        _ => Object::ArithmeticError,
-      },
-      _ => Object::ArithmeticError,
-    };
-
-    if result == Object::ArithmeticError {
-      Err(SaturdayResult::runtime_error(
-        &expr.operator,
-        "Illegal expression",
-      ))
-    } else {
-      Ok(result)
-    }
+    self.binary(&expr.operator, left, right)
   }
 
   fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<Object, SaturdayResult> {
@@ -228,35 +476,26 @@ impl ExprVisitor<Object> for Interpreter {
     }
 
     if let Object::Func(function) = callee {
-      if arguments.len() != function.func.arity() {
-        return Err(SaturdayResult::runtime_error(
-          &expr.paren,
-          &format!(
-            "Expected {} arguments but got {}.",
-            function.func.arity(),
-            arguments.len()
-          ),
-        ));
-      }
-
-      function.func.call(self, arguments)
+      self.check_arity(&expr.paren, function.func.deref(), arguments.len())?;
+      self.guarded_call(&expr.paren, || function.func.call(self, arguments))
     } else if let Object::Class(class) = callee {
-      if arguments.len() != class.arity() {
-        return Err(SaturdayResult::runtime_error(
-          &expr.paren,
-          &format!(
-            "Expected {} arguments but got {}.",
-            class.arity(),
-            arguments.len()
-          ),
-        ));
-      }
-
-      class.instantiate(self, arguments, Rc::clone(&class))
+      self.check_arity(&expr.paren, class.deref(), arguments.len())?;
+      self.guarded_call(&expr.paren, || {
+        SaturdayClass::instantiate(&class, self, arguments)
+      })
     } else {
+      // 被调用的值没有名字，用变量或属性名指出是哪个
+      let name = match expr.callee.deref() {
+        Expr::Variable(variable) => variable.name.as_string(),
+        Expr::Get(get) => get.name.as_string(),
+        _ => callee.to_string(),
+      };
       Err(SaturdayResult::runtime_error(
         &expr.paren,
-        "Can only call function and classes",
+        &format!(
+          "Can only call function and classes, but '{name}' is a {}.",
+          callee.type_name()
+        ),
       ))
     }
   }
@@ -264,7 +503,16 @@ impl ExprVisitor<Object> for Interpreter {
   fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<Object, SaturdayResult> {
     let object = self.evaluate(expr.object.clone())?;
     if let Object::Instance(inst) = object {
-      Ok(inst.get(&expr.name)?)
+      inst.get(&expr.name, self)
+    } else if let Object::Module(module) = object {
+      module.get(&expr.name)
+    } else if let Object::Class(class) = object {
+      class.find_static(&expr.name.as_string()).ok_or_else(|| {
+        SaturdayResult::runtime_error(
+          &expr.name,
+          &format!("Undefined static method '{}'.", expr.name.as_string()),
+        )
+      })
     } else {
       Err(SaturdayResult::runtime_error(
         &expr.name,
@@ -281,6 +529,20 @@ impl ExprVisitor<Object> for Interpreter {
     self.evaluate(expr.expression.clone())
   }
 
+  fn visit_interpolation_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &InterpolationExpr,
+  ) -> Result<Object, SaturdayResult> {
+    let mut result = String::new();
+    for part in expr.parts.iter() {
+      let value = self.evaluate(part.clone())?;
+      result.push_str(&self.stringify(&value)?);
+    }
+
+    Ok(Object::Str(result.into()))
+  }
+
   fn visit_literal_expr(&self, _: Rc<Expr>, expr: &LiteralExpr) -> Result<Object, SaturdayResult> {
     Ok(expr.value.clone().unwrap())
   }
@@ -288,21 +550,84 @@ impl ExprVisitor<Object> for Interpreter {
   fn visit_logical_expr(&self, _: Rc<Expr>, expr: &LogicalExpr) -> Result<Object, SaturdayResult> {
     let left = self.evaluate(expr.left.clone())?;
 
-    if expr.operator.is(TokenType::Or) {
-      if self.is_truthy(&left) {
+    if expr.operator.is(TokenType::QuestionQuestion) {
+      if left != Object::Nil {
         return Ok(left);
       }
-    } else if !self.is_truthy(&left) {
-      return Ok(left);
+      return self.evaluate(expr.right.clone());
+    }
+
+    let result = if expr.operator.is(TokenType::Or) == self.is_truthy(&left) {
+      left
+    } else {
+      self.evaluate(expr.right.clone())?
+    };
+
+    if *self.strict_logical.borrow() {
+      Ok(Object::Bool(self.is_truthy(&result)))
+    } else {
+      Ok(result)
+    }
+  }
+
+  fn visit_lambda_expr(&self, _: Rc<Expr>, expr: &LambdaExpr) -> Result<Object, SaturdayResult> {
+    let function = SaturdayFunction::new(&expr.function, &self.environment.borrow(), false);
+    Ok(Object::Func(Callable {
+      func: Rc::new(function),
+    }))
+  }
+
+  /// `super` 所在的环境紧挨在 `this` 所在环境的外层
+  fn visit_super_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &SuperExpr,
+  ) -> Result<Object, SaturdayResult> {
+    let distance = *self
+      .locals
+      .borrow()
+      .get(&wrapper)
+      .ok_or_else(|| SaturdayResult::system_error("unresolved super expression"))?;
+    let environment = self.environment.borrow().clone();
+    let superclass = environment.borrow().get_at(distance, "super")?;
+    let this = environment.borrow().get_at(distance - 1, "this")?;
+    let (Object::Class(superclass), Object::Instance(instance)) = (superclass, this) else {
+      return Err(SaturdayResult::system_error("malformed super environment"));
+    };
+
+    let name = expr.method.as_string();
+    if let Some(getter) = superclass.find_getter(&name) {
+      self.guarded_call(&expr.method, || {
+        getter.bind(instance).call(self, Vec::new())
+      })
+    } else if let Some(method) = superclass.find_method(&name) {
+      Ok(Object::Func(Callable {
+        func: Rc::new(method.bind(instance)),
+      }))
+    } else {
+      Err(SaturdayResult::runtime_error(
+        &expr.method,
+        &format!("Undefined property '{name}'."),
+      ))
     }
+  }
 
-    self.evaluate(expr.right.clone())
+  fn visit_this_expr(&self, wrapper: Rc<Expr>, expr: &ThisExpr) -> Result<Object, SaturdayResult> {
+    self.look_up_variable(&expr.keyword, wrapper)
   }
 
   fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<Object, SaturdayResult> {
     let object = self.evaluate(expr.object.clone())?;
     if let Object::Instance(inst) = object {
-      let value = self.evaluate(expr.value.clone())?;
+      // `obj.f += x` 复用已经求值的对象，并和变量一样先读旧值再求右侧
+      let value = match &expr.operator {
+        Some(operator) => {
+          let old = inst.get(&expr.name, self)?;
+          let value = self.evaluate(expr.value.clone())?;
+          self.binary(operator, old, value)?
+        }
+        None => self.evaluate(expr.value.clone())?,
+      };
       inst.set(&expr.name, value.clone());
       Ok(value)
     } else {
@@ -340,101 +665,779 @@ impl ExprVisitor<Object> for Interpreter {
 
 impl Interpreter {
   pub fn new() -> Self {
-    let globals = Rc::new(RefCell::new(Environment::new()));
-    globals.borrow_mut().define(
-      "clock",
-      Object::Func(Callable {
-        func: Rc::new(NativeClock {}),
-      }),
-    );
+    Interpreter::with_writer(Box::new(std::io::stdout()))
+  }
+
+  /// 输出写入到指定的 writer，便于嵌入时捕获程序输出
+  pub fn with_writer(out: Box<dyn Write>) -> Self {
+    Interpreter::with_writer_and_capabilities(out, Capabilities::default())
+  }
+
+  /// 只注册允许的内置函数，见 [`Capabilities`]
+  pub fn with_capabilities(capabilities: Capabilities) -> Self {
+    Interpreter::with_writer_and_capabilities(Box::new(std::io::stdout()), capabilities)
+  }
+
+  pub fn with_writer_and_capabilities(out: Box<dyn Write>, capabilities: Capabilities) -> Self {
+    let globals = Rc::new(RefCell::new(Interpreter::global_environment(capabilities)));
 
     Self {
       globals: Rc::clone(&globals),
       environment: RefCell::new(Rc::clone(&globals)),
       locals: RefCell::new(HashMap::new()),
+      global_slots: RefCell::new(HashMap::new()),
+      call_depth: RefCell::new(0),
+      max_call_depth: RefCell::new(DEFAULT_MAX_CALL_DEPTH),
+      out: RefCell::new(out),
+      clock: RefCell::new(Box::new(SystemClock)),
+      capabilities,
+      file_system: RefCell::new(Some(Rc::new(StdFileSystem))),
+      steps: RefCell::new(0),
+      step_limit: RefCell::new(None),
+      strict_logical: RefCell::new(false),
+      float_epsilon: RefCell::new(None),
+      strict_conditions: RefCell::new(false),
+      importing: RefCell::new(Vec::new()),
     }
   }
 
-  fn evaluate(&self, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
-    expr.accept(expr.clone(), self)
+  /// 只包含内置函数的全局环境
+  fn global_environment(capabilities: Capabilities) -> Environment {
+    let mut globals = Environment::new();
+    for (name, func) in natives() {
+      if !capabilities.allows(name) {
+        continue;
+      }
+      globals.define(name, Object::Func(Callable { func }));
+    }
+    globals
   }
 
-  fn execute(&self, stmt: Rc<Stmt>) -> Result<(), SaturdayResult> {
-    stmt.accept(stmt.clone(), self)
+  /// # 注册内置函数
+  /// 把闭包包装成可调用对象定义在全局环境中。
+  /// 闭包可以返回 `SaturdayResult::native_error`，报告时会带上调用位置。
+  pub fn define_native(&self, name: &str, arity: usize, function: NativeFn) {
+    let native = NativeClosure {
+      name: name.to_string(),
+      arity,
+      function,
+    };
+    self.globals.borrow_mut().define(
+      name,
+      Object::Func(Callable {
+        func: Rc::new(native),
+      }),
+    );
   }
 
-  pub fn execute_block(
+  /// 写入程序输出
+  pub fn write_output(&self, text: &str) -> Result<(), SaturdayResult> {
+    let mut out = self.out.borrow_mut();
+    out
+      .write_all(text.as_bytes())
+      .and_then(|_| out.flush())
+      .map_err(|e| SaturdayResult::system_error(&format!("Could not write output: {e}")))
+  }
+
+  /// 丢弃所有用户定义，恢复到刚创建时的状态
+  pub fn reset(&self) {
+    *self.globals.borrow_mut() = Interpreter::global_environment(self.capabilities);
+    self.environment.replace(Rc::clone(&self.globals));
+    self.locals.borrow_mut().clear();
+    self.global_slots.borrow_mut().clear();
+    self.call_depth.replace(0);
+    self.steps.replace(0);
+  }
+
+  fn evaluate(&self, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
+    self.step(Interpreter::expr_token(&expr))?;
+    expr.accept(expr.clone(), self)
+  }
+
+  /// 在指定环境中对表达式求值，求值结束后恢复当前环境
+  pub fn evaluate_in(
     &self,
-    statements: &Rc<Vec<Rc<Stmt>>>,
-    environment: Environment,
-  ) -> Result<(), SaturdayResult> {
-    let previous = self.environment.replace(Rc::new(RefCell::new(environment)));
-    let result = statements
-      .iter()
-      .try_for_each(|statement| self.execute(statement.clone()));
+    expr: Rc<Expr>,
+    environment: Rc<RefCell<Environment>>,
+  ) -> Result<Object, SaturdayResult> {
+    let previous = self.environment.replace(environment);
+    let result = self.evaluate(expr);
     self.environment.replace(previous);
     result
   }
 
-  /// 任何不等于Nil和False的识别为true
-  fn is_truthy(&self, object: &Object) -> bool {
-    !matches!(object, Object::Nil | Object::Bool(false))
+  fn execute(&self, stmt: Rc<Stmt>) -> Result<(), SaturdayResult> {
+    self.step(Interpreter::stmt_token(&stmt))?;
+    stmt.accept(stmt.clone(), self)
   }
 
-  pub fn interpreter(&self, statements: &[Rc<Stmt>]) -> bool {
-    let mut success = true;
-    for statement in statements {
-      if self.execute(statement.clone()).is_err() {
-        success = false;
-        break;
+  /// 两个已经求值的操作数之间的二元运算
+  fn binary(
+    &self,
+    operator: &Token,
+    left: Object,
+    right: Object,
+  ) -> Result<Object, SaturdayResult> {
+    let op = operator.token_type();
+    if matches!(
+      op,
+      TokenType::Greater | TokenType::GreaterEqual | TokenType::Less | TokenType::LessEqual
+    ) {
+      // 与 IEEE 754 相同，数字与 NaN 的大小比较总是 false
+      if matches!((&left, &right), (Object::Num(l), Object::Num(r)) if l.is_nan() || r.is_nan()) {
+        return Ok(Object::Bool(false));
       }
+
+      let ordering = left.partial_cmp(&right).ok_or_else(|| {
+        Interpreter::illegal_operands(operator, left.type_name(), right.type_name())
+      })?;
+      return Ok(Object::Bool(match op {
+        TokenType::Greater => ordering.is_gt(),
+        TokenType::GreaterEqual => ordering.is_ge(),
+        TokenType::Less => ordering.is_lt(),
+        _ => ordering.is_le(),
+      }));
+    }
+
+    if matches!(
+      op,
+      TokenType::Ampersand
+        | TokenType::Pipe
+        | TokenType::Caret
+        | TokenType::LessLess
+        | TokenType::GreaterGreater
+    ) {
+      return Interpreter::bitwise(operator, &left, &right);
     }
 
-    success
+    let types = (left.type_name(), right.type_name());
+    let illegal = || Err(Interpreter::illegal_operands(operator, types.0, types.1));
+    match (left, right) {
+      (Object::Num(left), Object::Num(right)) => match op {
+        TokenType::Minus => Ok(Object::Num(left - right)),
+        TokenType::Slash => Ok(Object::Num(left / right)),
+        TokenType::Star => Ok(Object::Num(left * right)),
+        TokenType::Plus => Ok(Object::Num(left + right)),
+        TokenType::StarStar => Ok(Object::Num(left.powf(right))),
+        TokenType::BangEqual => Ok(Object::Bool(!numbers_equal(
+          left,
+          right,
+          self.float_epsilon(),
+        ))),
+        TokenType::Equal => Ok(Object::Bool(numbers_equal(
+          left,
+          right,
+          self.float_epsilon(),
+        ))),
+        _ => illegal(),
+      },
+      (Object::Num(left), Object::Str(right)) => match op {
+        TokenType::Plus => Ok(Object::Str(format!("{left}{right}").into())),
+        TokenType::BangEqual => Ok(Object::Bool(true)),
+        TokenType::Equal => Ok(Object::Bool(false)),
+        _ => illegal(),
+      },
+      (Object::Str(left), Object::Num(right)) => match op {
+        TokenType::Plus => Ok(Object::Str(format!("{left}{right}").into())),
+        TokenType::BangEqual => Ok(Object::Bool(true)),
+        TokenType::Equal => Ok(Object::Bool(false)),
+        _ => illegal(),
+      },
+      (Object::Str(left), Object::Str(right)) => match op {
+        TokenType::Plus => Ok(Object::Str(format!("{left}{right}").into())),
+        TokenType::BangEqual => Ok(Object::Bool(left != right)),
+        TokenType::Equal => Ok(Object::Bool(left == right)),
+        _ => illegal(),
+      },
+      (Object::Bool(left), Object::Bool(right)) => match op {
+        TokenType::BangEqual => Ok(Object::Bool(left != right)),
+        TokenType::Equal => Ok(Object::Bool(left == right)),
+        _ => illegal(),
+      },
+      (Object::Instance(left), Object::Instance(right)) => match op {
+        TokenType::BangEqual => Ok(Object::Bool(!Rc::ptr_eq(&left, &right))),
+        TokenType::Equal => Ok(Object::Bool(Rc::ptr_eq(&left, &right))),
+        _ => illegal(),
+      },
+      (Object::Nil, Object::Nil) => match op {
+        TokenType::BangEqual => Ok(Object::Bool(false)),
+        TokenType::Equal => Ok(Object::Bool(true)),
+        _ => illegal(),
+      },
+      // 其余组合只支持相等比较，类型不同时总是不相等
+      (left, right) => match op {
+        TokenType::BangEqual => Ok(Object::Bool(left != right)),
+        TokenType::Equal => Ok(Object::Bool(left == right)),
+        _ => illegal(),
+      },
+    }
   }
 
-  pub fn print_environment(&self) {
-    println!("{:?}", self.environment.borrow().borrow());
+  /// 运算符不支持这两种类型的操作数，错误信息给出两边的类型名
+  fn illegal_operands(operator: &Token, left: &str, right: &str) -> SaturdayResult {
+    SaturdayResult::runtime_error(
+      operator,
+      &format!(
+        "Operator '{}' cannot be applied to {left} and {right}.",
+        operator.lexeme
+      ),
+    )
   }
 
-  pub fn resolve(&self, expr: Rc<Expr>, depth: usize) {
-    self.locals.borrow_mut().insert(expr, depth);
+  /// 位运算，两个操作数都必须是整数
+  fn bitwise(operator: &Token, left: &Object, right: &Object) -> Result<Object, SaturdayResult> {
+    let integer = |value: &Object| match value {
+      Object::Num(n) if n.fract() == 0.0 && n.abs() <= i64::MAX as f64 => Some(*n as i64),
+      _ => None,
+    };
+    let (Some(left), Some(right)) = (integer(left), integer(right)) else {
+      return Err(SaturdayResult::runtime_error(
+        operator,
+        &format!("Operands of '{}' must be integers.", operator.lexeme),
+      ));
+    };
+
+    let shift = |shifted: Option<i64>| {
+      shifted.ok_or_else(|| {
+        SaturdayResult::runtime_error(operator, "Shift amount must be between 0 and 63.")
+      })
+    };
+    let result = match operator.token_type() {
+      TokenType::Ampersand => left & right,
+      TokenType::Pipe => left | right,
+      TokenType::Caret => left ^ right,
+      TokenType::LessLess => shift(u32::try_from(right).ok().and_then(|r| left.checked_shl(r)))?,
+      _ => shift(u32::try_from(right).ok().and_then(|r| left.checked_shr(r)))?,
+    };
+
+    Ok(Object::Num(result as f64))
   }
 
-  pub fn look_up_variable(&self, name: &Token, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
+  /// # 循环体的控制流
+  /// 不带标签或标签与本循环相同的 break/continue 由本循环处理，
+  /// 返回是否结束循环；其余的继续向外层传播
+  fn loop_control(
+    &self,
+    result: Result<(), SaturdayResult>,
+    label: &Option<Token>,
+  ) -> Result<bool, SaturdayResult> {
+    let targets = |target: &Option<String>| match target {
+      Some(target) => label
+        .as_ref()
+        .is_some_and(|label| *label.lexeme == **target),
+      None => true,
+    };
+
+    match result {
+      Ok(()) => Ok(false),
+      Err(SaturdayResult::Break { label }) if targets(&label) => Ok(true),
+      Err(SaturdayResult::Continue { label }) if targets(&label) => Ok(false),
+      Err(e) => Err(e),
+    }
+  }
+
+  /// # 计一步
+  /// 超过步数限制时在 `token` 处报告运行时错误。
+  /// 没有 token 的节点（字面量、分组、块等）留给接下来带 token 的节点报告
+  fn step(&self, token: Option<&Token>) -> Result<(), SaturdayResult> {
+    if let Some(limit) = *self.step_limit.borrow() {
+      let mut steps = self.steps.borrow_mut();
+      if *steps < limit {
+        *steps += 1;
+      } else if let Some(token) = token {
+        return Err(SaturdayResult::runtime_error(
+          token,
+          "Execution step limit exceeded.",
+        ));
+      }
+    }
+
+    Ok(())
+  }
+
+  /// 报告步数超限时使用的表达式位置
+  fn expr_token(expr: &Expr) -> Option<&Token> {
+    match expr {
+      Expr::Assign(e) => Some(&e.name),
+      Expr::Binary(e) => Some(&e.operator),
+      Expr::Call(e) => Some(&e.paren),
+      Expr::Get(e) => Some(&e.name),
+      Expr::Lambda(e) => Some(&e.keyword),
+      Expr::Logical(e) => Some(&e.operator),
+      Expr::Postfix(e) => Some(&e.operator),
+      Expr::Set(e) => Some(&e.name),
+      Expr::Super(e) => Some(&e.method),
+      Expr::This(e) => Some(&e.keyword),
+      Expr::Unary(e) => Some(&e.operator),
+      Expr::Variable(e) => Some(&e.name),
+      Expr::Grouping(_) | Expr::Interpolation(_) | Expr::Literal(_) => None,
+    }
+  }
+
+  /// 报告步数超限时使用的语句位置
+  fn stmt_token(stmt: &Stmt) -> Option<&Token> {
+    match stmt {
+      Stmt::Assert(s) => Some(&s.keyword),
+      Stmt::Class(s) => Some(&s.name),
+      Stmt::Break(s) => Some(&s.token),
+      Stmt::Continue(s) => Some(&s.token),
+      Stmt::Const(s) => Some(&s.name),
+      Stmt::Do(s) => Some(&s.keyword),
+      Stmt::ForEach(s) => Some(&s.name),
+      Stmt::Function(s) => Some(&s.name),
+      Stmt::Global(s) => Some(&s.keyword),
+      Stmt::If(s) => Some(&s.keyword),
+      Stmt::Import(s) => Some(&s.keyword),
+      Stmt::Repeat(s) => Some(&s.keyword),
+      Stmt::Return(s) => Some(&s.keyword),
+      Stmt::Throw(s) => Some(&s.keyword),
+      Stmt::Def(s) => Some(&s.name),
+      Stmt::Undef(s) => Some(&s.keyword),
+      Stmt::While(s) => Some(&s.keyword),
+      Stmt::Block(_) | Stmt::Expression(_) | Stmt::Print(_) | Stmt::Try(_) => None,
+    }
+  }
+
+  pub fn execute_block(
+    &self,
+    statements: &Rc<Vec<Rc<Stmt>>>,
+    environment: Environment,
+  ) -> Result<(), SaturdayResult> {
+    let previous = self.environment.replace(Rc::new(RefCell::new(environment)));
+    let result = statements
+      .iter()
+      .try_for_each(|statement| self.execute(statement.clone()));
+    self.environment.replace(previous);
+    result
+  }
+
+  /// # 执行带尾调用的函数体
+  /// 先执行除最后一条外的语句；如果尾调用的目标满足 `is_self`，
+  /// 只计算参数并返回给调用方复用当前栈帧，否则正常执行最后一条语句
+  pub fn execute_tail_block(
+    &self,
+    statements: &Rc<Vec<Rc<Stmt>>>,
+    tail: &CallExpr,
+    environment: Environment,
+    is_self: impl Fn(&Callable) -> bool,
+  ) -> Result<Option<Vec<Object>>, SaturdayResult> {
+    let previous = self.environment.replace(Rc::new(RefCell::new(environment)));
+    let result = (|| {
+      let (last, init) = statements.split_last().unwrap();
+      for statement in init {
+        self.execute(statement.clone())?;
+      }
+
+      match self.evaluate(tail.callee.clone())? {
+        Object::Func(function) if is_self(&function) => {
+          let mut arguments = Vec::new();
+          for argument in tail.arguments.iter() {
+            arguments.push(self.evaluate(argument.clone())?);
+          }
+
+          self.check_arity(&tail.paren, function.func.deref(), arguments.len())?;
+          Ok(Some(arguments))
+        }
+        _ => self.execute(last.clone()).map(|_| None),
+      }
+    })();
+    self.environment.replace(previous);
+    result
+  }
+
+  fn check_arity(
+    &self,
+    paren: &Token,
+    callee: &dyn SaturdayCallable,
+    count: usize,
+  ) -> Result<(), SaturdayResult> {
+    let (min, max) = (callee.min_arity(), callee.arity());
+    if count < min || (count > max && !callee.is_variadic()) {
+      let expected = if callee.is_variadic() {
+        format!("at least {min}")
+      } else if min == max {
+        format!("{max}")
+      } else {
+        format!("{min} to {max}")
+      };
+
+      return Err(SaturdayResult::runtime_error(
+        paren,
+        &format!(
+          "Expected {expected} arguments but got {count} when calling '{}'.",
+          callee.name()
+        ),
+      ));
+    }
+
+    Ok(())
+  }
+
+  /// 替换 `clock()` 使用的时间来源
+  pub fn set_clock(&self, clock: Box<dyn Clock>) {
+    self.clock.replace(clock);
+  }
+
+  pub fn now(&self) -> Result<f64, SaturdayResult> {
+    self.clock.borrow().now()
+  }
+
+  /// 替换文件相关内置函数使用的文件系统，`None` 时调用它们会报错
+  pub fn set_file_system(&self, file_system: Option<Rc<dyn FileSystem>>) {
+    self.file_system.replace(file_system);
+  }
+
+  pub fn file_system(&self) -> Result<Rc<dyn FileSystem>, SaturdayResult> {
+    self
+      .file_system
+      .borrow()
+      .clone()
+      .ok_or_else(|| SaturdayResult::native_error("File system access is disabled."))
+  }
+
+  /// 设置步数限制并重新计数，`None` 表示不限制
+  pub fn set_step_limit(&self, limit: Option<u64>) {
+    self.step_limit.replace(limit);
+    self.steps.replace(0);
+  }
+
+  /// 设置 `and`、`or` 是否把结果转换为布尔值，短路求值不受影响
+  pub fn set_strict_logical(&self, strict: bool) {
+    self.strict_logical.replace(strict);
+  }
+
+  pub fn strict_logical(&self) -> bool {
+    *self.strict_logical.borrow()
+  }
+
+  /// 要求条件是布尔值，默认任何值都可以按真值判断
+  pub fn set_strict_conditions(&self, strict: bool) {
+    self.strict_conditions.replace(strict);
+  }
+
+  pub fn strict_conditions(&self) -> bool {
+    *self.strict_conditions.borrow()
+  }
+
+  /// 设置数字相等比较的容差，见 [`numbers_equal`]，默认精确比较
+  pub fn set_float_epsilon(&self, epsilon: Option<f64>) {
+    self.float_epsilon.replace(epsilon);
+  }
+
+  pub fn float_epsilon(&self) -> Option<f64> {
+    *self.float_epsilon.borrow()
+  }
+
+  /// 设置最大调用深度
+  pub fn set_max_call_depth(&self, depth: usize) {
+    self.max_call_depth.replace(depth);
+  }
+
+  /// 记录调用深度，无论调用成功与否都会恢复计数，内置函数的错误在 `paren` 处报告
+  pub(crate) fn guarded_call(
+    &self,
+    paren: &Token,
+    call: impl FnOnce() -> Result<Object, SaturdayResult>,
+  ) -> Result<Object, SaturdayResult> {
+    self.counted_call(call).map_err(|e| match e {
+      SaturdayResult::NativeError { message } => SaturdayResult::runtime_error(paren, &message),
+      e => e,
+    })
+  }
+
+  /// 超过调用深度时返回内置函数错误，由外层的 `guarded_call` 补上位置
+  fn counted_call(
+    &self,
+    call: impl FnOnce() -> Result<Object, SaturdayResult>,
+  ) -> Result<Object, SaturdayResult> {
+    if *self.call_depth.borrow() >= *self.max_call_depth.borrow() {
+      return Err(SaturdayResult::native_error("Maximum call depth exceeded."));
+    }
+
+    *self.call_depth.borrow_mut() += 1;
+    let result = call();
+    *self.call_depth.borrow_mut() -= 1;
+    result
+  }
+
+  /// 内置函数调用传入的函数，和调用表达式一样计入调用深度
+  pub fn call_value(
+    &self,
+    function: &Callable,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    self.counted_call(|| function.func.call(self, arguments))
+  }
+
+  /// # 转为字符串
+  /// 实例的类定义了 `to_string` 方法时调用它，否则使用默认的格式。
+  /// 列表和映射中的元素仍然使用默认格式
+  pub fn stringify(&self, value: &Object) -> Result<String, SaturdayResult> {
+    if let Object::Instance(instance) = value {
+      if let Some(method) = instance.class.find_method("to_string") {
+        let result = self.guarded_call(method.name_token(), || {
+          method.bind(Rc::clone(instance)).call(self, Vec::new())
+        })?;
+        return Ok(result.to_string());
+      }
+    }
+
+    Ok(value.to_string())
+  }
+
+  /// `if`、`while`、`do` 的条件。严格模式下必须是布尔值，否则按真值规则判断
+  fn condition(&self, keyword: &Token, condition: &Rc<Expr>) -> Result<bool, SaturdayResult> {
+    // 每次判断条件都计一步，空的循环体也能在关键字处停下
+    self.step(Some(keyword))?;
+    match self.evaluate(Rc::clone(condition))? {
+      Object::Bool(value) => Ok(value),
+      value if *self.strict_conditions.borrow() => Err(SaturdayResult::runtime_error(
+        keyword,
+        &format!("Condition must be a bool but got {}.", value.type_name()),
+      )),
+      value => Ok(self.is_truthy(&value)),
+    }
+  }
+
+  /// 任何不等于Nil和False的识别为true
+  pub fn is_truthy(&self, object: &Object) -> bool {
+    !matches!(object, Object::Nil | Object::Bool(false))
+  }
+
+  /// 依次执行语句，遇到第一个错误时停止并返回该错误
+  pub fn interpreter(&self, statements: &[Rc<Stmt>]) -> Result<(), SaturdayResult> {
+    for statement in statements {
+      self
+        .execute(statement.clone())
+        .map_err(Interpreter::uncaught)?;
+    }
+
+    Ok(())
+  }
+
+  /// 没有被捕获的 `throw` 到达顶层时作为运行时错误报告
+  fn uncaught(err: SaturdayResult) -> SaturdayResult {
+    match err {
+      SaturdayResult::Thrown { token, value } => {
+        SaturdayResult::runtime_error(&token, &format!("Uncaught exception '{}'.", value))
+      }
+      err => err,
+    }
+  }
+
+  /// 设置正在执行的脚本，`import` 的相对路径以它所在的目录为准
+  pub fn set_script_path(&self, path: &Path) {
+    let path = path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    self.importing.replace(vec![path]);
+  }
+
+  /// 相对于当前文件所在目录的绝对路径，没有当前文件时相对于工作目录
+  fn import_path(&self, path: &Token) -> Result<PathBuf, SaturdayResult> {
+    let relative = match &path.literal {
+      Some(Object::Str(relative)) => relative.clone(),
+      _ => path.lexeme.clone(),
+    };
+    let base = self
+      .importing
+      .borrow()
+      .last()
+      .and_then(|file| file.parent().map(Path::to_path_buf))
+      .unwrap_or_default();
+    self
+      .import_file_system(path)?
+      .canonicalize(&base.join(&*relative))
+      .map_err(|e| SaturdayResult::runtime_error(path, &format!("Can't import '{relative}': {e}.")))
+  }
+
+  /// 导入模块使用的文件系统，能力关闭或未安装时在导入语句处报错
+  fn import_file_system(&self, token: &Token) -> Result<Rc<dyn FileSystem>, SaturdayResult> {
+    match self.file_system.borrow().clone() {
+      Some(file_system) if self.capabilities.file_system => Ok(file_system),
+      _ => Err(SaturdayResult::runtime_error(
+        token,
+        "File system access is disabled.",
+      )),
+    }
+  }
+
+  /// 扫描、解析并在给定的全局环境中执行导入的文件
+  fn run_module(
+    &self,
+    source: &str,
+    globals: Rc<RefCell<Environment>>,
+  ) -> Result<(), SaturdayResult> {
+    set_source(source);
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let statements = Rc::new(Parser::new(tokens).parse()?);
+
+    let resolver = Resolver::new(self);
+    resolver.resolve(&statements)?;
+    if !resolver.success() {
+      return Err(SaturdayResult::system_error(
+        "Could not resolve the imported file.",
+      ));
+    }
+
+    let previous = self.environment.replace(globals);
+    let result = statements
+      .iter()
+      .try_for_each(|statement| self.execute(statement.clone()));
+    self.environment.replace(previous);
+    result
+  }
+
+  /// # 对源码中的单个表达式求值
+  /// 依次扫描、解析、解析作用域并求值，返回表达式的值
+  pub fn eval_str(&self, source: &str) -> Result<Object, SaturdayResult> {
+    set_source(source);
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens()?;
+    let expr = Rc::new(Parser::new(tokens).parse_expression()?);
+
+    let resolver = Resolver::new(self);
+    resolver.resolve_expr(Rc::clone(&expr))?;
+    if !resolver.success() {
+      return Err(SaturdayResult::system_error(
+        "Could not resolve the expression.",
+      ));
+    }
+
+    self.evaluate(expr).map_err(Interpreter::uncaught)
+  }
+
+  /// # 执行并返回结果
+  /// 与 `interpreter` 相同，但最后一条语句若是表达式语句，则返回它的值。
+  /// 供 REPL 回显表达式结果使用。
+  pub fn interpret_with_value(&self, statements: &[Rc<Stmt>]) -> Option<Object> {
+    let (last, rest) = statements.split_last()?;
+    self.interpreter(rest).ok()?;
+
+    match last.deref() {
+      Stmt::Expression(stmt) => self
+        .evaluate(stmt.expression.clone())
+        .map_err(Interpreter::uncaught)
+        .ok(),
+      _ => {
+        let _ = self.interpreter(std::slice::from_ref(last));
+        None
+      }
+    }
+  }
+
+  pub fn resolve(&self, expr: Rc<Expr>, depth: usize) {
+    self.locals.borrow_mut().insert(expr, depth);
+  }
+
+  pub fn look_up_variable(&self, name: &Token, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
     if let Some(distance) = self.locals.borrow().get(&expr) {
       self
         .environment
         .borrow()
         .borrow()
-        .get_at(*distance, &name.as_string())
+        .get_at(*distance, name.symbol())
+    } else {
+      self.look_up_global(name, expr)
+    }
+  }
+
+  /// 按解析结果给局部变量或全局变量赋值
+  fn assign_variable(
+    &self,
+    expr: &Rc<Expr>,
+    name: &Token,
+    value: Object,
+  ) -> Result<(), SaturdayResult> {
+    if let Some(distance) = self.locals.borrow().get(expr) {
+      self
+        .environment
+        .borrow()
+        .borrow_mut()
+        .assign_at(*distance, name, value)
     } else {
-      self.globals.borrow().get(name)
+      self.current_globals().borrow_mut().assign(name, value)
+    }
+  }
+
+  /// 当前代码所在文件的全局环境
+  fn current_globals(&self) -> Rc<RefCell<Environment>> {
+    Environment::globals_of(&self.environment.borrow())
+  }
+
+  /// 重新定义全局变量会覆盖原来的位置，只有 `reset` 替换全局环境时才需要清空缓存。
+  /// 模块中的函数在模块自己的全局环境中查找全局变量
+  fn look_up_global(&self, name: &Token, expr: Rc<Expr>) -> Result<Object, SaturdayResult> {
+    let globals = self.current_globals();
+    let globals = globals.borrow();
+    if let Some(&slot) = self.global_slots.borrow().get(&expr) {
+      return Ok(globals.get_slot(slot));
+    }
+
+    if let Some(slot) = globals.slot_of(name) {
+      self.global_slots.borrow_mut().insert(expr, slot);
+      return Ok(globals.get_slot(slot));
     }
+    globals.get(name)
+  }
+}
+
+impl Default for Interpreter {
+  fn default() -> Self {
+    Self::new()
   }
 }
 
 #[cfg(test)]
 mod tests {
   use super::*;
+  use crate::parser::Parser;
+  use crate::resolver::Resolver;
+  use crate::scanner::Scanner;
   use crate::token::Token;
 
-  fn make_literal(o: Object) -> Box<Expr> {
-    Box::new(Expr::Literal(LiteralExpr { value: Some(o) }))
+  /// 完整执行一段源码，返回是否执行成功
+  fn run_source(terp: &Interpreter, source: &str) -> bool {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(terp);
+    assert!(resolver.resolve(&statements).is_ok());
+    assert!(resolver.success());
+    terp.interpreter(&statements).is_ok()
   }
 
-  fn make_literal_string(s: &str) -> Box<Expr> {
-    make_literal(Object::Str(s.to_string()))
+  /// 解析并执行，返回运行时错误的文本
+  fn run_error(terp: &Interpreter, source: &str) -> String {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    let resolver = Resolver::new(terp);
+    assert!(resolver.resolve(&statements).is_ok());
+    assert!(resolver.success());
+    terp.interpreter(&statements).unwrap_err().to_string()
+  }
+
+  fn global(terp: &Interpreter, name: &str) -> Object {
+    let token = Token::new(TokenType::Identifier, name.to_string(), None, 0, 0);
+    terp.globals.borrow().get(&token).ok().unwrap()
+  }
+
+  fn make_literal(o: Object) -> Rc<Expr> {
+    Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: Some(o) })))
+  }
+
+  fn make_literal_string(s: &str) -> Rc<Expr> {
+    make_literal(Object::Str((*s).into()))
   }
 
   #[test]
   fn test_unary_minus() {
     let terp = Interpreter::new();
     let unary_expr = UnaryExpr {
-      operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
+      operator: Token::new(TokenType::Minus, "-".to_string(), None, 123, 0),
       right: make_literal(Object::Num(123.0)),
     };
-    let result = terp.visit_unary_expr(&unary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Unary(Rc::new(unary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(-123.0)));
   }
@@ -443,10 +1446,10 @@ mod tests {
   fn test_unary_not() {
     let terp = Interpreter::new();
     let unary_expr = UnaryExpr {
-      operator: Token::new(TokenType::Bang, "!".to_string(), None, 123),
+      operator: Token::new(TokenType::Bang, "!".to_string(), None, 123, 0),
       right: make_literal(Object::Bool(false)),
     };
-    let result = terp.visit_unary_expr(&unary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Unary(Rc::new(unary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(true)));
   }
@@ -456,10 +1459,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal(Object::Num(15.0)),
-      operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
+      operator: Token::new(TokenType::Minus, "-".to_string(), None, 123, 0),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(8.0)));
   }
@@ -469,10 +1472,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal(Object::Num(21.0)),
-      operator: Token::new(TokenType::Slash, "/".to_string(), None, 123),
+      operator: Token::new(TokenType::Slash, "/".to_string(), None, 123, 0),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(3.0)));
   }
@@ -482,10 +1485,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal(Object::Num(15.0)),
-      operator: Token::new(TokenType::Star, "*".to_string(), None, 123),
+      operator: Token::new(TokenType::Star, "*".to_string(), None, 123, 0),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(105.0)));
   }
@@ -495,10 +1498,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal(Object::Num(15.0)),
-      operator: Token::new(TokenType::Plus, "+".to_string(), None, 123),
+      operator: Token::new(TokenType::Plus, "+".to_string(), None, 123, 0),
       right: make_literal(Object::Num(7.0)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Num(22.0)));
   }
@@ -508,12 +1511,24 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal_string("hello, "),
-      operator: Token::new(TokenType::Plus, "+".to_string(), None, 123),
+      operator: Token::new(TokenType::Plus, "+".to_string(), None, 123, 0),
       right: make_literal_string("world!"),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
-    assert_eq!(result.ok(), Some(Object::Str("hello, world!".to_string())));
+    assert_eq!(result.ok(), Some(Object::Str("hello, world!".into())));
+  }
+
+  #[test]
+  fn test_string_literal_is_shared() {
+    let terp = Interpreter::new();
+    let literal = make_literal_string("hello");
+    let first = terp.evaluate(literal.clone());
+    let second = terp.evaluate(literal);
+    match (first, second) {
+      (Ok(Object::Str(first)), Ok(Object::Str(second))) => assert!(Rc::ptr_eq(&first, &second)),
+      other => panic!("expected two strings, got {other:?}"),
+    }
   }
 
   #[test]
@@ -521,10 +1536,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal(Object::Num(15.0)),
-      operator: Token::new(TokenType::Minus, "-".to_string(), None, 123),
+      operator: Token::new(TokenType::Minus, "-".to_string(), None, 123, 0),
       right: make_literal(Object::Bool(true)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_err());
   }
 
@@ -533,17 +1548,158 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal(Object::Num(15.0)),
-      operator: Token::new(TokenType::Greater, ">".to_string(), None, 123),
+      operator: Token::new(TokenType::Greater, ">".to_string(), None, 123, 0),
       right: make_literal(Object::Bool(true)),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_err());
   }
 
+  #[test]
+  fn test_illegal_operand_combinations() {
+    let terp = Interpreter::new();
+    for (source, message) in [
+      (
+        "1 - \"a\";",
+        "Operator '-' cannot be applied to num and str.",
+      ),
+      (
+        "\"a\" * 2;",
+        "Operator '*' cannot be applied to str and num.",
+      ),
+      (
+        "\"a\" - \"b\";",
+        "Operator '-' cannot be applied to str and str.",
+      ),
+      (
+        "true + false;",
+        "Operator '+' cannot be applied to bool and bool.",
+      ),
+      (
+        "nil + nil;",
+        "Operator '+' cannot be applied to nil and nil.",
+      ),
+      (
+        "clock / clock;",
+        "Operator '/' cannot be applied to function and function.",
+      ),
+      ("1 + nil;", "Operator '+' cannot be applied to num and nil."),
+      ("nil * 2;", "Operator '*' cannot be applied to nil and num."),
+      (
+        "3 > true;",
+        "Operator '>' cannot be applied to num and bool.",
+      ),
+      (
+        "\"a\" <= 1;",
+        "Operator '<=' cannot be applied to str and num.",
+      ),
+      (
+        "nil <= nil;",
+        "Operator '<=' cannot be applied to nil and nil.",
+      ),
+    ] {
+      let err = run_error(&terp, source);
+      assert!(err.ends_with(message), "{source}: {err}");
+    }
+  }
+
+  #[test]
+  fn test_strict_conditions() {
+    let terp = Interpreter::new();
+    let source = "
+      def taken = false;
+      if \"x\" { taken = true; }
+      def runs = 0;
+      while runs { runs = nil; }
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "taken"), Object::Bool(true));
+    assert_eq!(global(&terp, "runs"), Object::Nil);
+
+    terp.set_strict_conditions(true);
+    assert!(terp.strict_conditions());
+    assert!(run_source(
+      &terp,
+      "def n = 0; while n < 3 { n += 1; } if n == 3 { taken = 1; }"
+    ));
+    assert_eq!(global(&terp, "taken"), Object::Num(1.0));
+    for (source, message) in [
+      (
+        "if \"x\" {}",
+        "line 1:1 at 'if' Condition must be a bool but got str.",
+      ),
+      (
+        "while 1 {}",
+        "line 1:1 at 'while' Condition must be a bool but got num.",
+      ),
+      (
+        "do {} while nil;",
+        "line 1:7 at 'while' Condition must be a bool but got nil.",
+      ),
+      (
+        "for ; clock; {}",
+        "line 1:1 at 'for' Condition must be a bool but got function.",
+      ),
+    ] {
+      let err = run_error(&terp, source);
+      assert_eq!(err, message, "{source}");
+    }
+  }
+
+  #[test]
+  fn test_call_errors_name_the_callee() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "fun add(a, b) { return a + b; } class Point { init(x, y) {} } def n = 1;"
+    ));
+    for (source, message) in [
+      (
+        "add(1);",
+        "line 1:6 at ')' Expected 2 arguments but got 1 when calling 'add'.",
+      ),
+      (
+        "Point(1, 2, 3);",
+        "line 1:14 at ')' Expected 2 arguments but got 3 when calling 'Point'.",
+      ),
+      (
+        "clock(1);",
+        "line 1:8 at ')' Expected 0 arguments but got 1 when calling 'clock'.",
+      ),
+      (
+        "n();",
+        "line 1:3 at ')' Can only call function and classes, but 'n' is a num.",
+      ),
+      (
+        "\"s\"();",
+        "line 1:5 at ')' Can only call function and classes, but 's' is a str.",
+      ),
+    ] {
+      let err = run_error(&terp, source);
+      assert_eq!(err, message);
+    }
+  }
+
+  #[test]
+  fn test_float_epsilon() {
+    let terp = Interpreter::new();
+    let source = "def exact = 0.1 + 0.2 == 0.3; def differ = 0.1 + 0.2 != 0.3;";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "exact"), Object::Bool(false));
+    assert_eq!(global(&terp, "differ"), Object::Bool(true));
+
+    terp.set_float_epsilon(Some(1e-9));
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "exact"), Object::Bool(true));
+    assert_eq!(global(&terp, "differ"), Object::Bool(false));
+    assert!(run_source(&terp, "def far = 1 == 1.001;"));
+    assert_eq!(global(&terp, "far"), Object::Bool(false));
+  }
+
   #[test]
   fn test_equals() {
     run_comparison_test(
-      Token::new(TokenType::Equal, "==".to_string(), None, 123),
+      Token::new(TokenType::Equal, "==".to_string(), None, 123, 0),
       vec![false, true, false],
     );
   }
@@ -551,7 +1707,7 @@ mod tests {
   #[test]
   fn test_not_equals() {
     run_comparison_test(
-      Token::new(TokenType::BangEqual, "!=".to_string(), None, 123),
+      Token::new(TokenType::BangEqual, "!=".to_string(), None, 123, 0),
       vec![true, false, true],
     );
   }
@@ -561,10 +1717,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal_string("hello"),
-      operator: Token::new(TokenType::Equal, "==".to_string(), None, 123),
+      operator: Token::new(TokenType::Equal, "==".to_string(), None, 123, 0),
       right: make_literal_string("hellx"),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(false)));
   }
@@ -574,10 +1730,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal_string("world"),
-      operator: Token::new(TokenType::Equal, "==".to_string(), None, 123),
+      operator: Token::new(TokenType::Equal, "==".to_string(), None, 123, 0),
       right: make_literal_string("world"),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(true)));
   }
@@ -587,10 +1743,10 @@ mod tests {
     let terp = Interpreter::new();
     let binary_expr = BinaryExpr {
       left: make_literal(Object::Nil),
-      operator: Token::new(TokenType::Equal, "==".to_string(), None, 123),
+      operator: Token::new(TokenType::Equal, "==".to_string(), None, 123, 0),
       right: make_literal(Object::Nil),
     };
-    let result = terp.visit_binary_expr(&binary_expr);
+    let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
     assert!(result.is_ok());
     assert_eq!(result.ok(), Some(Object::Bool(true)));
   }
@@ -605,7 +1761,7 @@ mod tests {
         operator: tok.dup(),
         right: make_literal(Object::Num(15.0)),
       };
-      let result = terp.visit_binary_expr(&binary_expr);
+      let result = terp.evaluate(Rc::new(Expr::Binary(Rc::new(binary_expr))));
       assert!(result.is_ok());
       assert_eq!(
         result.ok(),
@@ -620,7 +1776,7 @@ mod tests {
   #[test]
   fn test_less_than() {
     run_comparison_test(
-      Token::new(TokenType::Less, "<".to_string(), None, 123),
+      Token::new(TokenType::Less, "<".to_string(), None, 123, 0),
       vec![true, false, false],
     );
   }
@@ -628,7 +1784,7 @@ mod tests {
   #[test]
   fn test_less_or_equal_to() {
     run_comparison_test(
-      Token::new(TokenType::LessEqual, "<=".to_string(), None, 123),
+      Token::new(TokenType::LessEqual, "<=".to_string(), None, 123, 0),
       vec![true, true, false],
     );
   }
@@ -636,7 +1792,7 @@ mod tests {
   #[test]
   fn test_greater_than() {
     run_comparison_test(
-      Token::new(TokenType::Greater, ">".to_string(), None, 123),
+      Token::new(TokenType::Greater, ">".to_string(), None, 123, 0),
       vec![false, false, true],
     );
   }
@@ -644,7 +1800,7 @@ mod tests {
   #[test]
   fn test_greater_or_equal_to() {
     run_comparison_test(
-      Token::new(TokenType::GreaterEqual, ">=".to_string(), None, 123),
+      Token::new(TokenType::GreaterEqual, ">=".to_string(), None, 123, 0),
       vec![false, true, true],
     );
   }
@@ -652,12 +1808,12 @@ mod tests {
   #[test]
   fn test_var_stmt_with_initializer() {
     let terp = Interpreter::new();
-    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123);
+    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123, 0);
     let def_stmt = DefStmt {
       name: name.dup(),
-      initializer: Some(*make_literal(Object::Num(23.0))),
+      initializer: Some(make_literal(Object::Num(23.0))),
     };
-    assert!(terp.visit_def_stmt(&def_stmt).is_ok());
+    assert!(terp.execute(Rc::new(Stmt::Def(Rc::new(def_stmt)))).is_ok());
     assert_eq!(
       terp.environment.borrow().borrow().get(&name).ok(),
       Some(Object::Num(23.0))
@@ -667,12 +1823,12 @@ mod tests {
   #[test]
   fn test_var_stmt_without_initializer() {
     let terp = Interpreter::new();
-    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123);
+    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123, 0);
     let def_stmt = DefStmt {
       name: name.dup(),
       initializer: None,
     };
-    assert!(terp.visit_def_stmt(&def_stmt).is_ok());
+    assert!(terp.execute(Rc::new(Stmt::Def(Rc::new(def_stmt)))).is_ok());
     assert_eq!(
       terp.environment.borrow().borrow().get(&name).ok(),
       Some(Object::Nil)
@@ -682,17 +1838,19 @@ mod tests {
   #[test]
   fn test_variable_expr() {
     let terp = Interpreter::new();
-    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123);
+    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123, 0);
     let def_stmt = DefStmt {
       name: name.dup(),
-      initializer: Some(*make_literal(Object::Num(23.0))),
+      initializer: Some(make_literal(Object::Num(23.0))),
     };
 
-    assert!(terp.visit_def_stmt(&def_stmt).is_ok());
+    assert!(terp.execute(Rc::new(Stmt::Def(Rc::new(def_stmt)))).is_ok());
 
     let def_expr = VariableExpr { name: name.dup() };
     assert_eq!(
-      terp.visit_variable_expr(&def_expr).ok(),
+      terp
+        .evaluate(Rc::new(Expr::Variable(Rc::new(def_expr))))
+        .ok(),
       Some(Object::Num(23.0))
     );
   }
@@ -700,8 +1858,1635 @@ mod tests {
   #[test]
   fn test_undefined_variable_expr() {
     let terp = Interpreter::new();
-    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123);
+    let name = Token::new(TokenType::Identifier, "foo".to_string(), None, 123, 0);
     let def_expr = VariableExpr { name: name.dup() };
-    assert!(terp.visit_variable_expr(&def_expr).is_err());
+    assert!(terp
+      .evaluate(Rc::new(Expr::Variable(Rc::new(def_expr))))
+      .is_err());
+  }
+
+  #[test]
+  fn test_default_parameter_values() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "fun greet(name, greeting = \"hi\") { return greeting + \" \" + name; }
+       def a = greet(\"bob\");
+       def b = greet(\"bob\", \"hello\");"
+    ));
+    assert_eq!(global(&terp, "a"), Object::Str("hi bob".into()));
+    assert_eq!(global(&terp, "b"), Object::Str("hello bob".into()));
+  }
+
+  #[test]
+  fn test_default_parameter_arity_range() {
+    let terp = Interpreter::new();
+    assert!(run_source(&terp, "fun f(a, b = 2) { return a + b; }"));
+    assert!(!run_source(&terp, "f();"));
+    assert!(!run_source(&terp, "f(1, 2, 3);"));
+  }
+
+  fn make_list(items: Vec<Object>) -> Object {
+    Object::list(items)
+  }
+
+  #[test]
+  fn test_rest_parameter_collects_trailing_arguments() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "fun collect(first, ...rest) { return rest; }
+       def none = collect(1);
+       def one = collect(1, 2);
+       def several = collect(1, 2, 3, 4);"
+    ));
+    assert_eq!(global(&terp, "none"), make_list(vec![]));
+    assert_eq!(global(&terp, "one"), make_list(vec![Object::Num(2.0)]));
+    assert_eq!(
+      global(&terp, "several"),
+      make_list(vec![Object::Num(2.0), Object::Num(3.0), Object::Num(4.0)])
+    );
+    assert!(!run_source(&terp, "collect();"));
+  }
+
+  #[test]
+  fn test_compound_assignment() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def x = 1; x += 5;
+       def y = 10; { y -= 2; y *= 3; y /= 4; }"
+    ));
+    assert_eq!(global(&terp, "x"), Object::Num(6.0));
+    assert_eq!(global(&terp, "y"), Object::Num(6.0));
+  }
+
+  #[test]
+  fn test_power() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def a = 2 ** 10; def b = 2 ** 3 ** 2; def c = -2 ** 2; def d = 2 ** -1;"
+    ));
+    assert_eq!(global(&terp, "a"), Object::Num(1024.0));
+    assert_eq!(global(&terp, "b"), Object::Num(512.0));
+    assert_eq!(global(&terp, "c"), Object::Num(-4.0));
+    assert_eq!(global(&terp, "d"), Object::Num(0.5));
+  }
+
+  #[test]
+  fn test_power_type_error() {
+    let terp = Interpreter::new();
+    assert!(!run_source(&terp, "2 ** true;"));
+    assert!(!run_source(&terp, "\"2\" ** 2;"));
+  }
+
+  #[test]
+  fn test_string_interpolation() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def name = \"bob\"; def age = 20;
+       def a = \"Hello ${name}, you are ${age + 1} years old\";
+       def b = \"${true} and ${nil}\";
+       def c = \"outer ${\"inner ${name}\"}!\";"
+    ));
+    assert_eq!(
+      global(&terp, "a"),
+      Object::Str("Hello bob, you are 21 years old".into())
+    );
+    assert_eq!(global(&terp, "b"), Object::Str("true and nil".into()));
+    assert_eq!(global(&terp, "c"), Object::Str("outer inner bob!".into()));
+  }
+
+  #[test]
+  fn test_unbounded_recursion_is_a_runtime_error() {
+    with_interpreter_stack(|| {
+      let terp = Interpreter::new();
+      terp.set_max_call_depth(INTERPRETER_STACK_MAX_CALL_DEPTH);
+      let err = run_error(&terp, "fun f(n) { return 1 + f(n + 1); } f(0);");
+      assert_eq!(err, "line 1:30 at ')' Maximum call depth exceeded.");
+      assert_eq!(*terp.call_depth.borrow(), 0);
+
+      // 出错后计数已恢复，可以继续正常调用
+      assert!(run_source(
+        &terp,
+        "fun g(n) { if n > 0 { return g(n - 1); } return n; } def r = g(50);"
+      ));
+      assert_eq!(global(&terp, "r"), Object::Num(0.0));
+
+      // 经过内置函数的递归同样计数
+      let source = format!(
+        "{LIST}
+        def m = fun (n) {{ return map(list(n), m); }};
+        m(1);"
+      );
+      let err = run_error(&terp, &source);
+      assert!(err.ends_with("Maximum call depth exceeded."), "{err}");
+      assert_eq!(*terp.call_depth.borrow(), 0);
+    });
+  }
+
+  #[test]
+  fn test_recursive_getters_hit_depth_limit() {
+    with_interpreter_stack(|| {
+      let terp = Interpreter::new();
+      terp.set_max_call_depth(INTERPRETER_STACK_MAX_CALL_DEPTH);
+      for source in [
+        "class A { get x { return this.x; } } print A().x;",
+        "class B { get y { return this.y; } } class C < B { get y { return super.y; } } print C().y;",
+        "class D { to_string() { return \"${this}\"; } } print D();",
+      ] {
+        let err = run_error(&terp, source);
+        assert!(err.ends_with("Maximum call depth exceeded."), "{err}");
+        assert_eq!(*terp.call_depth.borrow(), 0);
+      }
+    });
+  }
+
+  #[test]
+  fn test_default_depth_limit_fits_an_ordinary_thread() {
+    let terp = Interpreter::new();
+    let err = run_error(&terp, "fun f(n) { return 1 + f(n + 1); } f(0);");
+    assert_eq!(err, "line 1:30 at ')' Maximum call depth exceeded.");
+    assert_eq!(*terp.call_depth.borrow(), 0);
+
+    let source = format!(
+      "{LIST}
+      def m = fun (n) {{ return map(list(n), m); }};
+      m(1);"
+    );
+    let err = run_error(&terp, &source);
+    assert!(err.ends_with("Maximum call depth exceeded."), "{err}");
+    let err = run_error(&terp, "class E { get x { return this.x; } } print E().x;");
+    assert!(err.ends_with("Maximum call depth exceeded."), "{err}");
+  }
+
+  #[test]
+  fn test_tail_call_does_not_grow_the_stack() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "fun count(n, acc) { if n == 0 { return acc; } return count(n - 1, acc + 1); }
+       def r = count(100000, 0);"
+    ));
+    assert_eq!(global(&terp, "r"), Object::Num(100000.0));
+  }
+
+  #[test]
+  fn test_non_tail_recursion_hits_depth_limit() {
+    let terp = Interpreter::new();
+    terp.set_max_call_depth(100);
+    assert!(!run_source(
+      &terp,
+      "fun sum(n) { if n == 0 { return 0; } return n + sum(n - 1); } sum(200);"
+    ));
+    assert!(run_source(&terp, "def r = sum(50);"));
+    assert_eq!(global(&terp, "r"), Object::Num(1275.0));
+  }
+
+  #[test]
+  fn test_do_while_runs_body_once() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def n = 0; do { n = n + 1; } while false;
+       def m = 0; do { m += 1; if m == 3 { break; } } while true;
+       def k = 0; do { k += 1; } while k < 5;"
+    ));
+    assert_eq!(global(&terp, "n"), Object::Num(1.0));
+    assert_eq!(global(&terp, "m"), Object::Num(3.0));
+    assert_eq!(global(&terp, "k"), Object::Num(5.0));
+  }
+
+  #[test]
+  fn test_class_with_this_super_and_lambda() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "class Shape {
+         init(name) { this.name = name; }
+         describe() { return \"shape \" + this.name; }
+       }
+       class Square < Shape {
+         init(side) { super.init(\"square\"); this.side = side; }
+         describe() { return super.describe() + \" of side ${this.side}\"; }
+         area() { return this.side * this.side; }
+         scaler() { return fun (factor) { return this.area() * factor; }; }
+       }
+       def square = Square(3);
+       def text = square.describe();
+       def scaled = square.scaler()(2);
+       square.side = 4;
+       def area = square.area();
+       def twice = fun (x) { return x * 2; };
+       def four = twice(2);"
+    ));
+    assert_eq!(
+      global(&terp, "text"),
+      Object::Str("shape square of side 3".into())
+    );
+    assert_eq!(global(&terp, "scaled"), Object::Num(18.0));
+    assert_eq!(global(&terp, "area"), Object::Num(16.0));
+    assert_eq!(global(&terp, "four"), Object::Num(4.0));
+    assert!(!run_source(
+      &terp,
+      "def NotAClass = 1; class Broken < NotAClass {}"
+    ));
+  }
+
+  #[test]
+  fn test_repeat() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def zero = 0; repeat 0 { zero += 1; }
+       def three = 0; repeat 1 + 2 { three += 1; }
+       def stopped = 0; repeat 10 { stopped += 1; if stopped == 4 { break; } }
+       def low = 0; def i = 0;
+       repeat 6 { i += 1; if i > 3 { continue; } low += 1; }"
+    ));
+    assert_eq!(global(&terp, "zero"), Object::Num(0.0));
+    assert_eq!(global(&terp, "three"), Object::Num(3.0));
+    assert_eq!(global(&terp, "stopped"), Object::Num(4.0));
+    assert_eq!(global(&terp, "low"), Object::Num(3.0));
+    assert_eq!(global(&terp, "i"), Object::Num(6.0));
+
+    for source in ["repeat -1 { }", "repeat \"3\" { }"] {
+      assert_eq!(
+        run_error(&terp, source),
+        "line 1:1 at 'repeat' Repeat count must be a non-negative number."
+      );
+    }
+  }
+
+  #[test]
+  fn test_undef() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def x = 1; fun read() { return x; } read();"
+    ));
+    assert!(run_source(&terp, "undef x;"));
+    assert!(!run_source(&terp, "x;"));
+    assert!(!run_source(&terp, "read();"));
+    assert!(!run_source(&terp, "undef x;"));
+    assert!(run_source(&terp, "def x = 2; def y = read();"));
+    assert_eq!(global(&terp, "y"), Object::Num(2.0));
+  }
+
+  #[test]
+  fn test_global_declaration() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def x = 1;
+       fun shadowed() { def x = 0; fun set() { x = 5; } set(); return x; }
+       def local = shadowed();
+       fun explicit() {
+         def x = 0;
+         fun set() { global x; x = x + 10; }
+         set();
+         return x;
+       }
+       def untouched = explicit();"
+    ));
+    assert_eq!(global(&terp, "local"), Object::Num(5.0));
+    assert_eq!(global(&terp, "untouched"), Object::Num(0.0));
+    assert_eq!(global(&terp, "x"), Object::Num(11.0));
+
+    assert!(run_source(&terp, "fun bump() { missing = 1; }"));
+    assert!(!run_source(&terp, "bump();"));
+    assert!(run_source(
+      &terp,
+      "fun later() { global y; y = 3; } def y = 0; later();"
+    ));
+    assert_eq!(global(&terp, "y"), Object::Num(3.0));
+  }
+
+  #[test]
+  fn test_for_each_over_list() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "fun list(...items) { return items; }
+       def total = 0;
+       for x in list(1, 2, 3) { total += x; }
+       def count = 0;
+       for x in list() { count += 1; }
+       def seen = 0;
+       for x in list(1, 2, 3) { if x == 2 { break; } seen = x; }"
+    ));
+    assert_eq!(global(&terp, "total"), Object::Num(6.0));
+    assert_eq!(global(&terp, "count"), Object::Num(0.0));
+    assert_eq!(global(&terp, "seen"), Object::Num(1.0));
+  }
+
+  #[test]
+  fn test_for_each_over_non_list_is_an_error() {
+    let terp = Interpreter::new();
+    assert!(!run_source(&terp, "for x in 5 { print x; }"));
+  }
+
+  #[test]
+  fn test_const_declaration() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "const limit = 10; def doubled = limit * 2;"
+    ));
+    assert_eq!(global(&terp, "limit"), Object::Num(10.0));
+    assert_eq!(global(&terp, "doubled"), Object::Num(20.0));
+
+    assert!(!run_source(&terp, "limit = 11;"));
+    assert_eq!(global(&terp, "limit"), Object::Num(10.0));
+  }
+
+  #[test]
+  fn test_interpret_with_value() {
+    let terp = Interpreter::new();
+    let print = |expr| Rc::new(Stmt::Print(Rc::new(PrintStmt { expression: expr })));
+    let expression = Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
+      expression: make_literal(Object::Num(12.0)),
+    })));
+
+    assert_eq!(
+      terp.interpret_with_value(&[expression]),
+      Some(Object::Num(12.0))
+    );
+    assert_eq!(
+      terp.interpret_with_value(&[print(make_literal(Object::Nil))]),
+      None
+    );
+  }
+
+  #[test]
+  fn test_reset_clears_definitions() {
+    let terp = Interpreter::new();
+    assert!(run_source(&terp, "def a = 1;"));
+    terp.reset();
+
+    let names = |terp: &Interpreter| -> Vec<String> {
+      terp
+        .globals
+        .borrow()
+        .bindings()
+        .into_iter()
+        .map(|(name, _)| name)
+        .collect()
+    };
+    assert!(names(&terp).contains(&"clock".to_string()));
+    assert_eq!(names(&terp), names(&Interpreter::new()));
+  }
+
+  #[test]
+  fn test_interpreter_returns_runtime_error() {
+    let terp = Interpreter::new();
+    let mut scanner = Scanner::new("def a = 1;\na.field;".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Parser::new(tokens).parse().ok().unwrap();
+
+    match terp.interpreter(&statements) {
+      Err(SaturdayResult::RuntimeError { token, message }) => {
+        assert_eq!(token.as_string(), "field");
+        assert_eq!(token.line, 2);
+        assert_eq!(message, "Only instances have properties");
+      }
+      _ => panic!("expected a runtime error"),
+    }
+  }
+
+  #[test]
+  fn test_throw_is_caught() {
+    let terp = Interpreter::new();
+    assert!(run_source(
+      &terp,
+      "def caught = nil; try { throw \"boom\"; caught = 1; } catch (e) { caught = e; }"
+    ));
+    assert_eq!(global(&terp, "caught"), Object::Str("boom".into()));
+  }
+
+  #[test]
+  fn test_throw_propagates_out_of_nested_calls() {
+    let terp = Interpreter::new();
+    let source = "
+      fun inner(n) { if n > 2 { throw n * 10; } return n; }
+      fun outer(n) { return inner(n) + 1; }
+      def before = nil; def caught = nil;
+      try { before = outer(1); outer(3); } catch e { caught = e; }
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "before"), Object::Num(2.0));
+    assert_eq!(global(&terp, "caught"), Object::Num(30.0));
+    assert_eq!(*terp.call_depth.borrow(), 0);
+  }
+
+  #[test]
+  fn test_uncaught_throw_and_runtime_errors_are_not_caught() {
+    let terp = Interpreter::new();
+    assert!(!run_source(&terp, "throw 1;"));
+    assert!(!run_source(
+      &terp,
+      "try { missing; } catch (e) { print e; }"
+    ));
+  }
+
+  #[test]
+  fn test_nil_coalesce() {
+    let terp = Interpreter::new();
+    let source = "
+      def calls = 0;
+      fun fallback() { calls = calls + 1; return 5; }
+      def a = nil ?? fallback();
+      def b = false ?? fallback();
+      def c = 0 ?? fallback();
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "a"), Object::Num(5.0));
+    assert_eq!(global(&terp, "b"), Object::Bool(false));
+    assert_eq!(global(&terp, "c"), Object::Num(0.0));
+    assert_eq!(global(&terp, "calls"), Object::Num(1.0));
+  }
+
+  #[test]
+  fn test_print_goes_to_writer() {
+    let buffer = SharedBuffer::default();
+    let terp = Interpreter::with_writer(Box::new(buffer.clone()));
+    assert!(run_source(
+      &terp,
+      "print \"hi\"; def echoed = write(\"a\") + write(\"b\");"
+    ));
+    assert_eq!(buffer.contents(), "hi\nab");
+    assert_eq!(global(&terp, "echoed"), Object::Str("ab".into()));
+  }
+
+  #[test]
+  fn test_output_is_captured_in_order() {
+    let buffer = SharedBuffer::default();
+    let terp = Interpreter::with_writer(Box::new(buffer.clone()));
+    assert!(run_source(
+      &terp,
+      "print 1; for def i = 0; i < 2; i = i + 1 { print i * 10; } print \"done\";"
+    ));
+    assert_eq!(buffer.contents(), "1\n0\n10\ndone\n");
+  }
+
+  #[test]
+  fn test_step_limit_stops_infinite_loop() {
+    let terp = Interpreter::new();
+    terp.set_step_limit(Some(1000));
+    assert_eq!(
+      run_error(&terp, "while true {}"),
+      "line 1:1 at 'while' Execution step limit exceeded."
+    );
+    assert_eq!(*terp.steps.borrow(), 1000);
+    terp.set_step_limit(Some(1000));
+    assert_eq!(
+      run_error(&terp, "fun f() {\n  return f();\n}\nf();"),
+      "line 2:10 at 'f' Execution step limit exceeded."
+    );
+
+    terp.set_step_limit(None);
+    assert!(run_source(
+      &terp,
+      "def i = 0; while i < 2000 { i = i + 1; }"
+    ));
+  }
+
+  #[test]
+  fn test_clock_uses_installed_source() {
+    let terp = Interpreter::new();
+    terp.set_clock(Box::new(|| 1234.0));
+    assert!(run_source(&terp, "def now = clock();"));
+    assert_eq!(global(&terp, "now"), Object::Num(1234.0));
+  }
+
+  /// 内存中的文件系统
+  #[derive(Default)]
+  struct FakeFileSystem(RefCell<HashMap<String, String>>);
+
+  impl FileSystem for FakeFileSystem {
+    fn read(&self, path: &str) -> std::io::Result<String> {
+      self
+        .0
+        .borrow()
+        .get(path)
+        .cloned()
+        .ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))
+    }
+
+    fn write(&self, path: &str, contents: &str) -> std::io::Result<()> {
+      if path.is_empty() {
+        return Err(std::io::Error::from(std::io::ErrorKind::InvalidInput));
+      }
+      self
+        .0
+        .borrow_mut()
+        .insert(path.to_string(), contents.to_string());
+      Ok(())
+    }
+  }
+
+  #[test]
+  fn test_read_and_write_file() {
+    let path = std::env::temp_dir().join(format!("saturday_file_{}.txt", std::process::id()));
+    let path = path.display().to_string().replace('\\', "/");
+    let terp = Interpreter::new();
+    let source = format!(
+      "def wrote = write_file(\"{path}\", \"line\\n\");
+       def contents = read_file(\"{path}\");
+       def missing = read_file(\"{path}.missing\");"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(global(&terp, "wrote"), Object::Bool(true));
+    assert_eq!(global(&terp, "contents"), Object::Str("line\n".into()));
+    assert_eq!(global(&terp, "missing"), Object::Nil);
+    std::fs::remove_file(&path).unwrap();
+
+    let fake = Rc::new(FakeFileSystem::default());
+    terp.set_file_system(Some(fake.clone()));
+    assert!(run_source(
+      &terp,
+      "def stored = write_file(\"a\", \"b\"); def failed = write_file(\"\", \"b\");
+       def read_back = read_file(\"a\");"
+    ));
+    assert_eq!(global(&terp, "stored"), Object::Bool(true));
+    assert_eq!(global(&terp, "failed"), Object::Bool(false));
+    assert_eq!(global(&terp, "read_back"), Object::Str("b".into()));
+    assert_eq!(fake.0.borrow().get("a").map(String::as_str), Some("b"));
+  }
+
+  #[test]
+  fn test_sandboxed_capabilities() {
+    let terp = Interpreter::with_capabilities(Capabilities::sandboxed());
+    assert!(!run_source(&terp, "read_file(\"a\");"));
+    assert!(!run_source(&terp, "clock();"));
+    assert!(run_source(&terp, "def up = upper(\"a\");"));
+    terp.reset();
+    assert!(!run_source(&terp, "write_file;"));
+
+    let terp = Interpreter::with_capabilities(Capabilities {
+      clock: false,
+      ..Capabilities::default()
+    });
+    terp.set_file_system(Some(Rc::new(FakeFileSystem::default())));
+    assert!(run_source(&terp, "def missing = read_file(\"a\");"));
+    assert_eq!(global(&terp, "missing"), Object::Nil);
+    assert!(!run_source(&terp, "clock;"));
+  }
+
+  #[test]
+  fn test_sandboxed_import() {
+    let fake = Rc::new(FakeFileSystem::default());
+    fake
+      .0
+      .borrow_mut()
+      .insert("secret.sd".to_string(), "def secret = 1;".to_string());
+    let terp = Interpreter::with_capabilities(Capabilities::sandboxed());
+    terp.set_file_system(Some(fake));
+    let err = run_error(&terp, "import \"secret.sd\" as m; print m.secret;");
+    assert_eq!(
+      err,
+      "line 1:8 at '\"secret.sd\"' File system access is disabled."
+    );
+    assert!(!run_source(&terp, "import \"secret.sd\";"));
+  }
+
+  #[test]
+  fn test_file_access_can_be_disabled() {
+    let terp = Interpreter::new();
+    terp.set_file_system(None);
+    for source in ["read_file(\"a\");", "write_file(\"a\", \"b\");"] {
+      let err = run_error(&terp, source);
+      assert!(err.ends_with("File system access is disabled."), "{err}");
+    }
+  }
+
+  #[test]
+  fn test_compare() {
+    let terp = Interpreter::new();
+    let source = "
+      def nums = compare(1, 2);
+      def same = compare(2, 2);
+      def strs = compare(\"b\", \"a\");
+      def less = \"apple\" < \"banana\";
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "nums"), Object::Num(-1.0));
+    assert_eq!(global(&terp, "same"), Object::Num(0.0));
+    assert_eq!(global(&terp, "strs"), Object::Num(1.0));
+    assert_eq!(global(&terp, "less"), Object::Bool(true));
+
+    assert!(!run_source(&terp, "compare(1, \"1\");"));
+    assert!(!run_source(&terp, "compare(nil, nil);"));
+    assert!(!run_source(&terp, "1 < \"2\";"));
+  }
+
+  const LIST: &str = "fun list(...items) { return items; }";
+
+  #[test]
+  fn test_push_and_pop() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      def items = list(1);
+      push(items, 2);
+      def last = pop(items);
+      def first = pop(items);
+      def empty = pop(items);"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(global(&terp, "last"), Object::Num(2.0));
+    assert_eq!(global(&terp, "first"), Object::Num(1.0));
+    assert_eq!(global(&terp, "empty"), Object::Nil);
+    assert_eq!(global(&terp, "items"), make_list(vec![]));
+  }
+
+  #[test]
+  fn test_map_and_filter() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      fun double(n) {{ return n * 2; }}
+      fun even(n) {{ if n == 0 {{ return true; }} if n == 1 {{ return false; }} return even(n - 2); }}
+      def numbers = list(1, 2, 3, 4);
+      def doubled = map(numbers, double);
+      def evens = filter(numbers, even);"
+    );
+    assert!(run_source(&terp, &source));
+    let nums = |values: &[f64]| make_list(values.iter().map(|n| Object::Num(*n)).collect());
+    assert_eq!(global(&terp, "doubled"), nums(&[2.0, 4.0, 6.0, 8.0]));
+    assert_eq!(global(&terp, "evens"), nums(&[2.0, 4.0]));
+    assert_eq!(global(&terp, "numbers"), nums(&[1.0, 2.0, 3.0, 4.0]));
+
+    assert!(!run_source(&terp, "map(1, double);"));
+    assert!(!run_source(&terp, "map(numbers, 1);"));
+  }
+
+  #[test]
+  fn test_aggregate_natives() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      fun add(acc, n) {{ return acc + n; }}
+      def numbers = list(3, 1, 4, 1, 5);
+      def words = list(\"pear\", \"apple\", \"fig\");
+      def empty = list();
+      def total = sum(numbers);
+      def product = reduce(numbers, fun (acc, n) {{ return acc * n; }}, 1);
+      def joined = reduce(words, add, \"\");
+      def smallest = min_of(numbers);
+      def largest = max_of(numbers);
+      def first_word = min_of(words);
+      def last_word = max_of(words);
+      def empty_sum = sum(empty);
+      def empty_reduce = reduce(empty, add, 7);
+      def empty_min = min_of(empty);
+      def empty_max = max_of(empty);"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(global(&terp, "total"), Object::Num(14.0));
+    assert_eq!(global(&terp, "product"), Object::Num(60.0));
+    assert_eq!(global(&terp, "joined"), Object::Str("pearapplefig".into()));
+    assert_eq!(global(&terp, "smallest"), Object::Num(1.0));
+    assert_eq!(global(&terp, "largest"), Object::Num(5.0));
+    assert_eq!(global(&terp, "first_word"), Object::Str("apple".into()));
+    assert_eq!(global(&terp, "last_word"), Object::Str("pear".into()));
+    assert_eq!(global(&terp, "empty_sum"), Object::Num(0.0));
+    assert_eq!(global(&terp, "empty_reduce"), Object::Num(7.0));
+    assert_eq!(global(&terp, "empty_min"), Object::Nil);
+    assert_eq!(global(&terp, "empty_max"), Object::Nil);
+
+    assert!(!run_source(&terp, "sum(words);"));
+    assert!(!run_source(&terp, "sum(list(1, \"2\"));"));
+    assert!(!run_source(&terp, "min_of(list(1, \"a\"));"));
+    assert!(!run_source(&terp, "max_of(list(true, 2));"));
+    assert!(!run_source(&terp, "reduce(1, add, 0);"));
+  }
+
+  #[test]
+  fn test_sort_natives() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      class Pair {{ init(key, name) {{ this.key = key; this.name = name; }} }}
+      def numbers = list(3, -1, 2.5, 10, 2.5, 0);
+      def same = sort(numbers) == list(-1, 0, 2.5, 2.5, 3, 10);
+      def shown = \"${{numbers}}\";
+      def words = \"${{sort(list(\"pear\", \"Apple\", \"fig\", \"apple\"))}}\";
+      def descending = \"${{sort_by(list(1, 3, 2), fun (a, b) {{ return b - a; }})}}\";
+      def pairs = list(Pair(2, \"a\"), Pair(1, \"b\"), Pair(2, \"c\"), Pair(1, \"d\"));
+      sort_by(pairs, fun (x, y) {{ return x.key - y.key; }});
+      def names = \"\";
+      for p in pairs {{ names += p.name; }}
+      def empty = \"${{sort(list())}}\";"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(global(&terp, "same"), Object::Bool(true));
+    assert_eq!(
+      global(&terp, "shown"),
+      Object::Str("[-1, 0, 2.5, 2.5, 3, 10]".into())
+    );
+    assert_eq!(
+      global(&terp, "words"),
+      Object::Str("[Apple, apple, fig, pear]".into())
+    );
+    assert_eq!(global(&terp, "descending"), Object::Str("[3, 2, 1]".into()));
+    assert_eq!(global(&terp, "names"), Object::Str("bdac".into()));
+    assert_eq!(global(&terp, "empty"), Object::Str("[]".into()));
+
+    assert!(!run_source(&terp, "sort(list(1, \"a\"));"));
+    assert!(!run_source(&terp, "sort(freeze(list(2, 1)));"));
+    assert!(run_source(&terp, "def calls = 0;"));
+    assert!(!run_source(
+      &terp,
+      "sort_by(freeze(list(2, 1)), fun (a, b) { calls += 1; return a - b; });"
+    ));
+    assert_eq!(global(&terp, "calls"), Object::Num(0.0));
+    assert!(!run_source(
+      &terp,
+      "sort_by(list(2, 1), fun (a, b) { return true; });"
+    ));
+  }
+
+  #[test]
+  fn test_string_natives() {
+    let terp = Interpreter::new();
+    let source = "
+      def up = upper(\"straße\");
+      def low = lower(\"ÀB\");
+      def trimmed = trim(\"  hi \\t\");
+      def blank = upper(\"\");
+      def parts = split(\"a::b::\", \"::\");
+      def chars = split(\"ab\", \"\");
+      def none = split(\"\", \",\");
+    ";
+    assert!(run_source(&terp, source));
+    let strs =
+      |values: &[&str]| make_list(values.iter().map(|s| Object::Str((*s).into())).collect());
+    assert_eq!(global(&terp, "up"), Object::Str("STRASSE".into()));
+    assert_eq!(global(&terp, "low"), Object::Str("àb".into()));
+    assert_eq!(global(&terp, "trimmed"), Object::Str("hi".into()));
+    assert_eq!(global(&terp, "blank"), Object::Str("".into()));
+    assert_eq!(global(&terp, "parts"), strs(&["a", "b", ""]));
+    assert_eq!(global(&terp, "chars"), strs(&["a", "b"]));
+    assert_eq!(global(&terp, "none"), strs(&[""]));
+
+    assert!(!run_source(&terp, "upper(1);"));
+    assert!(!run_source(&terp, "split(\"a\", nil);"));
+  }
+
+  #[test]
+  fn test_chr_and_ord() {
+    let terp = Interpreter::new();
+    let source = "
+      def a = ord(\"abc\");
+      def b = chr(98);
+      def snowman = ord(\"☃\");
+      def round_trip = chr(ord(\"é\"));
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "a"), Object::Num(97.0));
+    assert_eq!(global(&terp, "b"), Object::Str("b".into()));
+    assert_eq!(global(&terp, "snowman"), Object::Num(9731.0));
+    assert_eq!(global(&terp, "round_trip"), Object::Str("é".into()));
+
+    for source in [
+      "chr(55296);",
+      "chr(1114112);",
+      "chr(-1);",
+      "chr(1.5);",
+      "ord(\"\");",
+    ] {
+      assert!(!run_source(&terp, source), "{source}");
+    }
+  }
+
+  #[test]
+  fn test_format() {
+    let terp = Interpreter::new();
+    let source = "
+      def pi = format(3.14159, \".2\");
+      def rounded = format(2.718, \".2\");
+      def carried = format(1.999, \".2\");
+      def whole = format(7.6, \".0\");
+      def padded = format(3.14159, \"8.3\");
+      def left = format(\"ab\", \"5\");
+      def right = format(\"ab\", \">5\");
+      def centered = format(\"ab\", \"^6\");
+      def wide = format(\"too long\", \"3\");
+      def plain = format(true, \"\");
+    ";
+    assert!(run_source(&terp, source));
+    for (name, expected) in [
+      ("pi", "3.14"),
+      ("rounded", "2.72"),
+      ("carried", "2.00"),
+      ("whole", "8"),
+      ("padded", "   3.142"),
+      ("left", "ab   "),
+      ("right", "   ab"),
+      ("centered", "  ab  "),
+      ("wide", "too long"),
+      ("plain", "true"),
+    ] {
+      assert_eq!(global(&terp, name), Object::Str(expected.into()), "{name}");
+    }
+
+    for source in [
+      "format(1, \".x\");",
+      "format(1, \".\");",
+      "format(1, \"+5\");",
+      "format(1, \"5.2.1\");",
+      "format(1, \"99999999\");",
+      "format(\"ab\", \".2\");",
+      "format(1, 2);",
+    ] {
+      assert!(!run_source(&terp, source), "{source}");
+    }
+  }
+
+  #[test]
+  fn test_bytes_and_base64() {
+    let terp = Interpreter::new();
+    let source = "
+      def bytes = to_bytes(\"héllo\");
+      def shown = str(bytes);
+      def encoded = base64_encode(bytes);
+      def decoded = base64_decode(encoded);
+      def same = decoded == bytes;
+      def text = from_bytes(decoded);
+      def empty = base64_encode(to_bytes(\"\"));
+      def one = base64_encode(to_bytes(\"a\"));
+      def two = base64_encode(to_bytes(\"ab\"));
+      def long = str(to_bytes(\"0123456789abcdefg\"));
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(
+      global(&terp, "bytes"),
+      Object::Bytes(Rc::new("héllo".as_bytes().to_vec()))
+    );
+    assert_eq!(
+      global(&terp, "shown"),
+      Object::Str("<bytes 68c3a96c6c6f>".into())
+    );
+    assert_eq!(global(&terp, "encoded"), Object::Str("aMOpbGxv".into()));
+    assert_eq!(global(&terp, "same"), Object::Bool(true));
+    assert_eq!(global(&terp, "text"), Object::Str("héllo".into()));
+    assert_eq!(global(&terp, "empty"), Object::Str("".into()));
+    assert_eq!(global(&terp, "one"), Object::Str("YQ==".into()));
+    assert_eq!(global(&terp, "two"), Object::Str("YWI=".into()));
+    assert_eq!(
+      global(&terp, "long"),
+      Object::Str("<bytes 30313233343536373839616263646566... (17 bytes)>".into())
+    );
+
+    for source in [
+      "base64_decode(\"abc\");",
+      "base64_decode(\"ab!=\");",
+      "base64_decode(\"a===\");",
+      "base64_decode(\"YQ==YQ==\");",
+      "from_bytes(base64_decode(\"/w==\"));",
+      "base64_encode(\"text\");",
+      "to_bytes(1);",
+    ] {
+      assert!(!run_source(&terp, source), "{source}");
+    }
+  }
+
+  #[test]
+  fn test_getter_is_read_as_field() {
+    let terp = Interpreter::new();
+    let source = "
+      def reads = 0;
+      class Counter { get next { reads = reads + 1; return reads * 10; } }
+      def counter = Counter();
+      def first = counter.next;
+      def second = counter.next;
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "first"), Object::Num(10.0));
+    assert_eq!(global(&terp, "second"), Object::Num(20.0));
+  }
+
+  #[test]
+  fn test_static_method_called_on_class() {
+    let terp = Interpreter::new();
+    let source = "
+      class Thing { static make(size) { return size * 2; } }
+      def made = Thing.make(21);
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "made"), Object::Num(42.0));
+    assert!(!run_source(&terp, "Thing.missing();"));
+    assert!(!run_source(&terp, "Thing().make(1);"));
+  }
+
+  #[test]
+  fn test_property_read_write_and_chain() {
+    let terp = Interpreter::new();
+    let source = "
+      class Node {}
+      fun build() {
+        def a = Node();
+        a.b = Node();
+        a.b.c = 3;
+        a.b.c += 4;
+        return a;
+      }
+      def root = build();
+      def value = root.b.c;
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "value"), Object::Num(7.0));
+    assert!(!run_source(&terp, "root.missing;"));
+    assert!(!run_source(&terp, "value.field = 1;"));
+  }
+
+  #[test]
+  fn test_compound_property_assignment_evaluates_object_once() {
+    let terp = Interpreter::new();
+    let source = "
+      class Counter {}
+      def counter = Counter();
+      counter.count = 10;
+      def made = 0;
+      fun make() { made += 1; return counter; }
+      def result = make().count += 5;
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "made"), Object::Num(1.0));
+    assert_eq!(global(&terp, "result"), Object::Num(15.0));
+    assert_eq!(
+      run_error(&terp, "make().count -= \"a\";"),
+      "line 1:14 at '-' Operator '-' cannot be applied to num and str."
+    );
+    assert_eq!(global(&terp, "made"), Object::Num(2.0));
+  }
+
+  #[test]
+  fn test_compound_property_assignment_reads_field_before_value() {
+    let terp = Interpreter::new();
+    let source = "
+      class C {}
+      def c = C();
+      fun bump() { c.n = 100; return 1; }
+      c.n = 1;
+      c.n += bump();
+      def field = c.n;
+      def v = 1;
+      fun bump_v() { v = 100; return 1; }
+      v += bump_v();
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "field"), Object::Num(2.0));
+    assert_eq!(global(&terp, "v"), Object::Num(2.0));
+  }
+
+  #[test]
+  fn test_initializer_returns_instance() {
+    let terp = Interpreter::new();
+    let source = "
+      def seen = nil;
+      class Point { init(x) { seen = x; return; } }
+      def point = Point(3);
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "seen"), Object::Num(3.0));
+    assert!(matches!(global(&terp, "point"), Object::Instance(_)));
+    assert!(!run_source(&terp, "Point();"));
+  }
+
+  #[test]
+  fn test_instance_equality() {
+    let terp = Interpreter::new();
+    let source = "
+      class Point {}
+      fun point(x) { def p = Point(); p.x = x; return p; }
+      def a = point(1);
+      def alias = a;
+      def same = a == alias;
+      def fresh = a == point(1);
+      def differs = a != point(1);
+      def equal = equals(a, point(1));
+      def unequal = equals(a, point(2));
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "same"), Object::Bool(true));
+    assert_eq!(global(&terp, "fresh"), Object::Bool(false));
+    assert_eq!(global(&terp, "differs"), Object::Bool(true));
+    assert_eq!(global(&terp, "equal"), Object::Bool(true));
+    assert_eq!(global(&terp, "unequal"), Object::Bool(false));
+  }
+
+  #[test]
+  fn test_function_display() {
+    let terp = Interpreter::new();
+    assert!(run_source(&terp, "fun add(a, b) { return a + b; }"));
+    assert_eq!(global(&terp, "add").to_string(), "<fn add>");
+    assert_eq!(global(&terp, "clock").to_string(), "<native fn clock>");
+    assert_eq!(
+      format!("{:?}", global(&terp, "push")),
+      "Func(<native fn push>)"
+    );
+  }
+
+  #[test]
+  fn test_assert() {
+    let terp = Interpreter::new();
+    assert!(run_source(&terp, "assert 1 < 2; assert_eq(\"a\", \"a\");"));
+
+    let err = run_error(&terp, "def x = 1;\nassert x > 2;");
+    assert!(err.starts_with("line 2"));
+    assert!(err.contains("Assertion failed."));
+
+    assert!(!run_source(&terp, "assert_eq(1, 2);"));
+    assert!(!run_source(&terp, "assert_eq(1, \"1\");"));
+  }
+
+  #[test]
+  fn test_eval_str() {
+    let terp = Interpreter::new();
+    assert_eq!(terp.eval_str("1 + 2").ok(), Some(Object::Num(3.0)));
+
+    assert!(run_source(&terp, "def name = \"sat\";"));
+    assert_eq!(
+      terp.eval_str("upper(name)").ok(),
+      Some(Object::Str("SAT".into()))
+    );
+
+    assert!(terp.eval_str("1 +").is_err());
+    assert!(terp.eval_str("1 2").is_err());
+    assert!(terp.eval_str("missing").is_err());
+  }
+
+  #[test]
+  fn test_define_native() {
+    let terp = Interpreter::new();
+    terp.define_native(
+      "double",
+      1,
+      Rc::new(|_, arguments| match arguments[0] {
+        Object::Num(n) => Ok(Object::Num(n * 2.0)),
+        _ => Err(SaturdayResult::native_error("Expected a number.")),
+      }),
+    );
+
+    assert!(run_source(&terp, "def four = double(2);"));
+    assert_eq!(global(&terp, "four"), Object::Num(4.0));
+    assert_eq!(global(&terp, "double").to_string(), "<native fn double>");
+    assert!(!run_source(&terp, "double(\"two\");"));
+    assert!(!run_source(&terp, "double(1, 2);"));
+  }
+
+  #[test]
+  fn test_labeled_break_and_continue() {
+    let terp = Interpreter::new();
+    let source = "
+      def pairs = 0;
+      def reached = nil;
+      outer: for def i = 0; i < 3; i = i + 1 {
+        for def j = 0; j < 3; j = j + 1 {
+          if j == 1 { continue outer; }
+          if i == 2 { reached = j; break outer; }
+          pairs = pairs + 1;
+        }
+      }
+      def skipped = 0;
+      for def k = 0; k < 5; k = k + 1 {
+        if k < 3 { continue; }
+        skipped = skipped + k;
+      }
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "pairs"), Object::Num(2.0));
+    assert_eq!(global(&terp, "reached"), Object::Num(0.0));
+    assert_eq!(global(&terp, "skipped"), Object::Num(7.0));
+  }
+
+  #[test]
+  fn test_bitwise_operators() {
+    let terp = Interpreter::new();
+    let source = "
+      def both = 12 & 10;
+      def either = 12 | 10;
+      def xor = 12 ^ 10;
+      def left = 1 << 4;
+      def right = -16 >> 2;
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "both"), Object::Num(8.0));
+    assert_eq!(global(&terp, "either"), Object::Num(14.0));
+    assert_eq!(global(&terp, "xor"), Object::Num(6.0));
+    assert_eq!(global(&terp, "left"), Object::Num(16.0));
+    assert_eq!(global(&terp, "right"), Object::Num(-4.0));
+    // `==` 先于 `|` 计算，得到的布尔值不能参与位运算
+    assert!(!run_source(&terp, "1 | 2 == 2;"));
+
+    assert!(!run_source(&terp, "1.5 & 1;"));
+    assert!(!run_source(&terp, "\"a\" | 1;"));
+    assert!(!run_source(&terp, "1 << 64;"));
+    assert!(!run_source(&terp, "1 >> -1;"));
+  }
+
+  #[test]
+  fn test_postfix_increment_and_decrement() {
+    let terp = Interpreter::new();
+    let source = "
+      def x = 5;
+      def before = x++;
+      def after = x;
+      def y = 5;
+      def down = y--;
+      def count = 0;
+      for def i = 0; i < 3; i++ { count++; }
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "before"), Object::Num(5.0));
+    assert_eq!(global(&terp, "after"), Object::Num(6.0));
+    assert_eq!(global(&terp, "down"), Object::Num(5.0));
+    assert_eq!(global(&terp, "y"), Object::Num(4.0));
+    assert_eq!(global(&terp, "count"), Object::Num(3.0));
+
+    // 返回修改前的值本身，而不是加一再减一的结果
+    let source = "
+      def f = 0.1;
+      def old = f++;
+      def big = 9007199254740994;
+      def same = big++ == 9007199254740994;
+      def twice = --5;
+      def n = 2;
+      def neg = --n;
+      def diff = n--1;
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "old"), Object::Num(0.1));
+    assert_eq!(global(&terp, "f"), Object::Num(1.1));
+    assert_eq!(global(&terp, "same"), Object::Bool(true));
+    assert_eq!(global(&terp, "twice"), Object::Num(5.0));
+    assert_eq!(global(&terp, "neg"), Object::Num(2.0));
+    assert_eq!(global(&terp, "diff"), Object::Num(3.0));
+    assert!(!run_source(&terp, "def s = \"a\"; s++;"));
+  }
+
+  #[test]
+  fn test_nan_and_infinity() {
+    let terp = Interpreter::new();
+    let source = "
+      def nan = 0 / 0;
+      def inf = 1 / 0;
+      def neg = -1 / 0;
+      def shown = \"${nan} ${inf} ${neg}\";
+      def less = nan < 1;
+      def greater = nan >= nan;
+      def same = nan == nan;
+      def differs = nan != nan;
+      def biggest = inf > 1000000000;
+      def checked = is_nan(nan);
+      def finite = is_nan(inf);
+    ";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "shown"), Object::Str("nan inf -inf".into()));
+    assert_eq!(global(&terp, "less"), Object::Bool(false));
+    assert_eq!(global(&terp, "greater"), Object::Bool(false));
+    assert_eq!(global(&terp, "same"), Object::Bool(false));
+    assert_eq!(global(&terp, "differs"), Object::Bool(true));
+    assert_eq!(global(&terp, "biggest"), Object::Bool(true));
+    assert_eq!(global(&terp, "checked"), Object::Bool(true));
+    assert_eq!(global(&terp, "finite"), Object::Bool(false));
+    assert!(!run_source(&terp, "is_nan(\"nan\");"));
+  }
+
+  #[test]
+  fn test_clone() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      def inner = list(1);
+      def original = list(2, inner);
+      def copy = clone(original);
+      def copied_inner = pop(copy);
+      push(copied_inner, 4);
+      push(copy, copied_inner);
+      push(original, 5);
+      def copied = \"${{original}} ${{copy}}\";
+
+      class Box {{}}
+      def box = Box();
+      box.items = list(1);
+      def other = clone(box);
+      push(other.items, 2);
+      other.name = \"other\";
+      def boxed = \"${{box.items}} ${{other.items}}\";
+      def fresh = box == other;
+      def number = clone(7);
+
+      def cycle = list();
+      push(cycle, cycle);
+      def again = clone(cycle);
+      def itself = pop(again);
+      push(again, 1);
+      def cyclic = \"${{itself}}\";"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(
+      global(&terp, "copied"),
+      Object::Str("[2, [1], 5] [2, [1, 4]]".into())
+    );
+    assert_eq!(global(&terp, "boxed"), Object::Str("[1] [1, 2]".into()));
+    assert_eq!(global(&terp, "fresh"), Object::Bool(false));
+    assert_eq!(global(&terp, "number"), Object::Num(7.0));
+    assert_eq!(global(&terp, "cyclic"), Object::Str("[1]".into()));
+    assert!(!run_source(&terp, "box.name;"));
+  }
+
+  #[test]
+  fn test_cyclic_values() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      def c = list(1);
+      push(c, c);
+      def same = c == clone(c);
+      def shown = \"${{c}}\";
+      def other = list(1, list(2));
+      def differs = c == other;
+      def m = dict();
+      put(m, \"self\", m);
+      put(m, \"list\", c);
+      def map_same = m == clone(m);
+      def map_shown = \"${{m}}\";"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(global(&terp, "same"), Object::Bool(true));
+    assert_eq!(global(&terp, "shown"), Object::Str("[1, [...]]".into()));
+    assert_eq!(global(&terp, "differs"), Object::Bool(false));
+    assert_eq!(global(&terp, "map_same"), Object::Bool(true));
+    assert_eq!(
+      global(&terp, "map_shown"),
+      Object::Str("{self: {...}, list: [1, [...]]}".into())
+    );
+  }
+
+  #[test]
+  fn test_freeze() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      def frozen = freeze(list(1, 2));
+      def shown = \"${{frozen}}\";
+      fun double(x) {{ return x * 2; }}
+      def doubled = map(frozen, double);
+      push(doubled, 1);
+      def thawed = clone(frozen);
+      push(thawed, 3);"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(global(&terp, "shown"), Object::Str("[1, 2]".into()));
+    assert_eq!(global(&terp, "thawed").to_string(), "[1, 2, 3]");
+
+    let err = run_error(&terp, "push(frozen, 3);");
+    assert_eq!(err, "line 1:15 at ')' Can't modify a frozen list.");
+    assert!(!run_source(&terp, "pop(frozen);"));
+    assert_eq!(global(&terp, "frozen").to_string(), "[1, 2]");
+  }
+
+  #[test]
+  fn test_map_natives() {
+    let terp = Interpreter::new();
+    let source = "def m = dict();
+      put(m, \"b\", 2);
+      put(put(m, \"a\", 1), \"b\", 3);
+      def shown = \"${m}\";
+      def ks = \"${keys(m)}\";
+      def vs = \"${values(m)}\";
+      def has_a = has(m, \"a\");
+      def has_c = has(m, \"c\");
+      def a = get(m, \"a\");
+      def c = get(m, \"c\");";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "shown"), Object::Str("{b: 3, a: 1}".into()));
+    assert_eq!(global(&terp, "ks"), Object::Str("[b, a]".into()));
+    assert_eq!(global(&terp, "vs"), Object::Str("[3, 1]".into()));
+    assert_eq!(global(&terp, "has_a"), Object::Bool(true));
+    assert_eq!(global(&terp, "has_c"), Object::Bool(false));
+    assert_eq!(global(&terp, "a"), Object::Num(1.0));
+    assert_eq!(global(&terp, "c"), Object::Nil);
+
+    let err = run_error(&terp, "keys(1);");
+    assert_eq!(err, "line 1:7 at ')' Expected a map but got '1'.");
+    assert!(!run_source(&terp, "has(\"m\", \"a\");"));
+  }
+
+  #[test]
+  fn test_map_equality_ignores_order() {
+    let terp = Interpreter::new();
+    let source = "def m = put(put(dict(), \"a\", 1), \"b\", 2);
+      def reordered = put(put(dict(), \"b\", 2), \"a\", 1);
+      def same = m == reordered;
+      def fewer = m == put(dict(), \"a\", 1);
+      def changed = m == put(put(dict(), \"b\", 3), \"a\", 1);";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "same"), Object::Bool(true));
+    assert_eq!(global(&terp, "fewer"), Object::Bool(false));
+    assert_eq!(global(&terp, "changed"), Object::Bool(false));
+  }
+
+  #[test]
+  fn test_map_keys() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      def m = dict();
+      put(m, 1, \"one\");
+      put(m, true, \"yes\");
+      put(m, nil, \"none\");
+      put(m, \"1\", \"string one\");
+      put(m, -0, \"zero\");
+      def one = get(m, 1);
+      def yes = get(m, 1 == 1);
+      def none = get(m, nil);
+      def string_one = get(m, \"1\");
+      def zero = get(m, 0);
+      def shown = \"${{keys(m)}}\";
+      def missing = has(m, false);"
+    );
+    assert!(run_source(&terp, &source));
+    assert_eq!(global(&terp, "one"), Object::Str("one".into()));
+    assert_eq!(global(&terp, "yes"), Object::Str("yes".into()));
+    assert_eq!(global(&terp, "none"), Object::Str("none".into()));
+    assert_eq!(
+      global(&terp, "string_one"),
+      Object::Str("string one".into())
+    );
+    assert_eq!(global(&terp, "zero"), Object::Str("zero".into()));
+    assert_eq!(
+      global(&terp, "shown"),
+      Object::Str("[1, true, nil, 1, 0]".into())
+    );
+    assert_eq!(global(&terp, "missing"), Object::Bool(false));
+
+    let err = run_error(&terp, "put(m, list(), 1);");
+    assert_eq!(err, "line 1:17 at ')' Can't use '[]' as a map key.");
+    assert!(!run_source(&terp, "has(m, clock);"));
+    assert!(!run_source(&terp, "get(m, 0 / 0);"));
+  }
+
+  #[test]
+  fn test_to_string_method() {
+    let buffer = SharedBuffer::default();
+    let terp = Interpreter::with_writer(Box::new(buffer.clone()));
+    let source = "class Point {
+        to_string() { return \"(\" + this_x + \", 2)\"; }
+      }
+      class Plain {}
+      def this_x = 1;
+      def point = Point();
+      print point;
+      print Plain();
+      def shown = \"at ${point}\";
+      def converted = str(point);
+      def number = str(3);";
+    assert!(run_source(&terp, source));
+    assert_eq!(buffer.contents(), "(1, 2)\n<Instance of Plain>\n");
+    assert_eq!(global(&terp, "shown"), Object::Str("at (1, 2)".into()));
+    assert_eq!(global(&terp, "converted"), Object::Str("(1, 2)".into()));
+    assert_eq!(global(&terp, "number"), Object::Str("3".into()));
+  }
+
+  #[test]
+  fn test_cross_type_equality() {
+    let terp = Interpreter::new();
+    let source = format!(
+      "{LIST}
+      def a = 1 == true;
+      def b = \"x\" == 1;
+      def c = nil == 0;
+      def d = 0 == nil;
+      def e = 1 != true;
+      def f = list(1, 2) == list(1, 2);
+      def g = clock == clock;"
+    );
+    assert!(run_source(&terp, &source));
+    for (name, expected) in [
+      ("a", false),
+      ("b", false),
+      ("c", false),
+      ("d", false),
+      ("e", true),
+      ("f", true),
+      ("g", true),
+    ] {
+      assert_eq!(global(&terp, name), Object::Bool(expected), "{name}");
+    }
+    assert!(!run_source(&terp, "1 + true;"));
+    assert!(!run_source(&terp, "\"x\" - 1;"));
+  }
+
+  #[test]
+  fn test_strict_logical() {
+    let source = "def a = 1 and 2;
+      def b = nil or \"x\";
+      def c = 0 and nil;
+      def d = false or nil;
+      def calls = 0;
+      fun touch() { calls = calls + 1; return true; }
+      def e = false and touch();
+      def f = true or touch();
+      def g = nil ?? 3;";
+
+    let terp = Interpreter::new();
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "a"), Object::Num(2.0));
+    assert_eq!(global(&terp, "b"), Object::Str("x".into()));
+    assert_eq!(global(&terp, "c"), Object::Nil);
+    assert_eq!(global(&terp, "d"), Object::Nil);
+
+    let strict = Interpreter::new();
+    strict.set_strict_logical(true);
+    assert!(run_source(&strict, source));
+    assert_eq!(global(&strict, "a"), Object::Bool(true));
+    assert_eq!(global(&strict, "b"), Object::Bool(true));
+    assert_eq!(global(&strict, "c"), Object::Bool(false));
+    assert_eq!(global(&strict, "d"), Object::Bool(false));
+    assert_eq!(global(&strict, "e"), Object::Bool(false));
+    assert_eq!(global(&strict, "f"), Object::Bool(true));
+    assert_eq!(global(&strict, "calls"), Object::Num(0.0));
+    assert_eq!(global(&strict, "g"), Object::Num(3.0));
+  }
+
+  #[test]
+  fn test_variable_heavy_program() {
+    let terp = Interpreter::new();
+    let source = "def total = 0;
+      fun counter() {
+        def count = 0;
+        fun next(step) { count = count + step; return count; }
+        return next;
+      }
+      def a = counter();
+      def b = counter();
+      for def i = 0; i < 200; i = i + 1 {
+        def total_here = a(1) + b(2);
+        {
+          def total = i;
+          total_here = total_here + total;
+        }
+        total = total + total_here;
+      }";
+    assert!(run_source(&terp, source));
+    // sum(i + 2i + 2 + i) for i in 1..=200 shifted by one
+    let expected: f64 = (0..200).map(|i| ((i + 1) + 2 * (i + 1) + i) as f64).sum();
+    assert_eq!(global(&terp, "total"), Object::Num(expected));
+  }
+
+  #[test]
+  fn test_global_lookup_cache() {
+    let terp = Interpreter::new();
+    let source = "def step = 2;
+      def total = 0;
+      fun read() { return step; }
+      for def i = 0; i < 500; i = i + 1 {
+        total = total + step;
+      }
+      def before = read();
+      def step = 5;
+      step = step + 1;
+      def after = read();
+      fun shadow() { def step = 100; return step + read(); }
+      def shadowed = shadow();";
+    assert!(run_source(&terp, source));
+    assert_eq!(global(&terp, "total"), Object::Num(1000.0));
+    assert_eq!(global(&terp, "before"), Object::Num(2.0));
+    assert_eq!(global(&terp, "after"), Object::Num(6.0));
+    assert_eq!(global(&terp, "shadowed"), Object::Num(106.0));
+
+    terp.reset();
+    assert!(run_source(&terp, "def step = 7; def again = step;"));
+    assert_eq!(global(&terp, "again"), Object::Num(7.0));
+  }
+
+  /// 在临时目录中写入一组文件，返回目录路径
+  fn write_files(name: &str, files: &[(&str, &str)]) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!("saturday-{name}-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("lib")).unwrap();
+    for (file, source) in files {
+      std::fs::write(dir.join(file), source).unwrap();
+    }
+    dir
+  }
+
+  #[test]
+  fn test_import() {
+    let dir = write_files(
+      "import",
+      &[
+        (
+          "main.sd",
+          "import \"lib/math.sd\";
+          def result = square(answer);",
+        ),
+        (
+          "lib/math.sd",
+          "import \"helpers.sd\";
+          fun square(x) { return twice(x) * x / 2; }
+          def answer = 6;",
+        ),
+        ("lib/helpers.sd", "fun twice(x) { return x + x; }"),
+      ],
+    );
+    let main = dir.join("main.sd");
+    let terp = Interpreter::new();
+    terp.set_script_path(&main);
+    assert!(run_source(&terp, &std::fs::read_to_string(&main).unwrap()));
+    assert_eq!(global(&terp, "result"), Object::Num(36.0));
+    assert!(!run_source(&terp, "import \"missing.sd\";"));
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_import_reads_through_file_system() {
+    let fake = Rc::new(FakeFileSystem::default());
+    fake.0.borrow_mut().insert(
+      "lib/math.sd".to_string(),
+      "import \"./../lib/helpers.sd\"; def answer = twice(21);".to_string(),
+    );
+    fake.0.borrow_mut().insert(
+      "lib/helpers.sd".to_string(),
+      "fun twice(x) { return x + x; }".to_string(),
+    );
+    let terp = Interpreter::new();
+    terp.set_file_system(Some(fake));
+    assert!(run_source(
+      &terp,
+      "import \"lib/math.sd\" as m; def result = m.answer;"
+    ));
+    assert_eq!(global(&terp, "result"), Object::Num(42.0));
+    assert!(!run_source(&terp, "import \"lib/missing.sd\";"));
+
+    terp.set_file_system(None);
+    let err = run_error(&terp, "import \"lib/math.sd\";");
+    assert!(err.ends_with("File system access is disabled."), "{err}");
+  }
+
+  #[test]
+  fn test_import_as_module() {
+    let dir = write_files(
+      "module",
+      &[
+        (
+          "main.sd",
+          "import \"lib/math.sd\" as m;
+          def area = m.circle(2);
+          def pi = m.pi;
+          m.set_pi(3);
+          def changed = m.circle(1);",
+        ),
+        (
+          "lib/math.sd",
+          "def pi = 3.5;
+          fun square(x) { return x * x; }
+          fun circle(r) { return pi * square(r); }
+          fun set_pi(value) { pi = value; }",
+        ),
+      ],
+    );
+    let main = dir.join("main.sd");
+    let terp = Interpreter::new();
+    terp.set_script_path(&main);
+    assert!(run_source(&terp, &std::fs::read_to_string(&main).unwrap()));
+    assert_eq!(global(&terp, "area"), Object::Num(14.0));
+    assert_eq!(global(&terp, "pi"), Object::Num(3.5));
+    assert_eq!(global(&terp, "changed"), Object::Num(3.0));
+    assert!(terp
+      .globals
+      .borrow()
+      .bindings()
+      .iter()
+      .all(|(name, _)| name != "square"));
+    assert!(!run_source(&terp, "m.missing;"));
+    std::fs::remove_dir_all(dir).unwrap();
+  }
+
+  #[test]
+  fn test_circular_import() {
+    let dir = write_files(
+      "circular",
+      &[
+        ("a.sd", "import \"b.sd\"; def a = 1;"),
+        ("b.sd", "import \"a.sd\"; def b = 2;"),
+      ],
+    );
+    let a = dir.join("a.sd");
+    let terp = Interpreter::new();
+    terp.set_script_path(&a);
+
+    let err = run_error(&terp, &std::fs::read_to_string(&a).unwrap());
+    let expected = format!(
+      "Circular import of '{}'.",
+      a.canonicalize().unwrap().display()
+    );
+    assert!(err.ends_with(&expected), "{err}");
+    std::fs::remove_dir_all(dir).unwrap();
   }
 }
@@ -0,0 +1,30 @@
+pub mod callable;
+pub mod environment;
+pub mod error;
+pub mod expr;
+pub mod formatter;
+#[cfg(test)]
+#[path = "../generate_ast/mod.rs"]
+mod generate_ast;
+pub mod interpreter;
+pub mod module;
+pub mod native_functions;
+pub mod object;
+pub mod optimizer;
+pub mod parser;
+pub mod resolver;
+pub mod saturday_class;
+pub mod saturday_function;
+pub mod saturday_instance;
+pub mod scanner;
+pub mod span;
+pub mod stmt;
+pub mod symbol;
+pub mod token;
+pub mod token_type;
+#[cfg(feature = "wasm")]
+pub mod wasm;
+// pub mod ast_printer;
+
+pub use error::SaturdayResult;
+pub use interpreter::Interpreter;
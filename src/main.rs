@@ -1,111 +1,879 @@
 extern crate core;
 
+use std::cell::RefCell;
 use std::env::args;
 use std::io;
 use std::io::{stdout, BufRead, Write};
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::time::Instant;
 
-use error::*;
-use scanner::*;
-// use crate::ast_printer::AstPrinter;
-use crate::interpreter::Interpreter;
-use crate::parser::Parser;
-use crate::resolver::Resolver;
-
-mod error;
-mod expr;
-mod parser;
-mod scanner;
-mod token;
-mod token_type;
-// mod ast_printer;
-mod callable;
-mod environment;
-mod interpreter;
-mod native_functions;
-mod object;
-mod resolver;
-mod saturday_class;
-mod saturday_function;
-mod saturday_instance;
-mod stmt;
+use saturday_ast::expr::Expr;
+use saturday_ast::object::Object;
+use saturday_ast::stmt::{FunctionStmt, Stmt};
+use saturday_ast::token::Token;
+use saturday_ast::token_type::TokenType;
+
+// use saturday_ast::ast_printer::AstPrinter;
+use saturday_ast::error::{
+  error_format, set_error_format, set_source, take_json_errors, ErrorFormat,
+};
+use saturday_ast::formatter::format_source;
+use saturday_ast::interpreter::{
+  with_interpreter_stack, Interpreter, INTERPRETER_STACK_MAX_CALL_DEPTH,
+};
+use saturday_ast::optimizer::Optimizer;
+use saturday_ast::parser::Parser;
+use saturday_ast::resolver::Resolver;
+use saturday_ast::scanner::*;
+use saturday_ast::SaturdayResult;
 
 fn main() {
-  let args: Vec<String> = args().collect();
-  let saturday = Saturday::new();
+  with_interpreter_stack(run_cli);
+}
+
+fn run_cli() {
+  let mut args: Vec<String> = args().collect();
+  let json_errors = args.iter().any(|arg| arg == "--errors=json");
+  args.retain(|arg| arg != "--errors=json");
+  if json_errors {
+    set_error_format(ErrorFormat::Json);
+  }
+  let time = args.iter().any(|arg| arg == "--time");
+  args.retain(|arg| arg != "--time");
+  let optimize = args.iter().any(|arg| arg == "--optimize");
+  args.retain(|arg| arg != "--optimize");
+
+  let mut saturday = Saturday::new();
+  saturday
+    .interpreter
+    .set_max_call_depth(INTERPRETER_STACK_MAX_CALL_DEPTH);
+  if time {
+    saturday.timing = Some(RefCell::new(Box::new(io::stderr())));
+  }
+  saturday.optimize = optimize;
 
   match args.len() {
-    1 => saturday.run_prompt(),
+    1 => {
+      if let Some(home) = std::env::var_os("HOME") {
+        saturday.history = History::load(Path::new(&home).join(".saturday_history"));
+      }
+      saturday.run_prompt()
+    }
     2 => saturday.run_file(&args[1]).expect("Could not run file"),
+    3 if args[1] == "--tokens" => {
+      let source = std::fs::read_to_string(&args[2]).expect("Could not read file");
+      match dump_tokens(source) {
+        Some(dump) => print!("{}", dump),
+        None => std::process::exit(65),
+      }
+    }
+    3 if args[1] == "--check" => {
+      let source = std::fs::read_to_string(&args[2]).expect("Could not read file");
+      std::process::exit(saturday.check(source));
+    }
+    3 if args[1] == "--fmt" => {
+      let source = std::fs::read_to_string(&args[2]).expect("Could not read file");
+      match format_source(&source) {
+        Some(formatted) => print!("{}", formatted),
+        None => std::process::exit(65),
+      }
+    }
+    3 if args[1] == "--run-ast-json" => {
+      saturday.run_ast_file(&args[2]).expect("Could not run file")
+    }
+    3 if args[1] == "--emit-ast-json" => {
+      let source = std::fs::read_to_string(&args[2]).expect("Could not read file");
+      match emit_ast_json(source) {
+        Some(json) => println!("{}", json),
+        None => std::process::exit(65),
+      }
+    }
     _ => {
-      println!("Usage: saturday-ast [script]");
+      println!("Usage: saturday-ast [--errors=json] [--time] [--optimize] [--tokens | --check | --fmt | --emit-ast-json | --run-ast-json] [script]");
       std::process::exit(64);
     }
   }
 }
 
+/// 只做词法分析，每行输出一个 token 的行号、类型、词素和字面量
+fn dump_tokens(source: String) -> Option<String> {
+  set_source(&source);
+  let mut scanner = Scanner::new(source);
+  let tokens = scanner.scan_tokens().ok()?;
+  Some(
+    tokens
+      .iter()
+      .map(|token| format!("{:>4} {}\n", token.line, token))
+      .collect(),
+  )
+}
+
+/// 只做词法和语法分析，把执行前的 AST 输出为 JSON
+fn emit_ast_json(source: String) -> Option<String> {
+  set_source(&source);
+  let mut scanner = Scanner::new(source);
+  let tokens = scanner.scan_tokens().ok()?;
+  let statements = Parser::new(tokens).parse().ok()?;
+  serde_json::to_string_pretty(&statements).ok()
+}
+
+/// # 检查外部生成的 AST
+/// 反序列化成功的语法树仍可能不是解析器能产生的形状，执行前先拒绝这些情况：
+/// 字面量必须有值，函数的默认值列表必须和形参一一对应
+fn check_ast(statements: &[Rc<Stmt>]) -> Result<(), String> {
+  statements.iter().try_for_each(|stmt| check_stmt(stmt))
+}
+
+fn check_stmt(stmt: &Stmt) -> Result<(), String> {
+  let option = |expr: &Option<Rc<Expr>>| expr.as_deref().map_or(Ok(()), check_expr);
+  match stmt {
+    Stmt::Assert(s) => check_expr(&s.condition),
+    Stmt::Block(s) => check_ast(&s.statements),
+    Stmt::Class(s) => {
+      option(&s.superclass)?;
+      check_ast(&s.methods)?;
+      check_ast(&s.statics)
+    }
+    Stmt::Const(s) => check_expr(&s.initializer),
+    Stmt::Do(s) => {
+      check_stmt(&s.body)?;
+      check_expr(&s.condition)
+    }
+    Stmt::Expression(s) => check_expr(&s.expression),
+    Stmt::ForEach(s) => {
+      check_expr(&s.iterable)?;
+      check_stmt(&s.body)
+    }
+    Stmt::Function(s) => check_function(s),
+    Stmt::If(s) => {
+      check_expr(&s.condition)?;
+      check_stmt(&s.then_branch)?;
+      s.else_branch.as_deref().map_or(Ok(()), check_stmt)
+    }
+    Stmt::Print(s) => check_expr(&s.expression),
+    Stmt::Repeat(s) => {
+      check_expr(&s.count)?;
+      check_stmt(&s.body)
+    }
+    Stmt::Return(s) => option(&s.value),
+    Stmt::Throw(s) => check_expr(&s.value),
+    Stmt::Try(s) => {
+      check_ast(&s.body)?;
+      check_ast(&s.handler)
+    }
+    Stmt::Def(s) => option(&s.initializer),
+    Stmt::While(s) => {
+      check_expr(&s.condition)?;
+      check_stmt(&s.body)?;
+      option(&s.increment)
+    }
+    Stmt::Break(_) | Stmt::Continue(_) | Stmt::Global(_) | Stmt::Import(_) | Stmt::Undef(_) => {
+      Ok(())
+    }
+  }
+}
+
+fn check_function(function: &FunctionStmt) -> Result<(), String> {
+  if function.defaults.len() != function.params.len() {
+    return Err(format!(
+      "function '{}' has {} parameters but {} defaults",
+      function.name.lexeme,
+      function.params.len(),
+      function.defaults.len()
+    ));
+  }
+
+  for default in function.defaults.iter().flatten() {
+    check_expr(default)?;
+  }
+  check_ast(&function.body)
+}
+
+fn check_expr(expr: &Expr) -> Result<(), String> {
+  match expr {
+    Expr::Assign(e) => check_expr(&e.value),
+    Expr::Binary(e) => {
+      check_expr(&e.left)?;
+      check_expr(&e.right)
+    }
+    Expr::Call(e) => {
+      check_expr(&e.callee)?;
+      e.arguments
+        .iter()
+        .try_for_each(|argument| check_expr(argument))
+    }
+    Expr::Get(e) => check_expr(&e.object),
+    Expr::Grouping(e) => check_expr(&e.expression),
+    Expr::Interpolation(e) => e.parts.iter().try_for_each(|part| check_expr(part)),
+    Expr::Lambda(e) => check_function(&e.function),
+    Expr::Literal(e) if e.value.is_none() => Err("literal without a value".to_string()),
+    Expr::Logical(e) => {
+      check_expr(&e.left)?;
+      check_expr(&e.right)
+    }
+    Expr::Set(e) => {
+      check_expr(&e.object)?;
+      check_expr(&e.value)
+    }
+    Expr::Unary(e) => check_expr(&e.right),
+    Expr::Literal(_) | Expr::Postfix(_) | Expr::Super(_) | Expr::This(_) | Expr::Variable(_) => {
+      Ok(())
+    }
+  }
+}
+
+/// # REPL 历史
+/// 解析成功的输入。设置了文件时启动时读取，之后追加写入。
+/// 文件中每条占一行，多行输入的换行和反斜杠经过转义，读回后与原输入相同
+#[derive(Default)]
+struct History {
+  path: Option<PathBuf>,
+  entries: RefCell<Vec<String>>,
+}
+
+impl History {
+  /// 文件不存在时从空历史开始
+  fn load(path: PathBuf) -> Self {
+    let entries = std::fs::read_to_string(&path)
+      .map(|text| text.lines().map(History::unescape).collect())
+      .unwrap_or_default();
+    Self {
+      path: Some(path),
+      entries: RefCell::new(entries),
+    }
+  }
+
+  /// 写入文件失败时只保留在内存中
+  fn append(&self, source: &str) {
+    if let Some(path) = &self.path {
+      let _ = std::fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| writeln!(file, "{}", History::escape(source)));
+    }
+    self.entries.borrow_mut().push(source.to_string());
+  }
+
+  /// 转义换行和反斜杠，让一条多行输入占一行，行尾注释不会吞掉后面的内容
+  fn escape(entry: &str) -> String {
+    entry.replace('\\', "\\\\").replace('\n', "\\n")
+  }
+
+  fn unescape(line: &str) -> String {
+    let mut entry = String::new();
+    let mut chars = line.chars();
+    while let Some(ch) = chars.next() {
+      match (ch, chars.clone().next()) {
+        ('\\', Some('n')) => {
+          entry.push('\n');
+          chars.next();
+        }
+        ('\\', Some('\\')) => {
+          entry.push('\\');
+          chars.next();
+        }
+        (ch, _) => entry.push(ch),
+      }
+    }
+    entry
+  }
+}
+
 struct Saturday {
   interpreter: Interpreter,
+  /// `--time` 时执行耗时写入这里，默认是标准错误
+  timing: Option<RefCell<Box<dyn Write>>>,
+  /// `--optimize` 时在解析作用域之前折叠常量
+  optimize: bool,
+  /// REPL 中解析成功的输入
+  history: History,
 }
 
 impl Saturday {
   pub fn new() -> Self {
     Self {
       interpreter: Interpreter::new(),
+      timing: None,
+      optimize: false,
+      history: History::default(),
     }
   }
 
-  fn run_file(&self, path: &str) -> io::Result<()> {
-    let buf = std::fs::read_to_string(path)?;
-    if self.run(buf).is_err() {
-      // Ignore: error was already reported
-      std::process::exit(65);
+  /// 执行语句，开启 `--time` 时无论成功与否都在结束后报告耗时
+  fn execute(&self, statements: &[Rc<Stmt>]) -> Result<(), SaturdayResult> {
+    let start = Instant::now();
+    let result = self.interpreter.interpreter(statements);
+    if let Some(timing) = &self.timing {
+      writeln!(
+        timing.borrow_mut(),
+        "elapsed: {} ms",
+        start.elapsed().as_millis()
+      )
+      .expect("write error");
     }
+    result
+  }
 
+  fn run_file(&self, path: &str) -> io::Result<()> {
+    let bytes = std::fs::read(path)?;
+    self.interpreter.set_script_path(Path::new(path));
+    Saturday::exit_on_error(Scanner::decode(bytes).and_then(|buf| self.run(buf)));
+    Ok(())
+  }
+
+  /// 执行 `--emit-ast-json` 输出的 AST，跳过词法和语法分析
+  fn run_ast_file(&self, path: &str) -> io::Result<()> {
+    let json = std::fs::read_to_string(path)?;
+    Saturday::exit_on_error(self.run_ast_json(&json));
     Ok(())
   }
 
+  fn exit_on_error(result: Result<bool, SaturdayResult>) {
+    if error_format() == ErrorFormat::Json {
+      eprintln!("{}", take_json_errors());
+    }
+
+    if let Some(code) = Saturday::exit_code(&result) {
+      std::process::exit(code);
+    }
+  }
+
+  /// 错误已经报告过，只需按错误类型给出退出码。
+  /// 语法或作用域检查没有通过时为 65，运行时错误为 70
+  fn exit_code(result: &Result<bool, SaturdayResult>) -> Option<i32> {
+    match result {
+      Ok(true) => None,
+      Err(SaturdayResult::RuntimeError { .. }) => Some(70),
+      Ok(false) | Err(_) => Some(65),
+    }
+  }
+
   fn run_prompt(&self) {
     let stdin = io::stdin();
-    print!("> ");
-    stdout().flush().expect("flush error");
-    for line in stdin.lock().lines() {
+    self.repl(stdin.lock(), &mut stdout());
+  }
+
+  fn repl<R: BufRead, W: Write>(&self, input: R, out: &mut W) {
+    let mut buffer = String::new();
+    Saturday::prompt(out, "> ");
+    for line in input.lines() {
       if let Ok(line) = line {
-        if line.is_empty() {
+        if line.is_empty() && buffer.is_empty() {
           break;
         }
 
-        let _ = self.run(line);
+        buffer.push_str(&line);
+        buffer.push('\n');
       } else {
         break;
       }
 
-      print!("> ");
-      stdout().flush().expect("flush error");
+      // 括号未闭合时继续读取下一行
+      if self.run_line(&buffer, out) {
+        if error_format() == ErrorFormat::Json {
+          eprintln!("{}", take_json_errors());
+        }
+
+        buffer.clear();
+        Saturday::prompt(out, "> ");
+      } else {
+        Saturday::prompt(out, "... ");
+      }
     }
   }
 
-  fn run(&self, source: String) -> Result<(), SaturdayResult> {
-    if source == "@" {
-      self.interpreter.print_environment();
-      return Ok(());
+  fn prompt<W: Write>(out: &mut W, prompt: &str) {
+    write!(out, "{}", prompt).expect("write error");
+    out.flush().expect("flush error");
+  }
+
+  /// # REPL 输入
+  /// 表达式语句的值会被回显。
+  /// 输入尚不完整时返回 `false`，由调用方继续读取。
+  fn run_line<W: Write>(&self, source: &str, out: &mut W) -> bool {
+    let mut source = source.trim_end().to_string();
+    if self.command(&source, out) {
+      return true;
+    }
+
+    set_source(&source);
+    let mut scanner = Scanner::new(source.clone());
+    let tokens = match scanner.scan_tokens() {
+      Ok(tokens) => tokens,
+      Err(_) => return true,
+    };
+
+    if Saturday::is_incomplete(tokens) {
+      return false;
+    }
+
+    // 允许在 REPL 中省略表达式末尾的分号
+    let mut completed;
+    let tokens = match Saturday::missing_semicolon(tokens) {
+      Some(at) => {
+        let mut chars: Vec<char> = source.chars().collect();
+        chars.insert(at, ';');
+        source = chars.into_iter().collect();
+        set_source(&source);
+        completed = Scanner::new(source.clone());
+        match completed.scan_tokens() {
+          Ok(tokens) => tokens,
+          Err(_) => return true,
+        }
+      }
+      None => tokens,
+    };
+
+    if let Ok(Some(statements)) = self.prepare(tokens) {
+      self.history.append(&source);
+      match self.interpreter.interpret_with_value(&statements) {
+        Some(Object::Nil) | None => {}
+        Some(value) => writeln!(out, "{}", value).expect("write error"),
+      }
     }
 
+    true
+  }
+
+  /// # REPL 命令
+  /// - `:load <path>` 在当前会话中执行文件
+  /// - `:env` 列出全局定义（`@` 为旧写法）
+  /// - `:reset` 清空会话中的所有定义
+  /// - `:history` 列出历史输入
+  ///
+  /// 不是命令时返回 `false`
+  fn command<W: Write>(&self, line: &str, out: &mut W) -> bool {
+    let (command, argument) = match line.split_once(char::is_whitespace) {
+      Some((command, argument)) => (command, argument.trim()),
+      None => (line, ""),
+    };
+
+    match command {
+      ":load" => {
+        if let Err(e) = self.run_file_in_session(argument) {
+          writeln!(out, "Could not load '{}': {}", argument, e).expect("write error");
+        }
+      }
+      ":env" | "@" => {
+        for (name, value) in self.interpreter.globals.borrow().bindings() {
+          writeln!(out, "{} = {}", name, value).expect("write error");
+        }
+      }
+      ":reset" => self.interpreter.reset(),
+      ":history" => {
+        for (number, entry) in self.history.entries.borrow().iter().enumerate() {
+          writeln!(out, "{:>4}  {}", number + 1, History::escape(entry)).expect("write error");
+        }
+      }
+      _ => return false,
+    }
+
+    true
+  }
+
+  fn run_file_in_session(&self, path: &str) -> io::Result<()> {
+    let buf = std::fs::read_to_string(path)?;
+    self.interpreter.set_script_path(Path::new(path));
+    let _ = self.run(buf);
+    Ok(())
+  }
+
+  /// 最后一个 token 不是 `;` 或 `}` 时，返回补分号的字符位置。
+  /// 按 token 而不是按文本判断，分号补在行尾注释之前
+  fn missing_semicolon(tokens: &[Token]) -> Option<usize> {
+    let last = tokens
+      .iter()
+      .rev()
+      .find(|token| !token.is(TokenType::Eof))?;
+    if last.is_one_of(&[TokenType::SemiColon, TokenType::RightBrace]) {
+      None
+    } else {
+      Some(last.start + last.len)
+    }
+  }
+
+  /// 还有未闭合的括号，说明语句在下一行继续
+  fn is_incomplete(tokens: &[Token]) -> bool {
+    let mut depth = 0;
+    for token in tokens {
+      match token.t_type {
+        TokenType::LeftParen | TokenType::LeftBrace => depth += 1,
+        TokenType::RightParen | TokenType::RightBrace => depth -= 1,
+        _ => {}
+      }
+    }
+
+    depth > 0
+  }
+
+  /// 返回 false 表示语法或作用域检查没有通过，程序没有执行
+  fn run(&self, source: String) -> Result<bool, SaturdayResult> {
+    set_source(&source);
     let mut scanner = Scanner::new(source);
     let tokens = scanner.scan_tokens()?;
+    match self.prepare(tokens)? {
+      Some(statements) => self.execute(&statements).map(|()| true),
+      None => Ok(false),
+    }
+  }
+
+  /// # 静态检查
+  /// 只做词法、语法和作用域分析，报告所有诊断但不执行。
+  /// 返回进程退出码，有错误时为 65
+  fn check(&self, source: String) -> i32 {
+    set_source(&source);
+    let mut scanner = Scanner::new(source);
+    // 词法错误之后继续做语法分析，一次报告尽可能多的问题
+    let (tokens, errors) = scanner.scan_with_errors();
+    let parsed = matches!(self.prepare(tokens), Ok(Some(_)));
+    let clean = errors.is_empty() && parsed;
+
+    if error_format() == ErrorFormat::Json {
+      eprintln!("{}", take_json_errors());
+    }
+    if clean {
+      0
+    } else {
+      65
+    }
+  }
+
+  fn run_ast_json(&self, json: &str) -> Result<bool, SaturdayResult> {
+    let statements: Vec<Rc<Stmt>> = serde_json::from_str(json)
+      .map_err(|e| SaturdayResult::system_error(&format!("Invalid AST JSON: {e}")))?;
+    check_ast(&statements)
+      .map_err(|e| SaturdayResult::system_error(&format!("Invalid AST JSON: {e}.")))?;
+    match self.resolve(statements)? {
+      Some(statements) => self.execute(&statements).map(|()| true),
+      None => Ok(false),
+    }
+  }
+
+  /// 解析并解析作用域，全部成功时返回语句列表
+  fn prepare(&self, tokens: &[Token]) -> Result<Option<Rc<Vec<Rc<Stmt>>>>, SaturdayResult> {
     let mut parser = Parser::new(tokens);
     let statements = parser.parse()?;
 
     if parser.success() {
-      let resolver = Resolver::new(&self.interpreter);
-      let s = Rc::new(statements);
-      resolver.resolve(&Rc::clone(&s))?;
+      return self.resolve(statements);
+    }
 
-      if resolver.success() {
-        self.interpreter.interpreter(&Rc::clone(&s));
-      }
+    Ok(None)
+  }
+
+  fn resolve(
+    &self,
+    statements: Vec<Rc<Stmt>>,
+  ) -> Result<Option<Rc<Vec<Rc<Stmt>>>>, SaturdayResult> {
+    let statements = if self.optimize {
+      Optimizer::new(&self.interpreter).optimize(&statements)?
+    } else {
+      statements
+    };
+    let resolver = Resolver::new(&self.interpreter);
+    let s = Rc::new(statements);
+    resolver.resolve(&Rc::clone(&s))?;
+
+    if resolver.success() {
+      return Ok(Some(s));
     }
 
-    Ok(())
+    Ok(None)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use saturday_ast::error::take_captured_errors;
+  use saturday_ast::interpreter::SharedBuffer;
+  use serde_json::json;
+
+  fn repl_output(input: &str) -> String {
+    let saturday = Saturday::new();
+    let mut out = Vec::new();
+    saturday.repl(input.as_bytes(), &mut out);
+    String::from_utf8(out).unwrap()
+  }
+
+  #[test]
+  fn repl_prints_expression_results() {
+    assert_eq!(repl_output("3 * 4\n"), "> 12\n> ");
+  }
+
+  #[test]
+  fn repl_reads_a_block_across_lines() {
+    let input =
+      "def total = 0;\nfor def i = 0; i < 3; i = i + 1 {\n  total = total + i;\n}\ntotal\n";
+    assert_eq!(repl_output(input), "> > ... ... > 3\n> ");
+  }
+
+  #[test]
+  fn repl_adds_semicolon_before_a_trailing_comment() {
+    assert_eq!(repl_output("1 + 2 // three\n"), "> 3\n> ");
+    assert_eq!(repl_output("\"a // b\" // c\n"), "> a // b\n> ");
+  }
+
+  #[test]
+  fn repl_does_not_print_statements_or_nil() {
+    assert_eq!(repl_output("def a = 1;\nnil\n"), "> > > ");
+  }
+
+  #[test]
+  fn repl_reset_clears_definitions() {
+    let saturday = Saturday::new();
+    let mut out = Vec::new();
+    saturday.repl("def a = 1;\n:env\n:reset\n:env\n".as_bytes(), &mut out);
+    let out = String::from_utf8(out).unwrap();
+    let (before, after) = out
+      .split_once("> > ")
+      .unwrap()
+      .1
+      .split_once("> > ")
+      .unwrap();
+    assert!(before.starts_with("a = 1\n"));
+    assert!(before.contains("clock = <native fn clock>\n"));
+    assert!(!after.contains("a = 1"));
+    assert!(after.contains("clock = <native fn clock>\n"));
+  }
+
+  #[test]
+  fn repl_load_runs_file_in_session() {
+    let path = std::env::temp_dir().join("saturday_repl_load.sat");
+    std::fs::write(&path, "def loaded = 40 + 2;").unwrap();
+    let input = format!(":load {}\nloaded\n", path.display());
+    assert_eq!(repl_output(&input), "> > 42\n> ");
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn history_round_trips_through_the_file() {
+    let path = std::env::temp_dir().join(format!("saturday_history_{}", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+
+    let history = History::load(path.clone());
+    history.append("def a = 1;");
+    history.append("if a {\n  print a;\n}");
+    history.append("def b = a; // copy\nprint \"\\\\n\";");
+    let loaded = History::load(path.clone());
+    assert_eq!(
+      *loaded.entries.borrow(),
+      vec![
+        "def a = 1;",
+        "if a {\n  print a;\n}",
+        "def b = a; // copy\nprint \"\\\\n\";"
+      ]
+    );
+    assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn repl_lists_history() {
+    assert_eq!(
+      repl_output("1 + 1\nif true {\n}\n1 +\n:history\n"),
+      "> 2\n> ... > >    1  1 + 1;\n   2  if true {\\n}\n> "
+    );
+  }
+
+  #[test]
+  fn runs_a_file_with_a_shebang() {
+    let path = std::env::temp_dir().join(format!("saturday_shebang_{}.sd", std::process::id()));
+    std::fs::write(&path, "#!/usr/bin/env saturday\nprint \"hi\";\n").unwrap();
+    let output = captured(|saturday| saturday.run(std::fs::read_to_string(&path).unwrap()));
+    assert_eq!(output, "hi\n");
+    std::fs::remove_file(path).unwrap();
+  }
+
+  #[test]
+  fn json_errors_for_a_program() {
+    let saturday = Saturday::new();
+    set_error_format(ErrorFormat::Json);
+    let result = saturday.run("fun f(unused) { return 1; }\nf(1);\nmissing;".to_string());
+    set_error_format(ErrorFormat::Human);
+
+    assert!(result.is_err());
+    assert_eq!(
+      take_json_errors(),
+      concat!(
+        r#"[{"kind":"warning","line":1,"column":7,"lexeme":"unused","message":"Local variable 'unused' is never used."},"#,
+        r#"{"kind":"runtime","line":3,"column":1,"lexeme":"missing","message":"Undefined variable 'missing'."}]"#
+      )
+    );
+  }
+
+  #[test]
+  fn check_reports_without_running() {
+    let buffer = SharedBuffer::default();
+    let saturday = Saturday {
+      interpreter: Interpreter::with_writer(Box::new(buffer.clone())),
+      timing: None,
+      optimize: false,
+      history: History::default(),
+    };
+    let broken = "print \"ran\";\nfun f() { def a = 1; def a = 2; }";
+    assert_eq!(saturday.check(broken.to_string()), 65);
+    let clean = "print \"ran\";\nfun f(unused) { return 1; }";
+    assert_eq!(saturday.check(clean.to_string()), 0);
+    assert!(buffer.contents().is_empty());
+  }
+
+  #[test]
+  fn static_errors_exit_with_65() {
+    let buffer = SharedBuffer::default();
+    let saturday = Saturday {
+      interpreter: Interpreter::with_writer(Box::new(buffer.clone())),
+      timing: None,
+      optimize: false,
+      history: History::default(),
+    };
+    set_error_format(ErrorFormat::Captured);
+    let resolve_error = saturday.run("print \"ran\";\nreturn 1;".to_string());
+    let parse_error = saturday.run("print \"ran\";\nprint ;".to_string());
+    let runtime_error = saturday.run("missing;".to_string());
+    set_error_format(ErrorFormat::Human);
+    take_captured_errors();
+
+    assert_eq!(Saturday::exit_code(&resolve_error), Some(65));
+    assert_eq!(Saturday::exit_code(&parse_error), Some(65));
+    assert_eq!(Saturday::exit_code(&runtime_error), Some(70));
+    assert_eq!(Saturday::exit_code(&saturday.run("1;".to_string())), None);
+    assert!(buffer.contents().is_empty());
+  }
+
+  #[test]
+  fn check_reports_lexical_and_syntax_errors_together() {
+    let saturday = Saturday::new();
+    set_error_format(ErrorFormat::Captured);
+    let code = saturday.check("def a = 1 @;\nprint a $;\nprint ;".to_string());
+    set_error_format(ErrorFormat::Human);
+
+    assert_eq!(code, 65);
+    let errors = take_captured_errors();
+    assert!(errors.contains("Unexpected character '@'."), "{}", errors);
+    assert!(errors.contains("Unexpected character '$'."), "{}", errors);
+    assert!(
+      errors.contains("line 3:7 at ';' Expect expression."),
+      "{}",
+      errors
+    );
+  }
+
+  #[test]
+  fn dump_tokens_of_a_program() {
+    assert_eq!(
+      dump_tokens("def a = 1;\nprint \"hi\";".to_string()).unwrap(),
+      concat!(
+        "   1 Def def None\n",
+        "   1 Identifier a None\n",
+        "   1 Assign = None\n",
+        "   1 Number 1 1\n",
+        "   1 SemiColon ; None\n",
+        "   2 Print print None\n",
+        "   2 String \"hi\" hi\n",
+        "   2 SemiColon ; None\n",
+        "   2 Eof  None\n",
+      )
+    );
+  }
+
+  #[test]
+  fn emit_ast_json_of_an_expression() {
+    let json = emit_ast_json("1 + x;".to_string()).unwrap();
+    let ast: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let binary = &ast[0]["Expression"]["expression"]["Binary"];
+    assert_eq!(binary["left"]["Literal"]["value"], json!({ "Num": 1.0 }));
+    assert_eq!(binary["operator"]["t_type"], "Plus");
+    assert_eq!(binary["operator"]["lexeme"], "+");
+    assert_eq!(binary["right"]["Variable"]["name"]["lexeme"], "x");
+    assert_eq!(binary["right"]["Variable"]["name"]["column"], 5);
+  }
+
+  fn captured(run: impl FnOnce(&Saturday) -> Result<bool, SaturdayResult>) -> String {
+    let buffer = SharedBuffer::default();
+    let saturday = Saturday {
+      interpreter: Interpreter::with_writer(Box::new(buffer.clone())),
+      timing: None,
+      optimize: false,
+      history: History::default(),
+    };
+    assert!(matches!(run(&saturday), Ok(true)));
+    buffer.contents()
+  }
+
+  #[test]
+  fn run_ast_json_matches_running_the_source() {
+    let source = include_str!("../closure.sd");
+    let json = emit_ast_json(source.to_string()).unwrap();
+
+    let from_source = captured(|saturday| saturday.run(source.to_string()));
+    let from_json = captured(|saturday| saturday.run_ast_json(&json));
+    assert_eq!(from_source, "test\n1\ntest\n2\n");
+    assert_eq!(from_json, from_source);
+
+    let function_literal =
+      r#"[{ "Print": { "expression": { "Literal": { "value": { "Func": null } } } } }]"#;
+    assert!(Saturday::new().run_ast_json(function_literal).is_err());
+  }
+
+  #[test]
+  fn run_ast_json_rejects_literal_without_value() {
+    let missing_value = r#"[{ "Print": { "expression": { "Literal": { "value": null } } } }]"#;
+    assert!(Saturday::new().run_ast_json(missing_value).is_err());
+
+    let json = emit_ast_json("fun f() { return 1 + 2; }".to_string()).unwrap();
+    let mut tree: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let right = &mut tree[0]["Function"]["body"][0]["Return"]["value"]["Binary"]["right"];
+    assert_eq!(right["Literal"]["value"], json!({ "Num": 2.0 }));
+    right["Literal"]["value"] = serde_json::Value::Null;
+    assert!(Saturday::new().run_ast_json(&tree.to_string()).is_err());
+  }
+
+  #[test]
+  fn run_ast_json_rejects_defaults_not_matching_params() {
+    let json = emit_ast_json("fun f(a, b) { print a; } f(1, 2);".to_string()).unwrap();
+    let mut tree: serde_json::Value = serde_json::from_str(&json).unwrap();
+    let defaults = &mut tree[0]["Function"]["defaults"];
+    assert_eq!(defaults.as_array().map(Vec::len), Some(2));
+    defaults.as_array_mut().unwrap().pop();
+    assert!(Saturday::new().run_ast_json(&tree.to_string()).is_err());
+  }
+
+  #[test]
+  fn time_flag_reports_elapsed_after_output() {
+    let output = SharedBuffer::default();
+    let timing = SharedBuffer::default();
+    let saturday = Saturday {
+      interpreter: Interpreter::with_writer(Box::new(output.clone())),
+      timing: Some(RefCell::new(Box::new(timing.clone()))),
+      optimize: false,
+      history: History::default(),
+    };
+    assert!(saturday.run("print 1 + 2;".to_string()).is_ok());
+    assert_eq!(output.contents(), "3\n");
+
+    let timing = timing.contents();
+    let millis = timing
+      .strip_prefix("elapsed: ")
+      .and_then(|rest| rest.strip_suffix(" ms\n"))
+      .unwrap();
+    assert!(millis.parse::<u128>().is_ok());
+  }
+
+  #[test]
+  fn optimize_flag_keeps_program_output() {
+    let source = include_str!("../closure.sd");
+    let plain = captured(|saturday| saturday.run(source.to_string()));
+    let buffer = SharedBuffer::default();
+    let saturday = Saturday {
+      interpreter: Interpreter::with_writer(Box::new(buffer.clone())),
+      timing: None,
+      optimize: true,
+      history: History::default(),
+    };
+    assert!(saturday.run(source.to_string()).is_ok());
+    assert_eq!(buffer.contents(), plain);
   }
 }
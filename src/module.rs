@@ -0,0 +1,52 @@
+use crate::environment::Environment;
+use crate::error::SaturdayResult;
+use crate::object::Object;
+use crate::token::Token;
+use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Formatter;
+use std::rc::Rc;
+
+/// # 模块
+/// `import "file" as name;` 导入的文件，属性就是它的全局变量。
+/// 模块之间的 `==` 比较身份
+#[derive(Debug)]
+pub struct Module {
+  pub name: String,
+  globals: Rc<RefCell<Environment>>,
+}
+
+impl Module {
+  pub fn new(name: String, globals: Rc<RefCell<Environment>>) -> Self {
+    Self { name, globals }
+  }
+
+  pub fn get(&self, name: &Token) -> Result<Object, SaturdayResult> {
+    let globals = self.globals.borrow();
+    globals
+      .slot_of(name)
+      .map(|slot| globals.get_slot(slot))
+      .ok_or_else(|| {
+        SaturdayResult::runtime_error(
+          name,
+          &format!(
+            "Undefined property '{}' in module '{}'.",
+            name.as_string(),
+            self.name
+          ),
+        )
+      })
+  }
+}
+
+impl PartialEq for Module {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
+}
+
+impl fmt::Display for Module {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "<module {}>", self.name)
+  }
+}
@@ -1,27 +1,1252 @@
-use crate::callable::SaturdayCallable;
+use crate::callable::{Callable, SaturdayCallable};
 use crate::error::SaturdayResult;
 use crate::interpreter::Interpreter;
-use crate::object::Object;
+use crate::object::{ListData, MapData, MapKey, Object};
+use crate::saturday_instance::SaturdayInstance;
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::rc::Rc;
 use std::time::SystemTime;
 
+/// 注册到全局环境中的内置函数
+pub fn natives() -> Vec<(&'static str, Rc<dyn SaturdayCallable>)> {
+  vec![
+    ("assert_eq", Rc::new(NativeAssertEq)),
+    ("base64_decode", Rc::new(NativeBase64Decode)),
+    ("base64_encode", Rc::new(NativeBase64Encode)),
+    ("chr", Rc::new(NativeChr)),
+    ("clock", Rc::new(NativeClock)),
+    ("clone", Rc::new(NativeClone)),
+    ("compare", Rc::new(NativeCompare)),
+    ("dict", Rc::new(NativeDict)),
+    ("equals", Rc::new(NativeEquals)),
+    ("filter", Rc::new(NativeFilter)),
+    ("format", Rc::new(NativeFormat)),
+    ("freeze", Rc::new(NativeFreeze)),
+    ("from_bytes", Rc::new(NativeFromBytes)),
+    ("get", Rc::new(NativeGet)),
+    ("has", Rc::new(NativeHas)),
+    ("is_nan", Rc::new(NativeIsNan)),
+    ("keys", Rc::new(NativeKeys)),
+    ("lower", Rc::new(NativeLower)),
+    ("map", Rc::new(NativeMap)),
+    ("max_of", Rc::new(NativeMaxOf)),
+    ("min_of", Rc::new(NativeMinOf)),
+    ("ord", Rc::new(NativeOrd)),
+    ("pop", Rc::new(NativePop)),
+    ("push", Rc::new(NativePush)),
+    ("put", Rc::new(NativePut)),
+    ("read_file", Rc::new(NativeReadFile)),
+    ("reduce", Rc::new(NativeReduce)),
+    ("sort", Rc::new(NativeSort)),
+    ("sort_by", Rc::new(NativeSortBy)),
+    ("split", Rc::new(NativeSplit)),
+    ("str", Rc::new(NativeStr)),
+    ("sum", Rc::new(NativeSum)),
+    ("to_bytes", Rc::new(NativeToBytes)),
+    ("trim", Rc::new(NativeTrim)),
+    ("upper", Rc::new(NativeUpper)),
+    ("values", Rc::new(NativeValues)),
+    ("write", Rc::new(NativeWrite)),
+    ("write_file", Rc::new(NativeWriteFile)),
+  ]
+}
+
+/// # 能力
+/// 决定哪些与外界交互的内置函数会被注册，默认全部开启。
+/// 关闭的内置函数不会被定义，调用时报告普通的未定义变量错误
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Capabilities {
+  /// `read_file`、`write_file` 和 `import`
+  pub file_system: bool,
+  /// `clock`
+  pub clock: bool,
+}
+
+impl Capabilities {
+  /// 不能访问文件系统和时钟，适合运行不受信任的脚本
+  pub fn sandboxed() -> Self {
+    Self {
+      file_system: false,
+      clock: false,
+    }
+  }
+
+  pub fn allows(&self, native: &str) -> bool {
+    match native {
+      "read_file" | "write_file" => self.file_system,
+      "clock" => self.clock,
+      _ => true,
+    }
+  }
+}
+
+impl Default for Capabilities {
+  fn default() -> Self {
+    Self {
+      file_system: true,
+      clock: true,
+    }
+  }
+}
+
+fn list_argument(value: &Object) -> Result<Rc<RefCell<ListData>>, SaturdayResult> {
+  match value {
+    Object::List(list) => Ok(Rc::clone(list)),
+    _ => Err(SaturdayResult::native_error(&format!(
+      "Expected a list but got '{value}'."
+    ))),
+  }
+}
+
+fn map_argument(value: &Object) -> Result<Rc<RefCell<MapData>>, SaturdayResult> {
+  match value {
+    Object::Map(map) => Ok(Rc::clone(map)),
+    _ => Err(SaturdayResult::native_error(&format!(
+      "Expected a map but got '{value}'."
+    ))),
+  }
+}
+
+fn number_argument(value: &Object) -> Result<f64, SaturdayResult> {
+  match value {
+    Object::Num(n) => Ok(*n),
+    _ => Err(SaturdayResult::native_error(&format!(
+      "Expected a number but got '{value}'."
+    ))),
+  }
+}
+
+fn string_argument(value: &Object) -> Result<&str, SaturdayResult> {
+  match value {
+    Object::Str(s) => Ok(s),
+    _ => Err(SaturdayResult::native_error(&format!(
+      "Expected a string but got '{value}'."
+    ))),
+  }
+}
+
+fn compare(left: &Object, right: &Object) -> Result<Ordering, SaturdayResult> {
+  left
+    .partial_cmp(right)
+    .ok_or_else(|| SaturdayResult::native_error(&format!("Can't compare '{left}' with '{right}'.")))
+}
+
+fn bytes_argument(value: &Object) -> Result<Rc<Vec<u8>>, SaturdayResult> {
+  match value {
+    Object::Bytes(bytes) => Ok(Rc::clone(bytes)),
+    _ => Err(SaturdayResult::native_error(&format!(
+      "Expected bytes but got '{value}'."
+    ))),
+  }
+}
+
+/// 能用 `count` 个参数调用的函数
+fn function_argument(value: &Object, count: usize) -> Result<Callable, SaturdayResult> {
+  match value {
+    Object::Func(function)
+      if function.func.min_arity() <= count
+        && (function.func.arity() >= count || function.func.is_variadic()) =>
+    {
+      Ok(function.clone())
+    }
+    _ => Err(SaturdayResult::native_error(&format!(
+      "Expected a function of {} but got '{value}'.",
+      if count == 1 {
+        "one argument"
+      } else {
+        "two arguments"
+      }
+    ))),
+  }
+}
+
+/// # 时钟
+/// `clock()` 读取的时间来源，返回毫秒数，测试中可以替换为固定值
+pub trait Clock {
+  fn now(&self) -> Result<f64, SaturdayResult>;
+}
+
+/// 系统时钟，默认的时间来源
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+  fn now(&self) -> Result<f64, SaturdayResult> {
+    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
+      Ok(n) => Ok(n.as_millis() as f64),
+      Err(e) => Err(SaturdayResult::system_error(&format!(
+        "Clock returned invalid duration: {:?}",
+        e
+      ))),
+    }
+  }
+}
+
+impl<F: Fn() -> f64> Clock for F {
+  fn now(&self) -> Result<f64, SaturdayResult> {
+    Ok(self())
+  }
+}
+
 pub struct NativeClock;
 
 impl SaturdayCallable for NativeClock {
   fn call(
     &self,
-    _interpreter: &Interpreter,
+    interpreter: &Interpreter,
     _arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult> {
-    match SystemTime::now().duration_since(SystemTime::UNIX_EPOCH) {
-      Ok(n) => Ok(Object::Num(n.as_millis() as f64)),
-      Err(e) => Err(SaturdayResult::system_error(&format!(
-        "Clock returned invalid duration: {:?}",
-        e
+    Ok(Object::Num(interpreter.now()?))
+  }
+
+  fn arity(&self) -> usize {
+    0
+  }
+
+  fn name(&self) -> String {
+    "clock".to_string()
+  }
+}
+
+/// # 文件系统
+/// `read_file`、`write_file` 和 `import` 访问的文件系统，测试或沙箱中可以替换
+pub trait FileSystem {
+  fn read(&self, path: &str) -> io::Result<String>;
+  fn write(&self, path: &str, contents: &str) -> io::Result<()>;
+
+  /// `import` 用来识别同一个文件的绝对路径，默认只按字面去掉 `.` 和 `..`
+  fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+      match component {
+        Component::CurDir => {}
+        Component::ParentDir => {
+          normalized.pop();
+        }
+        component => normalized.push(component),
+      }
+    }
+    Ok(normalized)
+  }
+}
+
+/// 本机文件系统，默认使用
+pub struct StdFileSystem;
+
+impl FileSystem for StdFileSystem {
+  fn read(&self, path: &str) -> io::Result<String> {
+    std::fs::read_to_string(path)
+  }
+
+  fn canonicalize(&self, path: &Path) -> io::Result<PathBuf> {
+    std::fs::canonicalize(path)
+  }
+
+  fn write(&self, path: &str, contents: &str) -> io::Result<()> {
+    std::fs::write(path, contents)
+  }
+}
+
+/// `read_file(path)` 读取整个文件，文件不存在时返回 `nil`
+pub struct NativeReadFile;
+
+impl SaturdayCallable for NativeReadFile {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let path = string_argument(&arguments[0])?;
+    match interpreter.file_system()?.read(path) {
+      Ok(contents) => Ok(Object::Str(contents.into())),
+      Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(Object::Nil),
+      Err(e) => Err(SaturdayResult::native_error(&format!(
+        "Can't read '{path}': {e}."
       ))),
     }
   }
 
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "read_file".to_string()
+  }
+}
+
+/// `write_file(path, contents)` 覆盖写入文件，返回是否成功
+pub struct NativeWriteFile;
+
+impl SaturdayCallable for NativeWriteFile {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let path = string_argument(&arguments[0])?;
+    let contents = string_argument(&arguments[1])?;
+    Ok(Object::Bool(
+      interpreter.file_system()?.write(path, contents).is_ok(),
+    ))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "write_file".to_string()
+  }
+}
+
+/// 输出参数但不换行，并返回参数本身
+pub struct NativeWrite;
+
+impl SaturdayCallable for NativeWrite {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let value = arguments.into_iter().next().unwrap_or(Object::Nil);
+    interpreter.write_output(&value.to_string())?;
+    Ok(value)
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "write".to_string()
+  }
+}
+
+/// `clone(x)` 深拷贝列表和实例，其余的值原样返回，拷贝出的列表总是可以修改
+pub struct NativeClone;
+
+impl NativeClone {
+  /// 已经拷贝过的列表和实例按地址记录，保留共享和循环引用
+  fn deep_copy(value: &Object, copies: &mut HashMap<*const (), Object>) -> Object {
+    match value {
+      Object::List(list) => {
+        let key = Rc::as_ptr(list) as *const ();
+        if let Some(copy) = copies.get(&key) {
+          return copy.clone();
+        }
+
+        let copy = Rc::new(RefCell::new(ListData::new(Vec::new())));
+        copies.insert(key, Object::List(Rc::clone(&copy)));
+        let items = list.borrow().items.clone();
+        for item in &items {
+          let item = NativeClone::deep_copy(item, copies);
+          copy.borrow_mut().items.push(item);
+        }
+        Object::List(copy)
+      }
+      Object::Instance(instance) => {
+        let key = Rc::as_ptr(instance) as *const ();
+        if let Some(copy) = copies.get(&key) {
+          return copy.clone();
+        }
+
+        let copy = Rc::new(SaturdayInstance::new(Rc::clone(&instance.class)));
+        copies.insert(key, Object::Instance(Rc::clone(&copy)));
+        for (name, field) in instance.fields() {
+          let field = NativeClone::deep_copy(&field, copies);
+          copy.set_field(name, field);
+        }
+        Object::Instance(copy)
+      }
+      value => value.clone(),
+    }
+  }
+}
+
+impl SaturdayCallable for NativeClone {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(NativeClone::deep_copy(&arguments[0], &mut HashMap::new()))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "clone".to_string()
+  }
+}
+
+/// `compare(a, b)` 返回 -1、0 或 1，不能比较时报错
+pub struct NativeCompare;
+
+impl SaturdayCallable for NativeCompare {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(Object::Num(
+      compare(&arguments[0], &arguments[1])? as i8 as f64
+    ))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "compare".to_string()
+  }
+}
+
+/// `assert_eq(a, b)` 按 `==` 的规则比较，不相等时报错
+pub struct NativeAssertEq;
+
+impl SaturdayCallable for NativeAssertEq {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    if arguments[0] == arguments[1] {
+      Ok(Object::Nil)
+    } else {
+      Err(SaturdayResult::native_error(&format!(
+        "Assertion failed: '{}' is not equal to '{}'.",
+        arguments[0], arguments[1]
+      )))
+    }
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "assert_eq".to_string()
+  }
+}
+
+/// `equals(a, b)` 值相等，实例比较类和字段而不是身份
+pub struct NativeEquals;
+
+impl SaturdayCallable for NativeEquals {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(Object::Bool(match (&arguments[0], &arguments[1]) {
+      (Object::Instance(left), Object::Instance(right)) => left.equals(right),
+      (left, right) => left == right,
+    }))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "equals".to_string()
+  }
+}
+
+/// `is_nan(x)` 判断数字是否为 NaN
+pub struct NativeIsNan;
+
+impl SaturdayCallable for NativeIsNan {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(Object::Bool(number_argument(&arguments[0])?.is_nan()))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "is_nan".to_string()
+  }
+}
+
+/// `freeze(list)` 把列表标记为不可修改，返回列表本身
+pub struct NativeFreeze;
+
+impl SaturdayCallable for NativeFreeze {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    list_argument(&arguments[0])?.borrow_mut().frozen = true;
+    Ok(arguments[0].clone())
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "freeze".to_string()
+  }
+}
+
+/// `push(list, x)` 在列表末尾追加元素，返回列表本身
+pub struct NativePush;
+
+impl SaturdayCallable for NativePush {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let list = list_argument(&arguments[0])?;
+    list.borrow_mut().push(arguments[1].clone())?;
+    Ok(Object::List(list))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "push".to_string()
+  }
+}
+
+/// `pop(list)` 移除并返回最后一个元素，空列表返回 nil
+pub struct NativePop;
+
+impl SaturdayCallable for NativePop {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let list = list_argument(&arguments[0])?;
+    let last = list.borrow_mut().pop()?;
+    Ok(last.unwrap_or(Object::Nil))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "pop".to_string()
+  }
+}
+
+/// `map(list, fn)` 对每个元素调用函数，返回新列表
+pub struct NativeMap;
+
+impl SaturdayCallable for NativeMap {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let items = list_argument(&arguments[0])?.borrow().items.clone();
+    let function = function_argument(&arguments[1], 1)?;
+    let mapped = items
+      .into_iter()
+      .map(|item| interpreter.call_value(&function, vec![item]))
+      .collect::<Result<Vec<Object>, SaturdayResult>>()?;
+    Ok(Object::list(mapped))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "map".to_string()
+  }
+}
+
+/// `filter(list, fn)` 保留函数返回真值的元素，返回新列表
+pub struct NativeFilter;
+
+impl SaturdayCallable for NativeFilter {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let items = list_argument(&arguments[0])?.borrow().items.clone();
+    let function = function_argument(&arguments[1], 1)?;
+    let mut kept = Vec::new();
+    for item in items {
+      let keep = interpreter.call_value(&function, vec![item.clone()])?;
+      if interpreter.is_truthy(&keep) {
+        kept.push(item);
+      }
+    }
+    Ok(Object::list(kept))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "filter".to_string()
+  }
+}
+
+/// `sum(list)` 所有元素的和，元素必须都是数字，空列表返回 0
+pub struct NativeSum;
+
+impl SaturdayCallable for NativeSum {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let list = list_argument(&arguments[0])?;
+    let mut total = 0.0;
+    for item in list.borrow().items.iter() {
+      total += number_argument(item)?;
+    }
+    Ok(Object::Num(total))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "sum".to_string()
+  }
+}
+
+/// `reduce(list, fn, init)` 从 `init` 开始依次调用 `fn(acc, item)`，空列表返回 `init`
+pub struct NativeReduce;
+
+impl SaturdayCallable for NativeReduce {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let items = list_argument(&arguments[0])?.borrow().items.clone();
+    let function = function_argument(&arguments[1], 2)?;
+    items
+      .into_iter()
+      .try_fold(arguments[2].clone(), |acc, item| {
+        interpreter.call_value(&function, vec![acc, item])
+      })
+  }
+
+  fn arity(&self) -> usize {
+    3
+  }
+
+  fn name(&self) -> String {
+    "reduce".to_string()
+  }
+}
+
+/// 按 `compare` 的规则找出最前或最后的元素，相等时保留先出现的，空列表返回 nil
+fn extreme(list: &Object, wanted: Ordering) -> Result<Object, SaturdayResult> {
+  let list = list_argument(list)?;
+  let mut best: Option<&Object> = None;
+  let items = &list.borrow().items;
+  for item in items.iter() {
+    match best {
+      Some(current) if compare(item, current)? != wanted => {}
+      _ => best = Some(item),
+    }
+  }
+  Ok(best.cloned().unwrap_or(Object::Nil))
+}
+
+/// `min_of(list)` 最小的元素，空列表返回 nil
+pub struct NativeMinOf;
+
+impl SaturdayCallable for NativeMinOf {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    extreme(&arguments[0], Ordering::Less)
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "min_of".to_string()
+  }
+}
+
+/// `max_of(list)` 最大的元素，空列表返回 nil
+pub struct NativeMaxOf;
+
+impl SaturdayCallable for NativeMaxOf {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    extreme(&arguments[0], Ordering::Greater)
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "max_of".to_string()
+  }
+}
+
+/// 稳定的归并排序，比较出错时立即停止。
+/// 不用 `Vec::sort_by`，比较函数不满足全序时它可能 panic
+fn merge_sort(
+  mut items: Vec<Object>,
+  compare: &mut dyn FnMut(&Object, &Object) -> Result<Ordering, SaturdayResult>,
+) -> Result<Vec<Object>, SaturdayResult> {
+  if items.len() <= 1 {
+    return Ok(items);
+  }
+
+  let right = merge_sort(items.split_off(items.len() / 2), compare)?;
+  let left = merge_sort(items, compare)?;
+  let mut sorted = Vec::with_capacity(left.len() + right.len());
+  let mut left = left.into_iter().peekable();
+  let mut right = right.into_iter().peekable();
+  while let (Some(l), Some(r)) = (left.peek(), right.peek()) {
+    if compare(l, r)? == Ordering::Greater {
+      sorted.extend(right.next());
+    } else {
+      sorted.extend(left.next());
+    }
+  }
+  sorted.extend(left);
+  sorted.extend(right);
+  Ok(sorted)
+}
+
+/// 排序时不借用列表，比较函数可以读取甚至修改它，排好后再写回
+fn sort_list(
+  list: &Object,
+  compare: &mut dyn FnMut(&Object, &Object) -> Result<Ordering, SaturdayResult>,
+) -> Result<Object, SaturdayResult> {
+  let data = list_argument(list)?;
+  // 冻结的列表不排序，也就不会调用比较函数
+  data.borrow().check_mutable()?;
+  let items = data.borrow().items.clone();
+  let sorted = merge_sort(items, compare)?;
+  data.borrow_mut().replace(sorted)?;
+  Ok(Object::List(data))
+}
+
+/// `sort(list)` 按 `compare` 的规则原地排序，返回列表本身
+pub struct NativeSort;
+
+impl SaturdayCallable for NativeSort {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    sort_list(&arguments[0], &mut compare)
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "sort".to_string()
+  }
+}
+
+/// `sort_by(list, fn)` 用 `fn(a, b)` 的正负决定先后，原地排序，返回列表本身
+pub struct NativeSortBy;
+
+impl SaturdayCallable for NativeSortBy {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let function = function_argument(&arguments[1], 2)?;
+    sort_list(&arguments[0], &mut |a, b| {
+      let order = interpreter.call_value(&function, vec![a.clone(), b.clone()])?;
+      match order {
+        Object::Num(n) if n < 0.0 => Ok(Ordering::Less),
+        Object::Num(n) if n > 0.0 => Ok(Ordering::Greater),
+        Object::Num(_) => Ok(Ordering::Equal),
+        _ => Err(SaturdayResult::native_error(&format!(
+          "Comparator must return a number but got '{order}'."
+        ))),
+      }
+    })
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "sort_by".to_string()
+  }
+}
+
+/// `dict()` 创建一个空映射
+pub struct NativeDict;
+
+impl SaturdayCallable for NativeDict {
+  fn call(&self, _: &Interpreter, _: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(Object::Map(Rc::new(RefCell::new(MapData::default()))))
+  }
+
   fn arity(&self) -> usize {
     0
   }
+
+  fn name(&self) -> String {
+    "dict".to_string()
+  }
+}
+
+/// `put(m, key, value)` 设置键对应的值，返回映射本身
+pub struct NativePut;
+
+impl SaturdayCallable for NativePut {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let map = map_argument(&arguments[0])?;
+    let key = MapKey::from_object(&arguments[1])?;
+    map.borrow_mut().insert(key, arguments[2].clone());
+    Ok(Object::Map(map))
+  }
+
+  fn arity(&self) -> usize {
+    3
+  }
+
+  fn name(&self) -> String {
+    "put".to_string()
+  }
+}
+
+/// `get(m, key)` 返回键对应的值，没有这个键时返回 nil
+pub struct NativeGet;
+
+impl SaturdayCallable for NativeGet {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let map = map_argument(&arguments[0])?;
+    let key = MapKey::from_object(&arguments[1])?;
+    let value = map.borrow().get(&key).cloned();
+    Ok(value.unwrap_or(Object::Nil))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "get".to_string()
+  }
+}
+
+/// `has(m, key)` 判断映射中是否有这个键
+pub struct NativeHas;
+
+impl SaturdayCallable for NativeHas {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let map = map_argument(&arguments[0])?;
+    let key = MapKey::from_object(&arguments[1])?;
+    let has = map.borrow().get(&key).is_some();
+    Ok(Object::Bool(has))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "has".to_string()
+  }
+}
+
+/// `keys(m)` 按插入顺序返回所有键
+pub struct NativeKeys;
+
+impl SaturdayCallable for NativeKeys {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let map = map_argument(&arguments[0])?;
+    let keys = map.borrow().keys().map(|key| key.to_object()).collect();
+    Ok(Object::list(keys))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "keys".to_string()
+  }
+}
+
+/// `values(m)` 按插入顺序返回所有值
+pub struct NativeValues;
+
+impl SaturdayCallable for NativeValues {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let map = map_argument(&arguments[0])?;
+    let values = map.borrow().values().cloned().collect();
+    Ok(Object::list(values))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "values".to_string()
+  }
+}
+
+/// `str(x)` 转为字符串，和 `print` 的输出一致
+pub struct NativeStr;
+
+impl SaturdayCallable for NativeStr {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    Ok(Object::Str(interpreter.stringify(&arguments[0])?.into()))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "str".to_string()
+  }
+}
+
+/// 格式说明中宽度和精度的上限，避免一次分配过大的字符串
+const MAX_FORMAT_WIDTH: usize = 1024;
+
+/// # `format(value, spec)`
+/// 按 `[<>^][宽度][.精度]` 转为字符串。
+/// 精度只能用于数字，保留固定位数的小数；宽度不足时用空格补齐，
+/// 数字默认右对齐，其他值默认左对齐
+pub struct NativeFormat;
+
+impl NativeFormat {
+  fn digits(text: &str) -> Option<usize> {
+    if text.is_empty() || !text.bytes().all(|b| b.is_ascii_digit()) {
+      return None;
+    }
+    text.parse().ok().filter(|n| *n <= MAX_FORMAT_WIDTH)
+  }
+}
+
+impl SaturdayCallable for NativeFormat {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    let spec = string_argument(&arguments[1])?;
+    let invalid = || SaturdayResult::native_error(&format!("Invalid format spec '{spec}'."));
+    let (align, rest) = match spec.chars().next() {
+      Some(c @ ('<' | '>' | '^')) => (Some(c), &spec[1..]),
+      _ => (None, spec),
+    };
+    let (width, precision) = match rest.split_once('.') {
+      Some((width, precision)) => (width, Some(precision)),
+      None => (rest, None),
+    };
+    let width = match width {
+      "" => 0,
+      width => NativeFormat::digits(width).ok_or_else(invalid)?,
+    };
+
+    let value = &arguments[0];
+    let text = match (value, precision) {
+      (Object::Num(n), Some(precision)) => {
+        let precision = NativeFormat::digits(precision).ok_or_else(invalid)?;
+        format!("{:.*}", precision, n)
+      }
+      (_, Some(_)) => return Err(invalid()),
+      (value, None) => interpreter.stringify(value)?,
+    };
+
+    let pad = width.saturating_sub(text.chars().count());
+    let default = if matches!(value, Object::Num(_)) {
+      '>'
+    } else {
+      '<'
+    };
+    let (left, right) = match align.unwrap_or(default) {
+      '<' => (0, pad),
+      '>' => (pad, 0),
+      _ => (pad / 2, pad - pad / 2),
+    };
+    Ok(Object::Str(
+      format!("{}{}{}", " ".repeat(left), text, " ".repeat(right)).into(),
+    ))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "format".to_string()
+  }
+}
+
+/// `ord(s)` 第一个字符的 Unicode 码点
+pub struct NativeOrd;
+
+impl SaturdayCallable for NativeOrd {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    match string_argument(&arguments[0])?.chars().next() {
+      Some(ch) => Ok(Object::Num(ch as u32 as f64)),
+      None => Err(SaturdayResult::native_error(
+        "Can't take ord of an empty string.",
+      )),
+    }
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "ord".to_string()
+  }
+}
+
+/// `chr(n)` 码点对应的单字符字符串，代理项和超出范围的码点会报错
+pub struct NativeChr;
+
+impl SaturdayCallable for NativeChr {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let code = number_argument(&arguments[0])?;
+    let ch = (code.fract() == 0.0 && (0.0..=u32::MAX as f64).contains(&code))
+      .then(|| char::from_u32(code as u32))
+      .flatten()
+      .ok_or_else(|| {
+        SaturdayResult::native_error(&format!("Invalid code point '{}'.", Object::Num(code)))
+      })?;
+    Ok(Object::Str(ch.to_string().into()))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "chr".to_string()
+  }
+}
+
+/// `upper(s)` 转为大写
+pub struct NativeUpper;
+
+impl SaturdayCallable for NativeUpper {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(Object::Str(
+      string_argument(&arguments[0])?.to_uppercase().into(),
+    ))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "upper".to_string()
+  }
+}
+
+/// `lower(s)` 转为小写
+pub struct NativeLower;
+
+impl SaturdayCallable for NativeLower {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(Object::Str(
+      string_argument(&arguments[0])?.to_lowercase().into(),
+    ))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "lower".to_string()
+  }
+}
+
+/// `trim(s)` 去掉首尾空白
+pub struct NativeTrim;
+
+impl SaturdayCallable for NativeTrim {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    Ok(Object::Str(string_argument(&arguments[0])?.trim().into()))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "trim".to_string()
+  }
+}
+
+/// `split(s, sep)` 按分隔符拆分为列表，分隔符为空时拆成单个字符
+pub struct NativeSplit;
+
+impl SaturdayCallable for NativeSplit {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let s = string_argument(&arguments[0])?;
+    let separator = string_argument(&arguments[1])?;
+    let parts: Vec<Object> = if separator.is_empty() {
+      s.chars()
+        .map(|ch| Object::Str(ch.to_string().into()))
+        .collect()
+    } else {
+      s.split(separator)
+        .map(|part| Object::Str(part.into()))
+        .collect()
+    };
+    Ok(Object::list(parts))
+  }
+
+  fn arity(&self) -> usize {
+    2
+  }
+
+  fn name(&self) -> String {
+    "split".to_string()
+  }
+}
+
+/// `to_bytes(s)` 字符串的 UTF-8 字节
+pub struct NativeToBytes;
+
+impl SaturdayCallable for NativeToBytes {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let s = string_argument(&arguments[0])?;
+    Ok(Object::Bytes(Rc::new(s.as_bytes().to_vec())))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "to_bytes".to_string()
+  }
+}
+
+/// `from_bytes(b)` 按 UTF-8 解码为字符串，非法的编码会报错
+pub struct NativeFromBytes;
+
+impl SaturdayCallable for NativeFromBytes {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let bytes = bytes_argument(&arguments[0])?;
+    String::from_utf8(bytes.to_vec())
+      .map(|text| Object::Str(text.into()))
+      .map_err(|_| SaturdayResult::native_error("Bytes are not valid UTF-8."))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "from_bytes".to_string()
+  }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+  b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// 标准字母表，末尾用 `=` 补齐
+fn base64_encode(bytes: &[u8]) -> String {
+  let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+  for chunk in bytes.chunks(3) {
+    let group = chunk
+      .iter()
+      .enumerate()
+      .fold(0u32, |group, (i, b)| group | (*b as u32) << (16 - 8 * i));
+    for i in 0..4 {
+      if i <= chunk.len() {
+        encoded.push(BASE64_ALPHABET[(group >> (18 - 6 * i) & 0x3f) as usize] as char);
+      } else {
+        encoded.push('=');
+      }
+    }
+  }
+  encoded
+}
+
+/// 只接受带补齐的标准格式，长度或字符不对时返回 `None`
+fn base64_decode(text: &str) -> Option<Vec<u8>> {
+  let text = text.as_bytes();
+  if !text.len().is_multiple_of(4) {
+    return None;
+  }
+
+  let mut decoded = Vec::with_capacity(text.len() / 4 * 3);
+  for (n, chunk) in text.chunks(4).enumerate() {
+    let last = n == text.len() / 4 - 1;
+    let padding = chunk.iter().rev().take_while(|c| **c == b'=').count();
+    if padding > 2 || (padding > 0 && !last) {
+      return None;
+    }
+
+    let mut group = 0u32;
+    for (i, c) in chunk[..4 - padding].iter().enumerate() {
+      let value = BASE64_ALPHABET.iter().position(|a| a == c)? as u32;
+      group |= value << (18 - 6 * i);
+    }
+    decoded.extend_from_slice(&group.to_be_bytes()[1..4 - padding]);
+  }
+  Some(decoded)
+}
+
+/// `base64_encode(b)` 把字节编码为 base64 字符串
+pub struct NativeBase64Encode;
+
+impl SaturdayCallable for NativeBase64Encode {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let bytes = bytes_argument(&arguments[0])?;
+    Ok(Object::Str(base64_encode(&bytes).into()))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "base64_encode".to_string()
+  }
+}
+
+/// `base64_decode(s)` 把 base64 字符串解码为字节
+pub struct NativeBase64Decode;
+
+impl SaturdayCallable for NativeBase64Decode {
+  fn call(&self, _: &Interpreter, arguments: Vec<Object>) -> Result<Object, SaturdayResult> {
+    let text = string_argument(&arguments[0])?;
+    base64_decode(text)
+      .map(|bytes| Object::Bytes(Rc::new(bytes)))
+      .ok_or_else(|| SaturdayResult::native_error(&format!("Invalid base64 string '{text}'.")))
+  }
+
+  fn arity(&self) -> usize {
+    1
+  }
+
+  fn name(&self) -> String {
+    "base64_decode".to_string()
+  }
+}
+
+/// 嵌入方注册内置函数时提供的闭包
+pub type NativeFn = Rc<dyn Fn(&Interpreter, Vec<Object>) -> Result<Object, SaturdayResult>>;
+
+/// 由闭包实现的内置函数，见 `Interpreter::define_native`
+pub struct NativeClosure {
+  pub name: String,
+  pub arity: usize,
+  pub function: NativeFn,
+}
+
+impl SaturdayCallable for NativeClosure {
+  fn call(
+    &self,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
+  ) -> Result<Object, SaturdayResult> {
+    (self.function)(interpreter, arguments)
+  }
+
+  fn arity(&self) -> usize {
+    self.arity
+  }
+
+  fn name(&self) -> String {
+    self.name.clone()
+  }
 }
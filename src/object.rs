@@ -1,25 +1,73 @@
 use crate::callable::Callable;
+use crate::error::SaturdayResult;
+use crate::module::Module;
 use crate::saturday_class::SaturdayClass;
 use crate::saturday_instance::SaturdayInstance;
+use serde::{Deserialize, Serialize};
+use std::cell::RefCell;
+use std::cmp::Ordering;
+use std::collections::HashMap;
 use std::fmt;
 use std::fmt::Formatter;
 use std::rc::Rc;
+use std::thread::LocalKey;
 
-#[derive(Debug, Clone, PartialEq)]
+/// 只有执行前会出现在 AST 字面量中的值可以序列化，
+/// 反序列化时遇到其余变体会报错
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub enum Object {
   Num(f64),
-  Str(String),
+  Str(Rc<str>),
   Bool(bool),
+  #[serde(skip)]
   Func(Callable),
+  #[serde(skip)]
   Class(Rc<SaturdayClass>),
+  #[serde(skip)]
   Instance(Rc<SaturdayInstance>),
+  #[serde(skip)]
+  List(Rc<RefCell<ListData>>),
+  #[serde(skip)]
+  Map(Rc<RefCell<MapData>>),
+  #[serde(skip)]
+  Module(Rc<Module>),
+  /// 不可变的字节序列，按内容比较
+  #[serde(skip)]
+  Bytes(Rc<Vec<u8>>),
   Nil,
-  ArithmeticError,
+}
+
+/// 显示字节序列时最多列出的字节数
+const BYTES_SHOWN: usize = 16;
+
+thread_local! {
+  /// 正在显示的列表和映射，再次遇到时显示为 `[...]`、`{...}`
+  static SHOWING: RefCell<Vec<*const ()>> = const { RefCell::new(Vec::new()) };
+  /// 正在比较的列表对和映射对，再次遇到同一对时视为相等
+  static COMPARING: RefCell<Vec<(*const (), *const ())>> = const { RefCell::new(Vec::new()) };
+}
+
+/// 列表和映射可以包含自身，递归显示或比较前先检查 `key` 是否已在处理中
+fn without_cycles<K: PartialEq, T>(
+  stack: &'static LocalKey<RefCell<Vec<K>>>,
+  key: K,
+  on_cycle: impl FnOnce() -> T,
+  body: impl FnOnce() -> T,
+) -> T {
+  if stack.with(|stack| stack.borrow().contains(&key)) {
+    return on_cycle();
+  }
+
+  stack.with(|stack| stack.borrow_mut().push(key));
+  let result = body();
+  stack.with(|stack| stack.borrow_mut().pop());
+  result
 }
 
 impl fmt::Display for Object {
   fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
     match self {
+      Object::Num(x) if x.is_nan() => write!(f, "nan"),
       Object::Num(x) => write!(f, "{x}"),
       Object::Str(x) => write!(f, "{x}"),
       Object::Bool(x) => {
@@ -29,11 +77,263 @@ impl fmt::Display for Object {
           write!(f, "false")
         }
       }
-      Object::Func(_) => write!(f, "<Func>"),
-      Object::Class(c) => write!(f, "<Class {}>", c.to_string()),
-      Object::Instance(i) => write!(f, "{}", i.to_string()),
+      Object::Func(function) => write!(f, "{function}"),
+      Object::Class(c) => write!(f, "<Class {c}>"),
+      Object::Instance(i) => write!(f, "{i}"),
+      Object::List(list) => {
+        let shown = without_cycles(
+          &SHOWING,
+          Rc::as_ptr(list) as *const (),
+          || "[...]".to_string(),
+          || {
+            let items: Vec<String> = list
+              .borrow()
+              .items
+              .iter()
+              .map(|item| item.to_string())
+              .collect();
+            format!("[{}]", items.join(", "))
+          },
+        );
+        write!(f, "{shown}")
+      }
+      Object::Map(map) => {
+        let shown = without_cycles(
+          &SHOWING,
+          Rc::as_ptr(map) as *const (),
+          || "{...}".to_string(),
+          || {
+            let entries: Vec<String> = map
+              .borrow()
+              .iter()
+              .map(|(key, value)| format!("{}: {value}", key.to_object()))
+              .collect();
+            format!("{{{}}}", entries.join(", "))
+          },
+        );
+        write!(f, "{shown}")
+      }
+      Object::Module(module) => write!(f, "{module}"),
+      Object::Bytes(bytes) => {
+        // 太长时只显示开头的字节和总长度
+        let hex: String = bytes
+          .iter()
+          .take(BYTES_SHOWN)
+          .map(|b| format!("{b:02x}"))
+          .collect();
+        if bytes.len() > BYTES_SHOWN {
+          write!(f, "<bytes {hex}... ({} bytes)>", bytes.len())
+        } else {
+          write!(f, "<bytes {hex}>")
+        }
+      }
       Object::Nil => write!(f, "nil"),
-      Object::ArithmeticError => panic!("Should not be trying to print this"),
+    }
+  }
+}
+
+/// # 数字相等
+/// 设置了容差时，两数之差的绝对值不超过容差即视为相等。
+/// 容差让 `0.1 + 0.2 == 0.3` 成立，代价是相等不再具有传递性，
+/// 所以只用于 `==`、`!=`，`equals` 和表的键仍然精确比较
+pub fn numbers_equal(left: f64, right: f64, epsilon: Option<f64>) -> bool {
+  match epsilon {
+    Some(epsilon) => left == right || (left - right).abs() <= epsilon,
+    None => left == right,
+  }
+}
+
+impl Object {
+  pub fn list(items: Vec<Object>) -> Object {
+    Object::List(Rc::new(RefCell::new(ListData::new(items))))
+  }
+
+  /// 错误信息中使用的类型名
+  pub fn type_name(&self) -> &'static str {
+    match self {
+      Object::Num(_) => "num",
+      Object::Str(_) => "str",
+      Object::Bool(_) => "bool",
+      Object::Func(_) => "function",
+      Object::Class(_) => "class",
+      Object::Instance(_) => "instance",
+      Object::List(_) => "list",
+      Object::Map(_) => "map",
+      Object::Module(_) => "module",
+      Object::Bytes(_) => "bytes",
+      Object::Nil => "nil",
+    }
+  }
+}
+
+/// # 列表
+/// 冻结后 `push`、`pop` 等修改操作会报错
+#[derive(Debug, Clone, Default)]
+pub struct ListData {
+  pub items: Vec<Object>,
+  pub frozen: bool,
+}
+
+impl ListData {
+  pub fn new(items: Vec<Object>) -> Self {
+    Self {
+      items,
+      frozen: false,
+    }
+  }
+
+  pub fn push(&mut self, item: Object) -> Result<(), SaturdayResult> {
+    self.check_mutable()?;
+    self.items.push(item);
+    Ok(())
+  }
+
+  pub fn pop(&mut self) -> Result<Option<Object>, SaturdayResult> {
+    self.check_mutable()?;
+    Ok(self.items.pop())
+  }
+
+  /// 用新的元素替换全部内容，`sort` 排序后写回
+  pub fn replace(&mut self, items: Vec<Object>) -> Result<(), SaturdayResult> {
+    self.check_mutable()?;
+    self.items = items;
+    Ok(())
+  }
+
+  pub(crate) fn check_mutable(&self) -> Result<(), SaturdayResult> {
+    if self.frozen {
+      Err(SaturdayResult::native_error("Can't modify a frozen list."))
+    } else {
+      Ok(())
+    }
+  }
+}
+
+/// # 映射的键
+/// 只有数字、字符串、布尔值和 nil 可以作为键，
+/// 数字按位保存，`0` 和 `-0` 视为同一个键
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum MapKey {
+  Num(u64),
+  Str(Rc<str>),
+  Bool(bool),
+  Nil,
+}
+
+impl MapKey {
+  pub fn from_object(value: &Object) -> Result<MapKey, SaturdayResult> {
+    match value {
+      Object::Num(n) if n.is_nan() => {
+        Err(SaturdayResult::native_error("Can't use nan as a map key."))
+      }
+      Object::Num(n) if *n == 0.0 => Ok(MapKey::Num(0.0f64.to_bits())),
+      Object::Num(n) => Ok(MapKey::Num(n.to_bits())),
+      Object::Str(s) => Ok(MapKey::Str(s.clone())),
+      Object::Bool(b) => Ok(MapKey::Bool(*b)),
+      Object::Nil => Ok(MapKey::Nil),
+      _ => Err(SaturdayResult::native_error(&format!(
+        "Can't use '{value}' as a map key."
+      ))),
+    }
+  }
+
+  pub fn to_object(&self) -> Object {
+    match self {
+      MapKey::Num(bits) => Object::Num(f64::from_bits(*bits)),
+      MapKey::Str(s) => Object::Str(s.clone()),
+      MapKey::Bool(b) => Object::Bool(*b),
+      MapKey::Nil => Object::Nil,
+    }
+  }
+}
+
+/// # 映射
+/// 按插入顺序保存，`keys`、`values` 也按这个顺序返回
+#[derive(Debug, Clone, Default)]
+pub struct MapData {
+  entries: Vec<(MapKey, Object)>,
+  index: HashMap<MapKey, usize>,
+}
+
+impl MapData {
+  /// 按插入顺序遍历所有键值对
+  pub fn iter(&self) -> impl Iterator<Item = (&MapKey, &Object)> {
+    self.entries.iter().map(|(key, value)| (key, value))
+  }
+
+  pub fn keys(&self) -> impl Iterator<Item = &MapKey> {
+    self.entries.iter().map(|(key, _)| key)
+  }
+
+  pub fn values(&self) -> impl Iterator<Item = &Object> {
+    self.entries.iter().map(|(_, value)| value)
+  }
+
+  pub fn len(&self) -> usize {
+    self.entries.len()
+  }
+
+  pub fn is_empty(&self) -> bool {
+    self.entries.is_empty()
+  }
+
+  pub fn get(&self, key: &MapKey) -> Option<&Object> {
+    self.index.get(key).map(|&i| &self.entries[i].1)
+  }
+
+  /// 已有的键原地覆盖，不改变顺序
+  pub fn insert(&mut self, key: MapKey, value: Object) {
+    match self.index.get(&key) {
+      Some(&i) => self.entries[i].1 = value,
+      None => {
+        self.index.insert(key.clone(), self.entries.len());
+        self.entries.push((key, value));
+      }
+    }
+  }
+}
+
+/// 列表只比较元素，是否冻结不影响相等
+impl PartialEq for ListData {
+  fn eq(&self, other: &Self) -> bool {
+    let key = (
+      self as *const Self as *const (),
+      other as *const Self as *const (),
+    );
+    without_cycles(&COMPARING, key, || true, || self.items == other.items)
+  }
+}
+
+/// 映射的相等与插入顺序无关，只要键相同且对应的值相等
+impl PartialEq for MapData {
+  fn eq(&self, other: &Self) -> bool {
+    let key = (
+      self as *const Self as *const (),
+      other as *const Self as *const (),
+    );
+    without_cycles(
+      &COMPARING,
+      key,
+      || true,
+      || {
+        self.len() == other.len()
+          && self
+            .iter()
+            .all(|(key, value)| other.get(key) == Some(value))
+      },
+    )
+  }
+}
+
+/// 只有同类型的数字、字符串、布尔值之间可以比较大小，
+/// 其余即使相等也不可比较，所以 `nil <= nil` 会报错
+impl PartialOrd for Object {
+  fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+    match (self, other) {
+      (Object::Num(left), Object::Num(right)) => left.partial_cmp(right),
+      (Object::Str(left), Object::Str(right)) => Some(left.cmp(right)),
+      (Object::Bool(left), Object::Bool(right)) => Some(left.cmp(right)),
+      _ => None,
     }
   }
 }
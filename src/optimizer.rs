@@ -0,0 +1,562 @@
+use crate::error::SaturdayResult;
+use crate::expr::{
+  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr, InterpolationExpr,
+  LambdaExpr, LiteralExpr, LogicalExpr, PostfixExpr, SetExpr, SuperExpr, ThisExpr, UnaryExpr,
+  VariableExpr,
+};
+use crate::interpreter::Interpreter;
+use crate::object::{numbers_equal, Object};
+use crate::stmt::{
+  AssertStmt, BlockStmt, BreakStmt, ClassStmt, ConstStmt, ContinueStmt, DefStmt, DoStmt,
+  ExpressionStmt, ForEachStmt, FunctionStmt, GlobalStmt, IfStmt, ImportStmt, PrintStmt, RepeatStmt,
+  ReturnStmt, Stmt, StmtVisitor, ThrowStmt, TryStmt, UndefStmt, WhileStmt,
+};
+use crate::token_type::TokenType;
+use std::rc::Rc;
+
+/// # 常量折叠
+/// 把只由字面量组成的子表达式在执行前计算出来，生成新的语法树。
+/// 会在运行时报错或产生不同结果的表达式（例如除以零）保持原样。
+///
+/// 解析器按节点地址记录变量的作用域，所以需要在解析作用域之前进行折叠
+pub struct Optimizer {
+  strict_logical: bool,
+  float_epsilon: Option<f64>,
+}
+
+impl Optimizer {
+  pub fn new(interpreter: &Interpreter) -> Self {
+    Self {
+      strict_logical: interpreter.strict_logical(),
+      float_epsilon: interpreter.float_epsilon(),
+    }
+  }
+
+  pub fn optimize(&self, statements: &[Rc<Stmt>]) -> Result<Vec<Rc<Stmt>>, SaturdayResult> {
+    statements
+      .iter()
+      .map(|statement| self.optimize_stmt(statement))
+      .collect()
+  }
+
+  fn optimize_stmt(&self, stmt: &Rc<Stmt>) -> Result<Rc<Stmt>, SaturdayResult> {
+    stmt.accept(stmt.clone(), self)
+  }
+
+  fn optimize_expr(&self, expr: &Rc<Expr>) -> Result<Rc<Expr>, SaturdayResult> {
+    expr.accept(expr.clone(), self)
+  }
+
+  fn optimize_block(&self, statements: &[Rc<Stmt>]) -> Result<Rc<Vec<Rc<Stmt>>>, SaturdayResult> {
+    Ok(Rc::new(self.optimize(statements)?))
+  }
+
+  fn optimize_option(&self, expr: &Option<Rc<Expr>>) -> Result<Option<Rc<Expr>>, SaturdayResult> {
+    expr
+      .as_ref()
+      .map(|expr| self.optimize_expr(expr))
+      .transpose()
+  }
+
+  /// 具名函数、方法和匿名函数共用
+  fn optimize_function(&self, function: &FunctionStmt) -> Result<FunctionStmt, SaturdayResult> {
+    Ok(FunctionStmt {
+      name: function.name.clone(),
+      params: function.params.clone(),
+      defaults: Rc::new(
+        function
+          .defaults
+          .iter()
+          .map(|default| self.optimize_option(default))
+          .collect::<Result<_, _>>()?,
+      ),
+      rest: function.rest.clone(),
+      body: self.optimize_block(&function.body)?,
+      getter: function.getter,
+    })
+  }
+
+  fn literal(value: Object) -> Rc<Expr> {
+    Rc::new(Expr::Literal(Rc::new(LiteralExpr { value: Some(value) })))
+  }
+
+  fn literal_value(expr: &Expr) -> Option<&Object> {
+    match expr {
+      Expr::Literal(literal) => literal.value.as_ref(),
+      _ => None,
+    }
+  }
+
+  fn is_truthy(value: &Object) -> bool {
+    !matches!(value, Object::Nil | Object::Bool(false))
+  }
+
+  /// 两个字面量之间的二元运算，不确定结果和运行时一致时返回 `None`
+  fn fold_binary(&self, op: TokenType, left: &Object, right: &Object) -> Option<Object> {
+    match (left, right) {
+      (Object::Num(l), Object::Num(r)) => match op {
+        TokenType::Plus => Some(Object::Num(l + r)),
+        TokenType::Minus => Some(Object::Num(l - r)),
+        TokenType::Star => Some(Object::Num(l * r)),
+        TokenType::Slash if *r != 0.0 => Some(Object::Num(l / r)),
+        TokenType::StarStar => Some(Object::Num(l.powf(*r))),
+        TokenType::Greater => Some(Object::Bool(l > r)),
+        TokenType::GreaterEqual => Some(Object::Bool(l >= r)),
+        TokenType::Less => Some(Object::Bool(l < r)),
+        TokenType::LessEqual => Some(Object::Bool(l <= r)),
+        TokenType::Equal => Some(Object::Bool(numbers_equal(*l, *r, self.float_epsilon))),
+        TokenType::BangEqual => Some(Object::Bool(!numbers_equal(*l, *r, self.float_epsilon))),
+        _ => None,
+      },
+      (Object::Str(l), Object::Str(r)) if op == TokenType::Plus => {
+        Some(Object::Str(format!("{l}{r}").into()))
+      }
+      _ => match op {
+        TokenType::Equal => Some(Object::Bool(left == right)),
+        TokenType::BangEqual => Some(Object::Bool(left != right)),
+        _ => None,
+      },
+    }
+  }
+}
+
+impl ExprVisitor<Rc<Expr>> for Optimizer {
+  fn visit_assign_expr(&self, _: Rc<Expr>, expr: &AssignExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(Rc::new(Expr::Assign(Rc::new(AssignExpr {
+      name: expr.name.clone(),
+      value: self.optimize_expr(&expr.value)?,
+    }))))
+  }
+
+  fn visit_binary_expr(&self, _: Rc<Expr>, expr: &BinaryExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    let left = self.optimize_expr(&expr.left)?;
+    let right = self.optimize_expr(&expr.right)?;
+    if let (Some(l), Some(r)) = (
+      Optimizer::literal_value(&left),
+      Optimizer::literal_value(&right),
+    ) {
+      if let Some(value) = self.fold_binary(expr.operator.token_type(), l, r) {
+        return Ok(Optimizer::literal(value));
+      }
+    }
+
+    Ok(Rc::new(Expr::Binary(Rc::new(BinaryExpr {
+      left,
+      operator: expr.operator.clone(),
+      right,
+    }))))
+  }
+
+  fn visit_call_expr(&self, _: Rc<Expr>, expr: &CallExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(Rc::new(Expr::Call(Rc::new(CallExpr {
+      callee: self.optimize_expr(&expr.callee)?,
+      paren: expr.paren.clone(),
+      arguments: expr
+        .arguments
+        .iter()
+        .map(|argument| self.optimize_expr(argument))
+        .collect::<Result<_, _>>()?,
+    }))))
+  }
+
+  fn visit_get_expr(&self, _: Rc<Expr>, expr: &GetExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(Rc::new(Expr::Get(Rc::new(GetExpr {
+      object: self.optimize_expr(&expr.object)?,
+      name: expr.name.clone(),
+    }))))
+  }
+
+  fn visit_grouping_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &GroupingExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    let expression = self.optimize_expr(&expr.expression)?;
+    if Optimizer::literal_value(&expression).is_some() {
+      return Ok(expression);
+    }
+
+    Ok(Rc::new(Expr::Grouping(Rc::new(GroupingExpr {
+      expression,
+    }))))
+  }
+
+  fn visit_interpolation_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &InterpolationExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(Rc::new(Expr::Interpolation(Rc::new(InterpolationExpr {
+      parts: expr
+        .parts
+        .iter()
+        .map(|part| self.optimize_expr(part))
+        .collect::<Result<_, _>>()?,
+    }))))
+  }
+
+  fn visit_literal_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    _: &LiteralExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  /// 左边是字面量时可以直接决定短路与否。
+  /// 严格模式下结果还要转成布尔值，只在两边都是字面量时折叠
+  fn visit_logical_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &LogicalExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    let left = self.optimize_expr(&expr.left)?;
+    let right = self.optimize_expr(&expr.right)?;
+    if let Some(value) = Optimizer::literal_value(&left) {
+      let op = expr.operator.token_type();
+      let short_circuit = match op {
+        TokenType::QuestionQuestion => *value != Object::Nil,
+        TokenType::Or => Optimizer::is_truthy(value),
+        _ => !Optimizer::is_truthy(value),
+      };
+      let result = if short_circuit {
+        left.clone()
+      } else {
+        right.clone()
+      };
+
+      if op == TokenType::QuestionQuestion || !self.strict_logical {
+        return Ok(result);
+      }
+      if let Some(value) = Optimizer::literal_value(&result) {
+        return Ok(Optimizer::literal(Object::Bool(Optimizer::is_truthy(
+          value,
+        ))));
+      }
+    }
+
+    Ok(Rc::new(Expr::Logical(Rc::new(LogicalExpr {
+      left,
+      operator: expr.operator.clone(),
+      right,
+    }))))
+  }
+
+  fn visit_lambda_expr(&self, _: Rc<Expr>, expr: &LambdaExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(Rc::new(Expr::Lambda(Rc::new(LambdaExpr {
+      keyword: expr.keyword.clone(),
+      function: Rc::new(self.optimize_function(&expr.function)?),
+    }))))
+  }
+
+  fn visit_super_expr(&self, wrapper: Rc<Expr>, _: &SuperExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_this_expr(&self, wrapper: Rc<Expr>, _: &ThisExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(Rc::new(Expr::Set(Rc::new(SetExpr {
+      object: self.optimize_expr(&expr.object)?,
+      name: expr.name.clone(),
+      operator: expr.operator.clone(),
+      value: self.optimize_expr(&expr.value)?,
+    }))))
+  }
+
+  fn visit_unary_expr(&self, _: Rc<Expr>, expr: &UnaryExpr) -> Result<Rc<Expr>, SaturdayResult> {
+    let right = self.optimize_expr(&expr.right)?;
+    match (expr.operator.token_type(), Optimizer::literal_value(&right)) {
+      (TokenType::Minus, Some(Object::Num(n))) => Ok(Optimizer::literal(Object::Num(-n))),
+      (TokenType::Bang, Some(value)) => Ok(Optimizer::literal(Object::Bool(
+        !Optimizer::is_truthy(value),
+      ))),
+      _ => Ok(Rc::new(Expr::Unary(Rc::new(UnaryExpr {
+        operator: expr.operator.clone(),
+        right,
+      })))),
+    }
+  }
+
+  fn visit_postfix_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    _: &PostfixExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_variable_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    _: &VariableExpr,
+  ) -> Result<Rc<Expr>, SaturdayResult> {
+    Ok(wrapper)
+  }
+}
+
+impl StmtVisitor<Rc<Stmt>> for Optimizer {
+  fn visit_assert_stmt(&self, _: Rc<Stmt>, stmt: &AssertStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Assert(Rc::new(AssertStmt {
+      keyword: stmt.keyword.clone(),
+      condition: self.optimize_expr(&stmt.condition)?,
+    }))))
+  }
+
+  fn visit_block_stmt(&self, _: Rc<Stmt>, stmt: &BlockStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Block(Rc::new(BlockStmt {
+      statements: self.optimize_block(&stmt.statements)?,
+    }))))
+  }
+
+  fn visit_class_stmt(&self, _: Rc<Stmt>, stmt: &ClassStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Class(Rc::new(ClassStmt {
+      name: stmt.name.clone(),
+      superclass: stmt.superclass.clone(),
+      methods: self.optimize_block(&stmt.methods)?,
+      statics: self.optimize_block(&stmt.statics)?,
+    }))))
+  }
+
+  fn visit_break_stmt(&self, wrapper: Rc<Stmt>, _: &BreakStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_continue_stmt(
+    &self,
+    wrapper: Rc<Stmt>,
+    _: &ContinueStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_const_stmt(&self, _: Rc<Stmt>, stmt: &ConstStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Const(Rc::new(ConstStmt {
+      name: stmt.name.clone(),
+      initializer: self.optimize_expr(&stmt.initializer)?,
+    }))))
+  }
+
+  fn visit_do_stmt(&self, _: Rc<Stmt>, stmt: &DoStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Do(Rc::new(DoStmt {
+      keyword: stmt.keyword.clone(),
+      body: self.optimize_stmt(&stmt.body)?,
+      condition: self.optimize_expr(&stmt.condition)?,
+      label: stmt.label.clone(),
+    }))))
+  }
+
+  fn visit_expression_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &ExpressionStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
+      expression: self.optimize_expr(&stmt.expression)?,
+    }))))
+  }
+
+  fn visit_foreach_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &ForEachStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::ForEach(Rc::new(ForEachStmt {
+      name: stmt.name.clone(),
+      iterable: self.optimize_expr(&stmt.iterable)?,
+      body: self.optimize_stmt(&stmt.body)?,
+      label: stmt.label.clone(),
+    }))))
+  }
+
+  fn visit_function_stmt(
+    &self,
+    _: Rc<Stmt>,
+    stmt: &FunctionStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Function(Rc::new(
+      self.optimize_function(stmt)?,
+    ))))
+  }
+
+  fn visit_if_stmt(&self, _: Rc<Stmt>, stmt: &IfStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::If(Rc::new(IfStmt {
+      keyword: stmt.keyword.clone(),
+      condition: self.optimize_expr(&stmt.condition)?,
+      then_branch: self.optimize_stmt(&stmt.then_branch)?,
+      else_branch: stmt
+        .else_branch
+        .as_ref()
+        .map(|branch| self.optimize_stmt(branch))
+        .transpose()?,
+    }))))
+  }
+
+  fn visit_import_stmt(
+    &self,
+    wrapper: Rc<Stmt>,
+    _: &ImportStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_print_stmt(&self, _: Rc<Stmt>, stmt: &PrintStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Print(Rc::new(PrintStmt {
+      expression: self.optimize_expr(&stmt.expression)?,
+    }))))
+  }
+
+  fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Return(Rc::new(ReturnStmt {
+      keyword: stmt.keyword.clone(),
+      value: self.optimize_option(&stmt.value)?,
+    }))))
+  }
+
+  fn visit_undef_stmt(&self, wrapper: Rc<Stmt>, _: &UndefStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_global_stmt(
+    &self,
+    wrapper: Rc<Stmt>,
+    _: &GlobalStmt,
+  ) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(wrapper)
+  }
+
+  fn visit_throw_stmt(&self, _: Rc<Stmt>, stmt: &ThrowStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Throw(Rc::new(ThrowStmt {
+      keyword: stmt.keyword.clone(),
+      value: self.optimize_expr(&stmt.value)?,
+    }))))
+  }
+
+  fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Try(Rc::new(TryStmt {
+      body: self.optimize_block(&stmt.body)?,
+      name: stmt.name.clone(),
+      handler: self.optimize_block(&stmt.handler)?,
+    }))))
+  }
+
+  fn visit_def_stmt(&self, _: Rc<Stmt>, stmt: &DefStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Def(Rc::new(DefStmt {
+      name: stmt.name.clone(),
+      initializer: self.optimize_option(&stmt.initializer)?,
+    }))))
+  }
+
+  fn visit_repeat_stmt(&self, _: Rc<Stmt>, stmt: &RepeatStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::Repeat(Rc::new(RepeatStmt {
+      keyword: stmt.keyword.clone(),
+      count: self.optimize_expr(&stmt.count)?,
+      body: self.optimize_stmt(&stmt.body)?,
+      label: stmt.label.clone(),
+    }))))
+  }
+
+  fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<Rc<Stmt>, SaturdayResult> {
+    Ok(Rc::new(Stmt::While(Rc::new(WhileStmt {
+      keyword: stmt.keyword.clone(),
+      condition: self.optimize_expr(&stmt.condition)?,
+      body: self.optimize_stmt(&stmt.body)?,
+      increment: self.optimize_option(&stmt.increment)?,
+      label: stmt.label.clone(),
+    }))))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::Parser;
+  use crate::scanner::Scanner;
+  use crate::token::Token;
+
+  fn optimized(source: &str) -> Vec<Rc<Stmt>> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Parser::new(tokens).parse().ok().unwrap();
+    Optimizer::new(&Interpreter::new())
+      .optimize(&statements)
+      .unwrap()
+  }
+
+  fn printed(statement: &Stmt) -> Rc<Expr> {
+    match statement {
+      Stmt::Print(print) => print.expression.clone(),
+      _ => panic!("expected a print statement"),
+    }
+  }
+
+  fn value(expr: &Rc<Expr>) -> Option<Object> {
+    Optimizer::literal_value(expr).cloned()
+  }
+
+  #[test]
+  fn folds_constant_arithmetic() {
+    let statements = optimized(
+      "print 2 + 3 * 4;
+      print -(1 - 3) * 2;
+      print \"a\" + \"b\" == \"ab\";
+      print !nil;",
+    );
+    assert_eq!(value(&printed(&statements[0])), Some(Object::Num(14.0)));
+    assert_eq!(value(&printed(&statements[1])), Some(Object::Num(4.0)));
+    assert_eq!(value(&printed(&statements[2])), Some(Object::Bool(true)));
+    assert_eq!(value(&printed(&statements[3])), Some(Object::Bool(true)));
+  }
+
+  #[test]
+  fn simplifies_logical_with_constant_left() {
+    let statements = optimized("print true and x; print false and x; print nil ?? x;");
+    assert!(matches!(&*printed(&statements[0]), Expr::Variable(_)));
+    assert_eq!(value(&printed(&statements[1])), Some(Object::Bool(false)));
+    assert!(matches!(&*printed(&statements[2]), Expr::Variable(_)));
+  }
+
+  #[test]
+  fn keeps_expressions_that_are_not_constant() {
+    let statements = optimized("print 1 / 0; print 1 + true; print (1 + 2) * f(4 - 1);");
+    assert!(matches!(&*printed(&statements[0]), Expr::Binary(_)));
+    assert!(matches!(&*printed(&statements[1]), Expr::Binary(_)));
+
+    let Expr::Binary(binary) = &*printed(&statements[2]) else {
+      panic!("expected a binary expression");
+    };
+    assert_eq!(value(&binary.left), Some(Object::Num(3.0)));
+    let Expr::Call(call) = &*binary.right else {
+      panic!("expected the call to be kept");
+    };
+    assert_eq!(value(&call.arguments[0]), Some(Object::Num(3.0)));
+  }
+
+  #[test]
+  fn optimized_program_runs_the_same() {
+    let source = "def calls = 0;
+      fun f(x) { calls = calls + 1; return x; }
+      def a = f(1) and 2 * 3;
+      def b = false and f(2);
+      def c = 10 / 0;
+      fun g() { def y = 2 + 2; return y * f(1); }
+      def d = g();";
+    let statements = optimized(source);
+    let terp = Interpreter::new();
+    let resolver = crate::resolver::Resolver::new(&terp);
+    let statements = Rc::new(statements);
+    resolver.resolve(&statements).unwrap();
+    terp.interpreter(&statements).unwrap();
+    let global = |name: &str| {
+      let token = Token::new(TokenType::Identifier, name.to_string(), None, 0, 0);
+      terp.globals.borrow().get(&token).ok().unwrap()
+    };
+    assert_eq!(global("a"), Object::Num(6.0));
+    assert_eq!(global("b"), Object::Bool(false));
+    assert_eq!(global("c"), Object::Num(f64::INFINITY));
+    assert_eq!(global("d"), Object::Num(4.0));
+    assert_eq!(global("calls"), Object::Num(2.0));
+  }
+}
@@ -1,21 +1,56 @@
 use crate::expr::{
-  AssignExpr, BinaryExpr, CallExpr, Expr, GetExpr, GroupingExpr, LiteralExpr, LogicalExpr, SetExpr,
-  UnaryExpr, VariableExpr,
+  AssignExpr, BinaryExpr, CallExpr, Expr, GetExpr, GroupingExpr, InterpolationExpr, LambdaExpr,
+  LiteralExpr, LogicalExpr, PostfixExpr, SetExpr, SuperExpr, ThisExpr, UnaryExpr, VariableExpr,
 };
 use crate::object::Object;
+use crate::span::{Span, Spans};
 use crate::stmt::{
-  BlockStmt, BreakStmt, ClassStmt, DefStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt,
-  ReturnStmt, Stmt, WhileStmt,
+  AssertStmt, BlockStmt, BreakStmt, ClassStmt, ConstStmt, ContinueStmt, DefStmt, DoStmt,
+  ExpressionStmt, ForEachStmt, FunctionStmt, GlobalStmt, IfStmt, ImportStmt, PrintStmt, RepeatStmt,
+  ReturnStmt, Stmt, ThrowStmt, TryStmt, UndefStmt, WhileStmt,
 };
 use crate::token::Token;
 use crate::token_type::*;
 use crate::SaturdayResult;
 use std::rc::Rc;
 
+/// 出错后同步到这些关键字，它们通常开始一条新语句
+const STATEMENT_STARTS: &[TokenType] = &[
+  TokenType::Class,
+  TokenType::Const,
+  TokenType::Fun,
+  TokenType::Var,
+  TokenType::Def,
+  TokenType::Do,
+  TokenType::For,
+  TokenType::If,
+  TokenType::While,
+  TokenType::Print,
+  TokenType::Return,
+  TokenType::Throw,
+  TokenType::Try,
+  TokenType::Assert,
+  TokenType::Import,
+  TokenType::Repeat,
+  TokenType::Undef,
+  TokenType::Global,
+  TokenType::Break,
+  TokenType::Continue,
+];
+
+/// 调用实参和函数形参的默认数量上限
+pub const DEFAULT_MAX_ARGUMENTS: usize = 255;
+
 pub struct Parser<'a> {
   tokens: &'a [Token],
   current: usize,
   had_error: bool,
+  /// 为 true 时换行也可以结束语句，默认必须写 `;`
+  newline_terminators: bool,
+  /// 实参和形参的数量上限
+  max_arguments: usize,
+  /// 解析出的每个节点在源码中的范围
+  spans: Spans,
 }
 
 impl<'a> Parser<'a> {
@@ -24,13 +59,43 @@ impl<'a> Parser<'a> {
       tokens,
       current: 0,
       had_error: false,
+      newline_terminators: false,
+      max_arguments: DEFAULT_MAX_ARGUMENTS,
+      spans: Spans::default(),
     }
   }
 
+  /// 调整实参和形参的数量上限，生成的大程序可以调高
+  pub fn set_max_arguments(&mut self, limit: usize) {
+    self.max_arguments = limit;
+  }
+
+  /// 允许用换行代替语句结尾的 `;`
+  pub fn set_newline_terminators(&mut self, enabled: bool) {
+    self.newline_terminators = enabled;
+  }
+
   pub fn success(&self) -> bool {
     !self.had_error
   }
 
+  /// 节点的源码范围，供编辑器把光标位置对应到语法节点
+  pub fn spans(&self) -> &Spans {
+    &self.spans
+  }
+
+  /// 从 `start` 处的 token 到刚消耗的 token
+  fn span_from(&self, start: usize) -> Span {
+    let last = self.current.max(start + 1) - 1;
+    Span::covering(&self.tokens[start], &self.tokens[last])
+  }
+
+  fn spanned(&mut self, start: usize, expr: Expr) -> Expr {
+    let span = self.span_from(start);
+    self.spans.insert_expr(&expr, span);
+    expr
+  }
+
   /// # 解析方法，调用expression解析tokens生成表达式
   pub fn parse(&mut self) -> Result<Vec<Rc<Stmt>>, SaturdayResult> {
     let mut statements = Vec::new();
@@ -41,19 +106,39 @@ impl<'a> Parser<'a> {
     Ok(statements)
   }
 
+  /// # 解析单个表达式
+  /// 表达式之后还有其他输入时报错
+  pub fn parse_expression(&mut self) -> Result<Expr, SaturdayResult> {
+    let expr = self.expression()?;
+    if !self.is_at_end() {
+      return Err(self.error(&self.peek().dup(), "Expect end of expression."));
+    }
+
+    Ok(expr)
+  }
+
   fn declaration(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let start = self.current;
     let result = if self.is_match(&[TokenType::Class]) {
       self.class_declaration()
-    } else if self.is_match(&[TokenType::Fun]) {
+    } else if self.check(TokenType::Fun) && self.check_next(TokenType::Identifier) {
+      // 没有名字的 `fun` 是匿名函数表达式
+      self.advance();
       self.function("function")
     } else if self.is_match(&[TokenType::Def]) {
       self.def_declaration()
+    } else if self.is_match(&[TokenType::Const]) {
+      self.const_declaration()
     } else {
       self.statement()
     };
 
-    if result.is_err() {
-      self.synchronize();
+    match &result {
+      Ok(stmt) => {
+        let span = self.span_from(start);
+        self.spans.insert_stmt(stmt, span);
+      }
+      Err(_) => self.synchronize(),
     }
 
     result
@@ -67,14 +152,93 @@ impl<'a> Parser<'a> {
       None
     };
 
-    self.consume(
-      TokenType::SemiColon,
-      "Expect ';' after variable declaration",
-    )?;
+    self.terminator("Expect ';' after variable declaration")?;
     Ok(Rc::new(Stmt::Def(Rc::new(DefStmt { name, initializer }))))
   }
 
-  fn while_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+  /// `const name = expr;` 常量必须有初始值，且不能重新赋值
+  fn const_declaration(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let name = self.consume(TokenType::Identifier, "Expect constant name.")?;
+    self.consume(TokenType::Assign, "Expect '=' after constant name.")?;
+    let initializer = Rc::new(self.expression()?);
+    self.terminator("Expect ';' after constant declaration")?;
+    Ok(Rc::new(Stmt::Const(Rc::new(ConstStmt {
+      name,
+      initializer,
+    }))))
+  }
+
+  fn assert_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let condition = Rc::new(self.expression()?);
+    self.terminator("Expect ';' after assertion.")?;
+    Ok(Stmt::Assert(Rc::new(AssertStmt { keyword, condition })))
+  }
+
+  /// `import "path";` 路径相对于当前文件所在的目录
+  fn import_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let path = self.consume(TokenType::String, "Expect file path after 'import'.")?;
+    let alias = if self.is_match(&[TokenType::As]) {
+      Some(self.consume(TokenType::Identifier, "Expect module name after 'as'.")?)
+    } else {
+      None
+    };
+    self.terminator("Expect ';' after import.")?;
+    Ok(Stmt::Import(Rc::new(ImportStmt {
+      keyword,
+      path,
+      alias,
+    })))
+  }
+
+  fn global_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let name = self.consume(
+      TokenType::Identifier,
+      "Expect variable name after 'global'.",
+    )?;
+    self.terminator("Expect ';' after global.")?;
+    Ok(Stmt::Global(Rc::new(GlobalStmt { keyword, name })))
+  }
+
+  fn undef_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let name = self.consume(TokenType::Identifier, "Expect variable name after 'undef'.")?;
+    self.terminator("Expect ';' after undef.")?;
+    Ok(Stmt::Undef(Rc::new(UndefStmt { keyword, name })))
+  }
+
+  fn throw_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let value = Rc::new(self.expression()?);
+    self.terminator("Expect ';' after thrown value.")?;
+    Ok(Stmt::Throw(Rc::new(ThrowStmt { keyword, value })))
+  }
+
+  /// `try { } catch (e) { }`，catch 变量外的括号可以省略
+  fn try_statement(&mut self) -> Result<Stmt, SaturdayResult> {
+    self.consume(TokenType::LeftBrace, "Expect '{' after 'try'.")?;
+    let body = Rc::new(self.block()?);
+
+    self.consume(TokenType::Catch, "Expect 'catch' after try block.")?;
+    let paren = self.is_match(&[TokenType::LeftParen]);
+    let name = self.consume(TokenType::Identifier, "Expect catch variable name.")?;
+    if paren {
+      self.consume(TokenType::RightParen, "Expect ')' after catch variable.")?;
+    }
+
+    self.consume(TokenType::LeftBrace, "Expect '{' after catch variable.")?;
+    let handler = Rc::new(self.block()?);
+    Ok(Stmt::Try(Rc::new(TryStmt {
+      body,
+      name,
+      handler,
+    })))
+  }
+
+  fn while_statement(&mut self, label: Option<Token>) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
     let condition = Rc::new(self.expression()?);
     if !self.peek().is(TokenType::LeftBrace) {
       return Err(SaturdayResult::parse_error(
@@ -84,7 +248,54 @@ impl<'a> Parser<'a> {
     }
 
     let body = self.statement()?;
-    Ok(Stmt::While(Rc::new(WhileStmt { condition, body })))
+    Ok(Stmt::While(Rc::new(WhileStmt {
+      keyword,
+      condition,
+      body,
+      increment: None,
+      label,
+    })))
+  }
+
+  /// `repeat count { body }` 执行固定次数的循环
+  fn repeat_statement(&mut self, label: Option<Token>) -> Result<Stmt, SaturdayResult> {
+    let keyword = self.previous().dup();
+    let count = Rc::new(self.expression()?);
+    if !self.peek().is(TokenType::LeftBrace) {
+      return Err(SaturdayResult::parse_error(
+        self.peek(),
+        "repeat must wrap by '{}'.",
+      ));
+    }
+
+    let body = self.statement()?;
+    Ok(Stmt::Repeat(Rc::new(RepeatStmt {
+      keyword,
+      count,
+      body,
+      label,
+    })))
+  }
+
+  /// `do { body } while condition;` 循环体至少执行一次
+  fn do_statement(&mut self, label: Option<Token>) -> Result<Stmt, SaturdayResult> {
+    if !self.peek().is(TokenType::LeftBrace) {
+      return Err(SaturdayResult::parse_error(
+        self.peek(),
+        "do must wrap by '{}'.",
+      ));
+    }
+
+    let body = self.statement()?;
+    let keyword = self.consume(TokenType::While, "Expect 'while' after do body.")?;
+    let condition = Rc::new(self.expression()?);
+    self.terminator("Expect ';' after do-while condition.")?;
+    Ok(Stmt::Do(Rc::new(DoStmt {
+      keyword,
+      body,
+      condition,
+      label,
+    })))
   }
 
   fn expression(&mut self) -> Result<Expr, SaturdayResult> {
@@ -93,29 +304,81 @@ impl<'a> Parser<'a> {
 
   fn class_declaration(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
     let name = self.consume(TokenType::Identifier, "Expect class name.")?;
+    let superclass = if self.is_match(&[TokenType::Less]) {
+      let name = self.consume(TokenType::Identifier, "Expect superclass name.")?;
+      Some(Rc::new(Expr::Variable(Rc::new(VariableExpr { name }))))
+    } else {
+      None
+    };
     self.consume(TokenType::LeftBrace, "Expect '{' before class body.")?;
 
     let mut methods = Vec::new();
+    let mut statics = Vec::new();
     while !self.check(TokenType::RightBrace) && !self.is_at_end() {
-      methods.push(self.function("method")?);
+      if self.is_match(&[TokenType::Static]) {
+        statics.push(self.function("static method")?);
+      } else if &*self.peek().lexeme == "get" && self.check_next(TokenType::Identifier) {
+        // `get` 只在后面紧跟名称时表示 getter，仍可以作为普通方法名
+        self.advance();
+        methods.push(self.getter()?);
+      } else {
+        methods.push(self.function("method")?);
+      }
     }
     self.consume(TokenType::RightBrace, "Expect '}' after class body.")?;
 
     Ok(Rc::new(Stmt::Class(Rc::new(ClassStmt {
       name,
+      superclass,
       methods: Rc::new(methods),
+      statics: Rc::new(statics),
     }))))
   }
 
   fn statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let start = self.current;
+    let stmt = self.statement_node()?;
+    let span = self.span_from(start);
+    self.spans.insert_stmt(&stmt, span);
+    Ok(stmt)
+  }
+
+  fn statement_node(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    if self.is_match(&[TokenType::Assert]) {
+      return Ok(Rc::new(self.assert_statement()?));
+    }
+
+    if self.is_match(&[TokenType::Import]) {
+      return Ok(Rc::new(self.import_statement()?));
+    }
+
+    if self.check(TokenType::Identifier) && self.check_next(TokenType::Colon) {
+      return self.labeled_statement();
+    }
+
     if self.is_match(&[TokenType::Break]) {
       let token = self.previous().dup();
-      self.consume(TokenType::SemiColon, "expect ';' after break statement.")?;
-      return Ok(Rc::new(Stmt::Break(Rc::new(BreakStmt { token }))));
+      let label = self.loop_label()?;
+      self.terminator("expect ';' after break statement.")?;
+      return Ok(Rc::new(Stmt::Break(Rc::new(BreakStmt { token, label }))));
+    }
+
+    if self.is_match(&[TokenType::Continue]) {
+      let token = self.previous().dup();
+      let label = self.loop_label()?;
+      self.terminator("expect ';' after continue statement.")?;
+      return Ok(Rc::new(Stmt::Continue(Rc::new(ContinueStmt {
+        token,
+        label,
+      }))));
+    }
+
+    if self.is_match(&[TokenType::Do]) {
+      return Ok(Rc::new(self.do_statement(None)?));
     }
 
     if self.is_match(&[TokenType::For]) {
-      return self.for_statement();
+      return self.for_statement(None);
     }
 
     if self.is_match(&[TokenType::If]) {
@@ -126,12 +389,32 @@ impl<'a> Parser<'a> {
       return Ok(Rc::new(self.print_statement()?));
     }
 
+    if self.is_match(&[TokenType::Repeat]) {
+      return Ok(Rc::new(self.repeat_statement(None)?));
+    }
+
     if self.is_match(&[TokenType::Return]) {
       return Ok(Rc::new(self.return_statement()?));
     }
 
+    if self.is_match(&[TokenType::Throw]) {
+      return Ok(Rc::new(self.throw_statement()?));
+    }
+
+    if self.is_match(&[TokenType::Try]) {
+      return Ok(Rc::new(self.try_statement()?));
+    }
+
+    if self.is_match(&[TokenType::Undef]) {
+      return Ok(Rc::new(self.undef_statement()?));
+    }
+
+    if self.is_match(&[TokenType::Global]) {
+      return Ok(Rc::new(self.global_statement()?));
+    }
+
     if self.is_match(&[TokenType::While]) {
-      return Ok(Rc::new(self.while_statement()?));
+      return Ok(Rc::new(self.while_statement(None)?));
     }
 
     if self.is_match(&[TokenType::LeftBrace]) {
@@ -143,7 +426,39 @@ impl<'a> Parser<'a> {
     self.expression_statement()
   }
 
-  fn for_statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+  /// `label: while ...`，标签只能放在循环语句前
+  fn labeled_statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let label = Some(self.advance().dup());
+    self.consume(TokenType::Colon, "Expect ':' after loop label.")?;
+
+    if self.is_match(&[TokenType::Do]) {
+      Ok(Rc::new(self.do_statement(label)?))
+    } else if self.is_match(&[TokenType::For]) {
+      self.for_statement(label)
+    } else if self.is_match(&[TokenType::Repeat]) {
+      Ok(Rc::new(self.repeat_statement(label)?))
+    } else if self.is_match(&[TokenType::While]) {
+      Ok(Rc::new(self.while_statement(label)?))
+    } else {
+      Err(self.error(&self.peek().dup(), "Expect a loop after label."))
+    }
+  }
+
+  /// `break`/`continue` 之后可选的标签
+  fn loop_label(&mut self) -> Result<Option<Token>, SaturdayResult> {
+    if !self.at_statement_end() && self.is_match(&[TokenType::Identifier]) {
+      Ok(Some(self.previous().dup()))
+    } else {
+      Ok(None)
+    }
+  }
+
+  fn for_statement(&mut self, label: Option<Token>) -> Result<Rc<Stmt>, SaturdayResult> {
+    let keyword = self.previous().dup();
+    if self.check(TokenType::Identifier) && self.check_next(TokenType::In) {
+      return Ok(Rc::new(self.for_each_statement(label)?));
+    }
+
     let initializer = if self.is_match(&[TokenType::SemiColon]) {
       None
     } else if self.is_match(&[TokenType::Def]) {
@@ -165,21 +480,11 @@ impl<'a> Parser<'a> {
       Some(self.expression()?)
     };
 
-    let mut body = self.statement()?;
-    // 执行完逻辑后将条件值增加
-    if let Some(incr) = increment {
-      body = Rc::new(Stmt::Block(Rc::new(BlockStmt {
-        statements: Rc::new(vec![
-          body,
-          Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
-            expression: Rc::new(incr),
-          }))),
-        ]),
-      })));
-    }
+    let body = self.statement()?;
 
-    // 将for循环转换成while
-    body = Rc::new(Stmt::While(Rc::new(WhileStmt {
+    // 将for循环转换成while，每次执行完循环体（包括 continue）后执行 increment
+    let mut body = Rc::new(Stmt::While(Rc::new(WhileStmt {
+      keyword,
       condition: if let Some(cond) = condition {
         Rc::new(cond)
       } else {
@@ -188,6 +493,8 @@ impl<'a> Parser<'a> {
         })))
       },
       body,
+      increment: increment.map(Rc::new),
+      label,
     })));
 
     // 在准备一个block将初始化表达式包裹进去
@@ -200,8 +507,30 @@ impl<'a> Parser<'a> {
     Ok(body)
   }
 
+  /// `for name in list { body }` 遍历列表
+  fn for_each_statement(&mut self, label: Option<Token>) -> Result<Stmt, SaturdayResult> {
+    let name = self.consume(TokenType::Identifier, "Expect loop variable name.")?;
+    self.consume(TokenType::In, "Expect 'in' after loop variable.")?;
+    let iterable = Rc::new(self.expression()?);
+    if !self.peek().is(TokenType::LeftBrace) {
+      return Err(SaturdayResult::parse_error(
+        self.peek(),
+        "for must wrap by '{}'.",
+      ));
+    }
+
+    let body = self.statement()?;
+    Ok(Stmt::ForEach(Rc::new(ForEachStmt {
+      name,
+      iterable,
+      body,
+      label,
+    })))
+  }
+
   fn if_statement(&mut self) -> Result<Stmt, SaturdayResult> {
     // 实现condition不带括号且必须有{的条件语句
+    let keyword = self.previous().dup();
     let condition = Rc::new(self.expression()?);
     if !self.peek().is(TokenType::LeftBrace) {
       return Err(SaturdayResult::parse_error(
@@ -225,6 +554,7 @@ impl<'a> Parser<'a> {
     };
 
     Ok(Stmt::If(Rc::new(IfStmt {
+      keyword,
       condition,
       then_branch,
       else_branch,
@@ -233,25 +563,25 @@ impl<'a> Parser<'a> {
 
   fn print_statement(&mut self) -> Result<Stmt, SaturdayResult> {
     let value = Rc::new(self.expression()?);
-    self.consume(TokenType::SemiColon, "Expect ';' after value.")?;
+    self.terminator("Expect ';' after value.")?;
     Ok(Stmt::Print(Rc::new(PrintStmt { expression: value })))
   }
 
   fn return_statement(&mut self) -> Result<Stmt, SaturdayResult> {
     let keyword = self.previous().dup();
-    let value = if self.check(TokenType::SemiColon) {
+    let value = if self.check(TokenType::SemiColon) || self.at_statement_end() {
       None
     } else {
       Some(Rc::new(self.expression()?))
     };
 
-    self.consume(TokenType::SemiColon, "Expect ';' after return value.")?;
+    self.terminator("Expect ';' after return value.")?;
     Ok(Stmt::Return(Rc::new(ReturnStmt { keyword, value })))
   }
 
   fn expression_statement(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
     let expr = Rc::new(self.expression()?);
-    self.consume(TokenType::SemiColon, "Expect ';' after value.")?;
+    self.terminator("Expect ';' after value.")?;
     Ok(Rc::new(Stmt::Expression(Rc::new(ExpressionStmt {
       expression: expr,
     }))))
@@ -259,21 +589,58 @@ impl<'a> Parser<'a> {
 
   fn function(&mut self, kind: &str) -> Result<Rc<Stmt>, SaturdayResult> {
     let name = self.consume(TokenType::Identifier, &format!("Expect {kind} name"))?;
+    Ok(Rc::new(Stmt::Function(Rc::new(
+      self.function_body(name, kind)?,
+    ))))
+  }
+
+  /// 函数名之后的参数列表和函数体，具名函数与匿名函数共用
+  fn function_body(&mut self, name: Token, kind: &str) -> Result<FunctionStmt, SaturdayResult> {
     self.consume(
       TokenType::LeftParen,
       &format!("Expect '(' after {kind} name."),
     )?;
 
     let mut params: Vec<Token> = Vec::new();
+    let mut defaults: Vec<Option<Rc<Expr>>> = Vec::new();
+    let mut rest = None;
     if !self.check(TokenType::RightParen) {
-      params.push(self.consume(TokenType::Identifier, "Expect parameter name")?);
-      while self.is_match(&[TokenType::Comma]) {
-        if params.len() >= 255 && !self.had_error {
+      loop {
+        if params.len() >= self.max_arguments && !self.had_error {
           let peek = self.peek().dup();
-          self.error(&peek, "Can't have more than 255 parameters.");
+          let message = format!("Can't have more than {} parameters.", self.max_arguments);
+          self.error(&peek, &message);
+        }
+
+        // `...name` 收集剩余参数，必须是最后一个参数
+        if self.is_match(&[TokenType::Ellipsis]) {
+          rest = Some(self.consume(TokenType::Identifier, "Expect rest parameter name")?);
+          if self.check(TokenType::Comma) {
+            let peek = self.peek().dup();
+            self.error(&peek, "Rest parameter must be the last parameter.");
+          }
+          break;
         }
 
         params.push(self.consume(TokenType::Identifier, "Expect parameter name")?);
+        // 有默认值的参数之后的参数也必须有默认值
+        if self.is_match(&[TokenType::Assign]) {
+          defaults.push(Some(Rc::new(self.expression()?)));
+        } else {
+          if defaults.iter().any(Option::is_some) {
+            let previous = self.previous().dup();
+            self.error(
+              &previous,
+              "Parameter without a default value can't follow one with a default value.",
+            );
+          }
+
+          defaults.push(None);
+        }
+
+        if !self.is_match(&[TokenType::Comma]) {
+          break;
+        }
       }
     }
 
@@ -284,10 +651,28 @@ impl<'a> Parser<'a> {
       &format!("Expect '{{' before {kind} body"),
     )?;
     let body = Rc::new(self.block()?);
-    Ok(Rc::new(Stmt::Function(Rc::new(FunctionStmt {
+    Ok(FunctionStmt {
       name,
       params: Rc::new(params),
+      defaults: Rc::new(defaults),
+      rest,
       body,
+      getter: false,
+    })
+  }
+
+  /// `get name { ... }`，读取属性时直接调用，没有参数
+  fn getter(&mut self) -> Result<Rc<Stmt>, SaturdayResult> {
+    let name = self.consume(TokenType::Identifier, "Expect getter name.")?;
+    self.consume(TokenType::LeftBrace, "Expect '{' before getter body")?;
+    let body = Rc::new(self.block()?);
+    Ok(Rc::new(Stmt::Function(Rc::new(FunctionStmt {
+      name,
+      params: Rc::new(Vec::new()),
+      defaults: Rc::new(Vec::new()),
+      rest: None,
+      body,
+      getter: true,
     }))))
   }
 
@@ -302,158 +687,370 @@ impl<'a> Parser<'a> {
   }
 
   fn assignment(&mut self) -> Result<Expr, SaturdayResult> {
-    let expr = self.or()?;
-
-    if self.is_match(&[TokenType::Assign]) {
+    let start = self.current;
+    let expr = self.nil_coalesce()?;
+
+    if self.is_match(&[
+      TokenType::Assign,
+      TokenType::PlusAssign,
+      TokenType::MinusAssign,
+      TokenType::StarAssign,
+      TokenType::SlashAssign,
+    ]) {
       let equals = self.previous().dup();
       let value = self.assignment()?;
+      if !matches!(expr, Expr::Variable(_) | Expr::Get(_)) {
+        self.error(&equals, "Invalid assignment target.");
+        return Ok(expr);
+      }
 
-      if let Expr::Variable(expr) = expr {
-        return Ok(Expr::Assign(Rc::new(AssignExpr {
-          name: expr.name.dup(),
-          value: Rc::new(value),
-        })));
-      } else if let Expr::Get(get) = expr {
-        return Ok(Expr::Set(Rc::new(SetExpr {
+      let operator = Parser::compound_operator(&equals);
+      let assign = match expr {
+        // 复合赋值 `a += b` 转换为 `a = a + b`
+        Expr::Variable(variable) => {
+          let name = variable.name.dup();
+          let value = match operator {
+            Some(operator) => Expr::Binary(Rc::new(BinaryExpr {
+              left: Rc::new(Expr::Variable(variable)),
+              operator,
+              right: Rc::new(value),
+            })),
+            None => value,
+          };
+          Expr::Assign(Rc::new(AssignExpr {
+            name,
+            value: Rc::new(value),
+          }))
+        }
+        // 属性的复合赋值保留运算符，对象表达式只求值一次
+        Expr::Get(get) => Expr::Set(Rc::new(SetExpr {
           object: Rc::clone(&get.object),
           name: get.name.clone(),
+          operator,
           value: Rc::new(value),
-        })));
-      }
+        })),
+        _ => unreachable!("assignment target checked above"),
+      };
+      return Ok(self.spanned(start, assign));
+    }
+
+    Ok(expr)
+  }
+
+  /// 复合赋值符号对应的二元运算符，普通赋值返回None
+  fn compound_operator(equals: &Token) -> Option<Token> {
+    let (t_type, lexeme) = match equals.token_type() {
+      TokenType::PlusAssign => (TokenType::Plus, "+"),
+      TokenType::MinusAssign => (TokenType::Minus, "-"),
+      TokenType::StarAssign => (TokenType::Star, "*"),
+      TokenType::SlashAssign => (TokenType::Slash, "/"),
+      _ => return None,
+    };
+
+    Some(Parser::operator_at(equals, t_type, lexeme))
+  }
 
-      self.error(&equals, "Invalid assignment target.");
+  /// 在原符号的位置上构造一个运算符，错误信息仍指向源码中的位置
+  fn operator_at(at: &Token, t_type: TokenType, lexeme: &str) -> Token {
+    let mut operator = Token::new(t_type, lexeme.to_string(), None, at.line, at.column);
+    operator.start = at.start;
+    operator
+  }
+
+  /// 后缀 `x++`/`x--`，只能作用于变量，结果是修改前的值
+  fn postfix(&mut self, expr: Expr) -> Result<Expr, SaturdayResult> {
+    let operator = self.previous().dup();
+    let Expr::Variable(variable) = &expr else {
+      self.error(&operator, "Invalid assignment target.");
+      return Ok(expr);
+    };
+
+    Ok(Expr::Postfix(Rc::new(PostfixExpr {
+      name: variable.name.dup(),
+      operator,
+    })))
+  }
+
+  /// `a ?? b`，优先级低于 `or`
+  fn nil_coalesce(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
+    let mut expr = self.or()?;
+
+    while self.is_match(&[TokenType::QuestionQuestion]) {
+      let operator = self.previous().dup();
+      let right = Rc::new(self.or()?);
+      expr = self.spanned(
+        start,
+        Expr::Logical(Rc::new(LogicalExpr {
+          left: Rc::new(expr),
+          operator,
+          right,
+        })),
+      );
     }
 
     Ok(expr)
   }
 
   fn or(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
     let mut expr = self.and()?;
 
     while self.is_match(&[TokenType::Or]) {
       let operator = self.previous().dup();
       let right = Rc::new(self.and()?);
-      expr = Expr::Logical(Rc::new(LogicalExpr {
-        left: Rc::new(expr),
-        operator,
-        right,
-      }));
+      expr = self.spanned(
+        start,
+        Expr::Logical(Rc::new(LogicalExpr {
+          left: Rc::new(expr),
+          operator,
+          right,
+        })),
+      );
     }
 
     Ok(expr)
   }
 
   fn and(&mut self) -> Result<Expr, SaturdayResult> {
-    let mut expr = self.equality()?;
+    let start = self.current;
+    let mut expr = self.bit_or()?;
 
     while self.is_match(&[TokenType::And]) {
       let operator = self.previous().dup();
-      let right = Rc::new(self.equality()?);
-      expr = Expr::Logical(Rc::new(LogicalExpr {
-        left: Rc::new(expr),
-        operator,
-        right,
-      }));
+      let right = Rc::new(self.bit_or()?);
+      expr = self.spanned(
+        start,
+        Expr::Logical(Rc::new(LogicalExpr {
+          left: Rc::new(expr),
+          operator,
+          right,
+        })),
+      );
+    }
+
+    Ok(expr)
+  }
+
+  /// # 位运算
+  /// 与 C 相同，`&` `^` `|` 比相等判断结合得更松，`<<` `>>` 比大小比较结合得更紧
+  fn bit_or(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
+    let mut expr = self.bit_xor()?;
+    while self.is_match(&[TokenType::Pipe]) {
+      let operator = self.previous().dup();
+      let right = self.bit_xor()?;
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
+    }
+
+    Ok(expr)
+  }
+
+  fn bit_xor(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
+    let mut expr = self.bit_and()?;
+    while self.is_match(&[TokenType::Caret]) {
+      let operator = self.previous().dup();
+      let right = self.bit_and()?;
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
+    }
+
+    Ok(expr)
+  }
+
+  fn bit_and(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
+    let mut expr = self.equality()?;
+    while self.is_match(&[TokenType::Ampersand]) {
+      let operator = self.previous().dup();
+      let right = self.equality()?;
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
     }
 
     Ok(expr)
   }
 
   fn equality(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
     let mut expr = self.comparison()?;
 
     while self.is_match(&[TokenType::BangEqual, TokenType::Equal]) {
       let operator = self.previous().dup();
       let right = self.comparison()?;
-      expr = Expr::Binary(Rc::new(BinaryExpr {
-        left: Rc::new(expr),
-        operator,
-        right: Rc::new(right),
-      }));
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
     }
 
     Ok(expr)
   }
 
   fn comparison(&mut self) -> Result<Expr, SaturdayResult> {
-    let mut expr = self.term()?;
+    let start = self.current;
+    let mut expr = self.shift()?;
     while self.is_match(&[
       TokenType::Greater,
       TokenType::GreaterEqual,
       TokenType::Less,
       TokenType::LessEqual,
     ]) {
+      let operator = self.previous().dup();
+      let right = self.shift()?;
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
+    }
+
+    Ok(expr)
+  }
+
+  fn shift(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
+    let mut expr = self.term()?;
+    while self.is_match(&[TokenType::LessLess, TokenType::GreaterGreater]) {
       let operator = self.previous().dup();
       let right = self.term()?;
-      expr = Expr::Binary(Rc::new(BinaryExpr {
-        left: Rc::new(expr),
-        operator,
-        right: Rc::new(right),
-      }));
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
     }
 
     Ok(expr)
   }
 
   fn term(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
     let mut expr = self.factor()?;
-    while self.is_match(&[TokenType::Minus, TokenType::Plus]) {
+    while !self.starts_next_statement() && self.is_match(&[TokenType::Minus, TokenType::Plus]) {
       let operator = self.previous().dup();
       let right = self.factor()?;
-      expr = Expr::Binary(Rc::new(BinaryExpr {
-        left: Rc::new(expr),
-        operator,
-        right: Rc::new(right),
-      }));
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
     }
 
     Ok(expr)
   }
 
   fn factor(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
     let mut expr = self.unary()?;
     while self.is_match(&[TokenType::Slash, TokenType::Star]) {
       let operator = self.previous().dup();
       let right = self.unary()?;
-      expr = Expr::Binary(Rc::new(BinaryExpr {
-        left: Rc::new(expr),
-        operator,
-        right: Rc::new(right),
-      }));
+      expr = self.spanned(
+        start,
+        Expr::Binary(Rc::new(BinaryExpr {
+          left: Rc::new(expr),
+          operator,
+          right: Rc::new(right),
+        })),
+      );
     }
 
     Ok(expr)
   }
 
   fn unary(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
     if self.is_match(&[TokenType::Bang, TokenType::Minus]) {
       let operator = self.previous().dup();
       let right = self.unary()?;
-      return Ok(Expr::Unary(Rc::new(UnaryExpr {
+      let unary = Expr::Unary(Rc::new(UnaryExpr {
         operator,
         right: Rc::new(right),
-      })));
+      }));
+      return Ok(self.spanned(start, unary));
     }
 
-    self.call()
+    self.power()
+  }
+
+  /// # 乘方运算 `**`
+  /// 右结合，优先级高于一元运算符：`-2 ** 2` 解析为 `-(2 ** 2)`，
+  /// 右操作数允许一元运算符，如 `2 ** -1`
+  fn power(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
+    let expr = self.call()?;
+    if self.is_match(&[TokenType::StarStar]) {
+      let operator = self.previous().dup();
+      let right = self.unary()?;
+      let power = Expr::Binary(Rc::new(BinaryExpr {
+        left: Rc::new(expr),
+        operator,
+        right: Rc::new(right),
+      }));
+      return Ok(self.spanned(start, power));
+    }
+
+    Ok(expr)
   }
 
   fn call(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
     let mut expr = self.primary()?;
     loop {
-      if self.is_match(&[TokenType::LeftParen]) {
-        expr = self.finish_call(&Rc::new(expr))?;
+      if !self.starts_next_statement() && self.is_match(&[TokenType::LeftParen]) {
+        let call = self.finish_call(&Rc::new(expr))?;
+        expr = self.spanned(start, call);
       } else if self.is_match(&[TokenType::Dot]) {
         let name = self.consume(TokenType::Identifier, "Expect property name after '.'.")?;
-        expr = Expr::Get(Rc::new(GetExpr {
+        let get = Expr::Get(Rc::new(GetExpr {
           object: Rc::new(expr),
           name,
-        }))
+        }));
+        expr = self.spanned(start, get);
       } else {
         break;
       }
     }
 
+    if self.is_match(&[TokenType::PlusPlus, TokenType::MinusMinus]) {
+      let postfix = self.postfix(expr)?;
+      return Ok(self.spanned(start, postfix));
+    }
+
     Ok(expr)
   }
 
@@ -463,17 +1060,24 @@ impl<'a> Parser<'a> {
     if !self.check(TokenType::RightParen) {
       arguments.push(Rc::new(self.expression()?));
       while self.is_match(&[TokenType::Comma]) {
-        if arguments.len() >= 255 && !self.had_error {
+        // 允许最后一个参数后面多一个逗号
+        if self.check(TokenType::RightParen) {
+          break;
+        }
+
+        if arguments.len() >= self.max_arguments && !self.had_error {
           let peek = self.peek().dup();
-          self.error(&peek, "Can't have more than 255 arguments.");
-          self.had_error = true;
-        } else {
-          arguments.push(Rc::new(self.expression()?));
+          let message = format!("Can't have more than {} arguments.", self.max_arguments);
+          self.error(&peek, &message);
         }
+        arguments.push(Rc::new(self.expression()?));
       }
     }
 
-    let paren = self.consume(TokenType::RightParen, "Expect ')' after arguments.")?;
+    let paren = self.consume_expected(
+      TokenType::RightParen,
+      &[TokenType::RightParen, TokenType::Comma],
+    )?;
     Ok(Expr::Call(Rc::new(CallExpr {
       callee: Rc::clone(callee),
       paren,
@@ -482,6 +1086,12 @@ impl<'a> Parser<'a> {
   }
 
   fn primary(&mut self) -> Result<Expr, SaturdayResult> {
+    let start = self.current;
+    let expr = self.primary_node()?;
+    Ok(self.spanned(start, expr))
+  }
+
+  fn primary_node(&mut self) -> Result<Expr, SaturdayResult> {
     if self.is_match(&[TokenType::False]) {
       return Ok(Expr::Literal(Rc::new(LiteralExpr {
         value: Some(Object::Bool(false)),
@@ -504,12 +1114,41 @@ impl<'a> Parser<'a> {
       })));
     }
 
+    if self.is_match(&[TokenType::Interpolation]) {
+      return self.interpolation();
+    }
+
     if self.is_match(&[TokenType::Identifier]) {
       return Ok(Expr::Variable(Rc::new(VariableExpr {
         name: self.previous().dup(),
       })));
     }
 
+    if self.is_match(&[TokenType::This]) {
+      return Ok(Expr::This(Rc::new(ThisExpr {
+        keyword: self.previous().dup(),
+      })));
+    }
+
+    if self.is_match(&[TokenType::Super]) {
+      let keyword = self.previous().dup();
+      self.consume(TokenType::Dot, "Expect '.' after 'super'.")?;
+      let method = self.consume(TokenType::Identifier, "Expect superclass method name.")?;
+      return Ok(Expr::Super(Rc::new(SuperExpr { keyword, method })));
+    }
+
+    // 匿名函数以 `lambda` 作为名字
+    if self.is_match(&[TokenType::Fun]) {
+      let keyword = self.previous().dup();
+      let mut name = keyword.dup();
+      name.lexeme = Rc::from("lambda");
+      let function = self.function_body(name, "lambda")?;
+      return Ok(Expr::Lambda(Rc::new(LambdaExpr {
+        keyword,
+        function: Rc::new(function),
+      })));
+    }
+
     if self.is_match(&[TokenType::LeftParen]) {
       let expr = self.expression()?;
       self.consume(TokenType::RightParen, "Expect ')' after expression")?;
@@ -522,6 +1161,27 @@ impl<'a> Parser<'a> {
     Err(SaturdayResult::parse_error(&peek, "Expect expression."))
   }
 
+  /// 解析字符串插值，字符串片段与 `${}` 中的表达式交替出现
+  fn interpolation(&mut self) -> Result<Expr, SaturdayResult> {
+    let mut parts = Vec::new();
+    loop {
+      parts.push(Rc::new(Expr::Literal(Rc::new(LiteralExpr {
+        value: self.previous().literal.clone(),
+      }))));
+      parts.push(Rc::new(self.expression()?));
+
+      if !self.is_match(&[TokenType::Interpolation]) {
+        self.consume(TokenType::String, "Expect end of string interpolation.")?;
+        parts.push(Rc::new(Expr::Literal(Rc::new(LiteralExpr {
+          value: self.previous().literal.clone(),
+        }))));
+        break;
+      }
+    }
+
+    Ok(Expr::Interpolation(Rc::new(InterpolationExpr { parts })))
+  }
+
   fn consume(&mut self, t_token: TokenType, message: &str) -> Result<Token, SaturdayResult> {
     if self.check(t_token) {
       Ok(self.advance().dup())
@@ -530,6 +1190,68 @@ impl<'a> Parser<'a> {
     }
   }
 
+  /// 与 `consume` 相同，失败时报告 `expected` 中所有可以出现在这里的 token
+  fn consume_expected(
+    &mut self,
+    t_type: TokenType,
+    expected: &[TokenType],
+  ) -> Result<Token, SaturdayResult> {
+    if self.check(t_type) {
+      Ok(self.advance().dup())
+    } else {
+      Err(self.error_expected(expected))
+    }
+  }
+
+  /// 例如 `Expected one of ')', ',' but found '}'.`
+  fn error_expected(&mut self, expected: &[TokenType]) -> SaturdayResult {
+    let found = self.peek().dup();
+    let names: Vec<String> = expected
+      .iter()
+      .map(|t_type| format!("'{t_type}'"))
+      .collect();
+    let expected = match names.as_slice() {
+      [name] => name.clone(),
+      _ => format!("one of {}", names.join(", ")),
+    };
+    let found_name = if found.is(TokenType::Eof) {
+      found.t_type.to_string()
+    } else {
+      format!("'{}'", found.lexeme)
+    };
+    self.error(
+      &found,
+      &format!("Expected {expected} but found {found_name}."),
+    )
+  }
+
+  /// 语句结尾的 `;`，换行结尾时在 `at_statement_end` 的位置也可以省略
+  fn terminator(&mut self, message: &str) -> Result<(), SaturdayResult> {
+    if self.is_match(&[TokenType::SemiColon]) || self.at_statement_end() {
+      Ok(())
+    } else {
+      Err(self.error(&self.peek().dup(), message))
+    }
+  }
+
+  /// 换行结尾时，下一个 token 在新的一行、是 `}` 或文件结尾，语句就到此结束
+  fn at_statement_end(&self) -> bool {
+    self.newline_terminators
+      && (self.check(TokenType::RightBrace)
+        || self.is_at_end()
+        || self.peek().line > self.previous().line)
+  }
+
+  /// 换行结尾时，新一行开头的 `-`、`(` 既能接在上一行后面，也能开始新的表达式。
+  /// 这里总是当作新语句的开头，要跨行书写时把运算符留在上一行末尾
+  fn starts_next_statement(&self) -> bool {
+    self.newline_terminators
+      && self
+        .peek()
+        .is_one_of(&[TokenType::Minus, TokenType::LeftParen])
+      && self.peek().line > self.previous().line
+  }
+
   fn error(&mut self, token: &Token, message: &str) -> SaturdayResult {
     self.had_error = true;
     SaturdayResult::parse_error(token, message)
@@ -543,18 +1265,7 @@ impl<'a> Parser<'a> {
         return;
       }
 
-      if matches!(
-        self.peek().token_type(),
-        TokenType::Class
-          | TokenType::Fun
-          | TokenType::Var
-          | TokenType::Def
-          | TokenType::For
-          | TokenType::If
-          | TokenType::While
-          | TokenType::Print
-          | TokenType::Return
-      ) {
+      if self.peek().is_one_of(STATEMENT_STARTS) {
         return;
       }
 
@@ -563,11 +1274,9 @@ impl<'a> Parser<'a> {
   }
 
   fn is_match(&mut self, types: &[TokenType]) -> bool {
-    for &t in types {
-      if self.check(t) {
-        self.advance();
-        return true;
-      }
+    if !self.is_at_end() && self.peek().is_one_of(types) {
+      self.advance();
+      return true;
     }
 
     false
@@ -581,6 +1290,10 @@ impl<'a> Parser<'a> {
     }
   }
 
+  fn check_next(&self, t_type: TokenType) -> bool {
+    matches!(self.tokens.get(self.current + 1), Some(token) if token.is(t_type))
+  }
+
   fn advance(&mut self) -> &Token {
     if !self.is_at_end() {
       self.current += 1;
@@ -601,3 +1314,368 @@ impl<'a> Parser<'a> {
     self.tokens.get(self.current - 1).unwrap()
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::error::{set_error_format, take_captured_errors, ErrorFormat};
+  use crate::scanner::Scanner;
+  use crate::span::Span;
+  use std::ops::Deref;
+
+  fn parse_source(source: &str) -> (Vec<Rc<Stmt>>, bool) {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse().ok().unwrap();
+    (statements, parser.success())
+  }
+
+  fn parse_expression_stmt(source: &str) -> Rc<Expr> {
+    let (statements, success) = parse_source(source);
+    assert!(success);
+    match statements[0].deref() {
+      Stmt::Expression(stmt) => Rc::clone(&stmt.expression),
+      _ => panic!("expected an expression statement"),
+    }
+  }
+
+  #[test]
+  fn compound_assignment_desugars_to_binary() {
+    let expr = parse_expression_stmt("x += 5;");
+    let Expr::Assign(assign) = expr.deref() else {
+      panic!("expected an assignment");
+    };
+    assert_eq!(assign.name.as_string(), "x");
+
+    let Expr::Binary(binary) = assign.value.deref() else {
+      panic!("expected a binary value");
+    };
+    assert!(binary.operator.is(TokenType::Plus));
+    assert!(matches!(binary.left.deref(), Expr::Variable(v) if v.name.as_string() == "x"));
+    assert!(matches!(binary.right.deref(), Expr::Literal(l) if l.value == Some(Object::Num(5.0))));
+  }
+
+  #[test]
+  fn compound_assignment_maps_each_operator() {
+    for (source, t_type) in [
+      ("x -= 1;", TokenType::Minus),
+      ("x *= 1;", TokenType::Star),
+      ("x /= 1;", TokenType::Slash),
+    ] {
+      let expr = parse_expression_stmt(source);
+      let Expr::Assign(assign) = expr.deref() else {
+        panic!("expected an assignment");
+      };
+      assert!(matches!(assign.value.deref(), Expr::Binary(b) if b.operator.is(t_type)));
+    }
+  }
+
+  #[test]
+  fn compound_property_assignment_keeps_operator() {
+    let expr = parse_expression_stmt("a.b += 5;");
+    let Expr::Set(set) = expr.deref() else {
+      panic!("expected a property assignment");
+    };
+    assert!(matches!(&set.operator, Some(operator) if operator.is(TokenType::Plus)));
+    assert!(matches!(set.value.deref(), Expr::Literal(l) if l.value == Some(Object::Num(5.0))));
+  }
+
+  #[test]
+  fn compound_assignment_rejects_invalid_target() {
+    let (_, success) = parse_source("1 += 2;");
+    assert!(!success);
+  }
+
+  #[test]
+  fn const_requires_initializer() {
+    let mut scanner = Scanner::new("const a;".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    assert!(Parser::new(tokens).parse().is_err());
+    let (statements, success) = parse_source("const a = 1;");
+    assert!(success);
+    assert!(matches!(statements[0].deref(), Stmt::Const(_)));
+  }
+
+  #[test]
+  fn nil_coalesce_binds_looser_than_or() {
+    let expr = parse_expression_stmt("a ?? b or c;");
+    match expr.deref() {
+      Expr::Logical(logical) => {
+        assert!(logical.operator.is(TokenType::QuestionQuestion));
+        assert!(matches!(logical.right.deref(), Expr::Logical(_)));
+      }
+      _ => panic!("expected a logical expression"),
+    }
+  }
+
+  #[test]
+  fn class_getter_is_flagged() {
+    let (statements, success) =
+      parse_source("class Box { get size { return 1; } get(key) { return key; } }");
+    assert!(success);
+    let Stmt::Class(class) = statements[0].deref() else {
+      panic!("expected a class");
+    };
+    let flags: Vec<(String, bool)> = class
+      .methods
+      .iter()
+      .map(|method| match method.deref() {
+        Stmt::Function(function) => (function.name.as_string(), function.getter),
+        _ => panic!("expected a method"),
+      })
+      .collect();
+    assert_eq!(
+      flags,
+      vec![("size".to_string(), true), ("get".to_string(), false)]
+    );
+  }
+
+  #[test]
+  fn class_static_methods_are_separate() {
+    let (statements, success) =
+      parse_source("class Thing { static make() { return Thing(); } size() { return 1; } }");
+    assert!(success);
+    let Stmt::Class(class) = statements[0].deref() else {
+      panic!("expected a class");
+    };
+    assert_eq!(class.statics.len(), 1);
+    assert_eq!(class.methods.len(), 1);
+
+    let mut scanner = Scanner::new("class Thing { static }".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    assert!(Parser::new(tokens).parse().is_err());
+  }
+
+  #[test]
+  fn parse_expression_rejects_trailing_input() {
+    let mut scanner = Scanner::new("1 + 2".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    assert!(matches!(
+      Parser::new(tokens).parse_expression(),
+      Ok(Expr::Binary(_))
+    ));
+
+    let mut scanner = Scanner::new("1 + 2; 3".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    assert!(Parser::new(tokens).parse_expression().is_err());
+  }
+
+  #[test]
+  fn bitwise_precedence_follows_c() {
+    // a | b == c 解析为 a | (b == c)
+    let expr = parse_expression_stmt("a | b == c;");
+    match expr.deref() {
+      Expr::Binary(binary) => {
+        assert!(binary.operator.is(TokenType::Pipe));
+        assert!(matches!(binary.right.deref(), Expr::Binary(b) if b.operator.is(TokenType::Equal)));
+      }
+      _ => panic!("expected a binary expression"),
+    }
+
+    // a < b << c 解析为 a < (b << c)
+    let expr = parse_expression_stmt("a < b << c;");
+    match expr.deref() {
+      Expr::Binary(binary) => {
+        assert!(binary.operator.is(TokenType::Less));
+        assert!(
+          matches!(binary.right.deref(), Expr::Binary(b) if b.operator.is(TokenType::LessLess))
+        );
+      }
+      _ => panic!("expected a binary expression"),
+    }
+  }
+
+  #[test]
+  fn postfix_requires_a_variable() {
+    let mut scanner = Scanner::new("a.b++; 1++;".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let mut parser = Parser::new(tokens);
+    assert!(parser.parse().is_ok());
+    assert!(!parser.success());
+  }
+
+  #[test]
+  fn malformed_arguments_list_the_expected_tokens() {
+    let message = |source: &str| {
+      let mut scanner = Scanner::new(source.to_string());
+      let tokens = scanner.scan_tokens().ok().unwrap();
+      match Parser::new(tokens).parse_expression() {
+        Err(SaturdayResult::ParseError { message, .. }) => message,
+        other => panic!("expected a parse error, got {other:?}"),
+      }
+    };
+    assert_eq!(message("f(1 2)"), "Expected one of ')', ',' but found '2'.");
+    assert_eq!(
+      message("f(a, b}"),
+      "Expected one of ')', ',' but found '}'."
+    );
+    assert_eq!(
+      message("f(1"),
+      "Expected one of ')', ',' but found end of file."
+    );
+  }
+
+  #[test]
+  fn call_arguments_allow_a_trailing_comma() {
+    let expr = parse_expression_stmt("f(1, 2,);");
+    let Expr::Call(call) = expr.deref() else {
+      panic!("expected a call");
+    };
+    assert_eq!(call.arguments.len(), 2);
+
+    let expr = parse_expression_stmt("f(1,\n);");
+    assert!(matches!(expr.deref(), Expr::Call(call) if call.arguments.len() == 1));
+
+    for source in ["f(,);", "f(1,,);"] {
+      let mut scanner = Scanner::new(source.to_string());
+      let tokens = scanner.scan_tokens().ok().unwrap();
+      assert!(Parser::new(tokens).parse().is_err(), "{source}");
+    }
+  }
+
+  #[test]
+  fn newlines_can_terminate_statements() {
+    let parse = |source: &str, newlines: bool| {
+      let mut scanner = Scanner::new(source.to_string());
+      let tokens = scanner.scan_tokens().ok().unwrap();
+      let mut parser = Parser::new(tokens);
+      parser.set_newline_terminators(newlines);
+      parser.parse().ok()
+    };
+    let source = "def a = 1
+      print a
+      def total = a +
+        2 *
+        3
+      fun f() { return }
+      while true { break }
+      print total; print a";
+    assert!(parse(source, false).is_none());
+
+    let statements = parse(source, true).unwrap();
+    assert_eq!(statements.len(), 7);
+    assert!(matches!(statements[0].deref(), Stmt::Def(_)));
+    assert!(matches!(statements[1].deref(), Stmt::Print(_)));
+    let Stmt::Def(total) = statements[2].deref() else {
+      panic!("expected a definition");
+    };
+    assert!(matches!(
+      total.initializer.as_deref(),
+      Some(Expr::Binary(_))
+    ));
+    let Stmt::Function(f) = statements[3].deref() else {
+      panic!("expected a function");
+    };
+    assert!(matches!(f.body[0].deref(), Stmt::Return(r) if r.value.is_none()));
+    assert!(matches!(statements[6].deref(), Stmt::Print(_)));
+
+    assert!(parse("print 1 print 2", true).is_none());
+
+    let statements = parse("def a = 1\n-a\nprint a\n(a)\nprint a -\n  1", true).unwrap();
+    assert_eq!(statements.len(), 5);
+    assert!(
+      matches!(statements[0].deref(), Stmt::Def(d) if matches!(d.initializer.as_deref(), Some(Expr::Literal(_))))
+    );
+    assert!(
+      matches!(statements[1].deref(), Stmt::Expression(e) if matches!(e.expression.deref(), Expr::Unary(_)))
+    );
+    assert!(
+      matches!(statements[2].deref(), Stmt::Print(p) if matches!(p.expression.deref(), Expr::Variable(_)))
+    );
+    assert!(
+      matches!(statements[3].deref(), Stmt::Expression(e) if matches!(e.expression.deref(), Expr::Grouping(_)))
+    );
+    assert!(
+      matches!(statements[4].deref(), Stmt::Print(p) if matches!(p.expression.deref(), Expr::Binary(_)))
+    );
+    assert_eq!(parse("def a = 1\n-a;", false).unwrap().len(), 1);
+  }
+
+  #[test]
+  fn argument_and_parameter_limits() {
+    let parse = |source: &str, limit: Option<usize>| {
+      let mut scanner = Scanner::new(source.to_string());
+      let tokens = scanner.scan_tokens().ok().unwrap();
+      let mut parser = Parser::new(tokens);
+      if let Some(limit) = limit {
+        parser.set_max_arguments(limit);
+      }
+      set_error_format(ErrorFormat::Captured);
+      let parsed = parser.parse().is_ok() && parser.success();
+      set_error_format(ErrorFormat::Human);
+      (parsed, take_captured_errors())
+    };
+    let call = |count: usize| format!("f({});", vec!["0"; count].join(", "));
+    let declaration = |count: usize| {
+      let params: Vec<String> = (0..count).map(|i| format!("p{i}")).collect();
+      format!("fun f({}) {{}}", params.join(", "))
+    };
+
+    assert_eq!(parse(&call(255), None), (true, String::new()));
+    assert_eq!(parse(&declaration(255), None), (true, String::new()));
+
+    let (parsed, errors) = parse(&call(256), None);
+    assert!(!parsed);
+    assert!(
+      errors.contains("Can't have more than 255 arguments."),
+      "{errors}"
+    );
+    let (parsed, errors) = parse(&declaration(256), None);
+    assert!(!parsed);
+    assert!(
+      errors.contains("Can't have more than 255 parameters."),
+      "{errors}"
+    );
+
+    assert_eq!(parse(&call(300), Some(300)), (true, String::new()));
+    assert_eq!(parse(&declaration(300), Some(300)), (true, String::new()));
+    let (parsed, errors) = parse(&call(3), Some(2));
+    assert!(!parsed);
+    assert!(
+      errors.contains("Can't have more than 2 arguments."),
+      "{errors}"
+    );
+  }
+
+  #[test]
+  fn spans_cover_whole_nodes() {
+    let source = "def total = 1;\nprint (total + 20) * f(3);";
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let mut parser = Parser::new(tokens);
+    let statements = parser.parse().ok().unwrap();
+    let spans = parser.spans();
+    let span = |start: (usize, usize), end: (usize, usize)| Span {
+      start_line: start.0,
+      start_column: start.1,
+      end_line: end.0,
+      end_column: end.1,
+    };
+
+    let Stmt::Print(print) = statements[1].deref() else {
+      panic!("expected a print statement");
+    };
+    assert_eq!(spans.stmt(&statements[1]), Some(span((2, 1), (2, 26))));
+    assert_eq!(spans.expr(&print.expression), Some(span((2, 7), (2, 25))));
+    let Expr::Binary(product) = print.expression.deref() else {
+      panic!("expected a binary expression");
+    };
+    assert_eq!(spans.expr(&product.left), Some(span((2, 7), (2, 18))));
+    let Expr::Grouping(group) = product.left.deref() else {
+      panic!("expected a grouping");
+    };
+    assert_eq!(spans.expr(&group.expression), Some(span((2, 8), (2, 17))));
+
+    let (innermost, inner_span) = spans.expr_at(2, 16).unwrap();
+    assert!(matches!(innermost, Expr::Literal(l) if l.value == Some(Object::Num(20.0))));
+    assert_eq!(inner_span, span((2, 16), (2, 17)));
+    let (argument, _) = spans.expr_at(2, 24).unwrap();
+    assert!(matches!(argument, Expr::Literal(_)));
+    let (call, _) = spans.expr_at(2, 25).unwrap();
+    assert!(matches!(call, Expr::Call(_)));
+    let (statement, _) = spans.stmt_at(1, 5).unwrap();
+    assert!(matches!(statement, Stmt::Def(_)));
+    assert!(spans.expr_at(3, 1).is_none());
+  }
+}
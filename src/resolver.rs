@@ -1,13 +1,16 @@
 use crate::error::SaturdayResult;
 use crate::expr::{
-  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr, LiteralExpr,
-  LogicalExpr, SetExpr, UnaryExpr, VariableExpr,
+  AssignExpr, BinaryExpr, CallExpr, Expr, ExprVisitor, GetExpr, GroupingExpr, InterpolationExpr,
+  LambdaExpr, LiteralExpr, LogicalExpr, PostfixExpr, SetExpr, SuperExpr, ThisExpr, UnaryExpr,
+  VariableExpr,
 };
 use crate::interpreter::Interpreter;
 use crate::stmt::{
-  BlockStmt, BreakStmt, ClassStmt, DefStmt, ExpressionStmt, FunctionStmt, IfStmt, PrintStmt,
-  ReturnStmt, Stmt, StmtVisitor, WhileStmt,
+  AssertStmt, BlockStmt, BreakStmt, ClassStmt, ConstStmt, ContinueStmt, DefStmt, DoStmt,
+  ExpressionStmt, ForEachStmt, FunctionStmt, GlobalStmt, IfStmt, ImportStmt, PrintStmt, RepeatStmt,
+  ReturnStmt, Stmt, StmtVisitor, ThrowStmt, TryStmt, UndefStmt, WhileStmt,
 };
+use crate::symbol::Symbol;
 use crate::token::Token;
 use std::cell::RefCell;
 use std::collections::HashMap;
@@ -16,10 +19,32 @@ use std::rc::Rc;
 
 pub struct Resolver<'a> {
   interpreter: &'a Interpreter,
-  scopes: RefCell<Vec<RefCell<HashMap<String, bool>>>>,
-  had_error: RefCell<bool>,
+  scopes: RefCell<Vec<RefCell<HashMap<Symbol, LocalVariable>>>>,
+  /// 已报告的错误数，出错后继续解析以便一次报告多个问题
+  errors: RefCell<usize>,
+  warnings: RefCell<usize>,
   current_function: RefCell<FunctionType>,
-  in_while: RefCell<bool>,
+  current_class: RefCell<ClassType>,
+  /// 当前所在的循环，由外到内，记录各自的标签
+  loops: RefCell<Vec<Option<String>>>,
+}
+
+/// 作用域中的局部变量，记录是否已定义、是否被读取过以及是否为常量。
+/// `global` 声明的名字也占一个位置，解析到它时改为访问全局变量
+struct LocalVariable {
+  token: Token,
+  defined: bool,
+  used: bool,
+  constant: bool,
+  global: bool,
+}
+
+/// 是否在类的方法中，决定能否使用 `this` 和 `super`
+#[derive(PartialEq, Clone, Copy)]
+enum ClassType {
+  None,
+  Class,
+  Subclass,
 }
 
 #[derive(PartialEq)]
@@ -27,6 +52,7 @@ enum FunctionType {
   None,
   Function,
   Method,
+  Initializer,
 }
 
 impl<'a> Resolver<'a> {
@@ -34,9 +60,11 @@ impl<'a> Resolver<'a> {
     Self {
       interpreter,
       scopes: RefCell::new(Vec::new()),
-      had_error: RefCell::new(false),
+      errors: RefCell::new(0),
+      warnings: RefCell::new(0),
       current_function: RefCell::new(FunctionType::None),
-      in_while: RefCell::new(false),
+      current_class: RefCell::new(ClassType::None),
+      loops: RefCell::new(Vec::new()),
     }
   }
 
@@ -49,14 +77,14 @@ impl<'a> Resolver<'a> {
   }
 
   pub fn success(&self) -> bool {
-    !*self.had_error.borrow()
+    *self.errors.borrow() == 0
   }
 
   fn resolve_stmt(&self, stmt: Rc<Stmt>) -> Result<(), SaturdayResult> {
     stmt.accept(stmt.clone(), self)
   }
 
-  fn resolve_expr(&self, expr: Rc<Expr>) -> Result<(), SaturdayResult> {
+  pub fn resolve_expr(&self, expr: Rc<Expr>) -> Result<(), SaturdayResult> {
     expr.accept(expr.clone(), self)
   }
 
@@ -64,30 +92,107 @@ impl<'a> Resolver<'a> {
     self.scopes.borrow_mut().push(RefCell::new(HashMap::new()));
   }
 
+  /// 弹出作用域，对声明后从未读取的变量给出警告（以 `_` 开头的变量除外）
   fn end_scope(&self) {
-    self.scopes.borrow_mut().pop();
+    if let Some(scope) = self.scopes.borrow_mut().pop() {
+      let mut unused: Vec<Token> = scope
+        .into_inner()
+        .into_values()
+        .filter(|variable| {
+          variable.defined && !variable.used && !variable.token.lexeme.starts_with('_')
+        })
+        .map(|variable| variable.token)
+        .collect();
+      unused.sort_by(|a, b| a.line.cmp(&b.line).then(a.lexeme.cmp(&b.lexeme)));
+
+      for token in unused {
+        self.warning(
+          &token,
+          &format!("Local variable '{}' is never used.", token.as_string()),
+        );
+      }
+    }
   }
 
   fn declare(&self, name: &Token) {
     if let Some(scope) = self.scopes.borrow().last() {
-      if scope.borrow().contains_key(&name.as_string()) {
+      if scope.borrow().contains_key(&name.symbol()) {
         self.error(name, "Already a variable with this name in this scope.");
       }
 
-      scope.borrow_mut().insert(name.as_string(), false);
+      scope.borrow_mut().insert(
+        name.symbol(),
+        LocalVariable {
+          token: name.dup(),
+          defined: false,
+          used: false,
+          constant: false,
+          global: false,
+        },
+      );
     }
   }
 
   fn define(&self, name: &Token) {
     if let Some(scope) = self.scopes.borrow().last() {
-      scope.borrow_mut().insert(name.as_string(), true);
+      if let Some(variable) = scope.borrow_mut().get_mut(&name.symbol()) {
+        variable.defined = true;
+      }
     }
   }
 
-  fn resolve_local(&self, expr: Rc<Expr>, name: &Token) {
+  fn define_const(&self, name: &Token) {
+    self.define(name);
+    if let Some(scope) = self.scopes.borrow().last() {
+      if let Some(variable) = scope.borrow_mut().get_mut(&name.symbol()) {
+        variable.constant = true;
+      }
+    }
+  }
+
+  /// 在当前作用域中定义 `this`、`super` 这样不由声明引入的名字
+  fn define_implicit(&self, at: &Token, name: &str) {
+    if let Some(scope) = self.scopes.borrow().last() {
+      let mut token = at.dup();
+      token.lexeme = Rc::from(name);
+      token.symbol = Some(Symbol::intern(name));
+      scope.borrow_mut().insert(
+        Symbol::intern(name),
+        LocalVariable {
+          token,
+          defined: true,
+          used: true,
+          constant: true,
+          global: false,
+        },
+      );
+    }
+  }
+
+  /// 局部常量可以在静态检查时发现重新赋值，全局常量留给运行时检查
+  fn check_not_constant(&self, name: &Token) {
+    for map in self.scopes.borrow().iter().rev() {
+      if let Some(variable) = map.borrow().get(&name.symbol()) {
+        if variable.constant && !variable.global {
+          // 与运行时对全局常量的报告措辞一致
+          self.error(
+            name,
+            &format!("Can't assign to constant '{}'.", name.as_string()),
+          );
+        }
+        return;
+      }
+    }
+  }
+
+  /// 只有读取才算使用，只被赋值的变量仍会报告从未使用
+  fn resolve_local(&self, expr: Rc<Expr>, name: &Token, read: bool) {
     for (scope, map) in self.scopes.borrow().iter().rev().enumerate() {
-      if map.borrow().contains_key(&name.as_string()) {
-        self.interpreter.resolve(expr, scope);
+      if let Some(variable) = map.borrow_mut().get_mut(&name.symbol()) {
+        variable.used |= read;
+        if !variable.global {
+          self.interpreter.resolve(expr, scope);
+        }
         return;
       }
     }
@@ -98,24 +203,63 @@ impl<'a> Resolver<'a> {
     function: &FunctionStmt,
     f_type: FunctionType,
   ) -> Result<(), SaturdayResult> {
+    // 默认值在函数定义所在的作用域中求值
+    for default in function.defaults.iter().flatten() {
+      self.resolve_expr(Rc::clone(default))?;
+    }
+
     let enclosing_function = self.current_function.replace(f_type);
+    // 函数体内不能 break 到函数外层的循环
+    let enclosing_loops = self.loops.take();
     self.begin_scope();
 
-    for param in function.params.iter() {
+    for param in function.params.iter().chain(function.rest.iter()) {
       self.declare(param);
       self.define(param);
     }
 
     self.resolve(&function.body)?;
     self.end_scope();
+    self.loops.replace(enclosing_loops);
     self.current_function.replace(enclosing_function);
 
     Ok(())
   }
 
+  /// 静态检查出的错误，与语法错误一样在执行前报告
+  fn resolve_loop_body(&self, label: &Option<Token>, body: Rc<Stmt>) -> Result<(), SaturdayResult> {
+    self
+      .loops
+      .borrow_mut()
+      .push(label.as_ref().map(Token::as_string));
+    let result = self.resolve_stmt(body);
+    self.loops.borrow_mut().pop();
+    result
+  }
+
+  /// `break`/`continue` 的标签必须属于某个外层循环
+  fn check_label(&self, label: &Option<Token>) {
+    if let Some(label) = label {
+      let name = label.as_string();
+      if !self
+        .loops
+        .borrow()
+        .iter()
+        .any(|l| l.as_ref() == Some(&name))
+      {
+        self.error(label, &format!("Unknown loop label '{}'.", name));
+      }
+    }
+  }
+
   fn error(&self, token: &Token, message: &str) {
-    self.had_error.replace(true);
-    SaturdayResult::runtime_error(token, message);
+    *self.errors.borrow_mut() += 1;
+    SaturdayResult::parse_error(token, message);
+  }
+
+  fn warning(&self, token: &Token, message: &str) {
+    *self.warnings.borrow_mut() += 1;
+    SaturdayResult::warning(token, message);
   }
 }
 
@@ -131,9 +275,39 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     self.declare(&stmt.name);
     self.define(&stmt.name);
 
+    // 静态方法不绑定实例，按普通函数解析
+    for method in stmt.statics.deref() {
+      if let Stmt::Function(method) = method.deref() {
+        self.resolve_function(method, FunctionType::Function)?;
+      }
+    }
+
+    let enclosing_class = self.current_class.replace(ClassType::Class);
+    // 与解释器对应，有父类时方法的闭包外多一层只包含 `super` 的作用域
+    if let Some(superclass) = &stmt.superclass {
+      if let Expr::Variable(variable) = superclass.deref() {
+        if variable.name.lexeme == stmt.name.lexeme {
+          self.error(&variable.name, "A class can't inherit from itself.");
+        }
+      }
+
+      self.current_class.replace(ClassType::Subclass);
+      self.resolve_expr(Rc::clone(superclass))?;
+      self.begin_scope();
+      self.define_implicit(&stmt.name, "super");
+    }
+
+    // 与 `SaturdayFunction::bind` 对应，方法的闭包外有一层只包含 `this` 的作用域
+    self.begin_scope();
+    self.define_implicit(&stmt.name, "this");
+
     for method in stmt.methods.deref() {
-      let declaration = FunctionType::Method;
       if let Stmt::Function(method) = method.deref() {
+        let declaration = if &*method.name.lexeme == "init" {
+          FunctionType::Initializer
+        } else {
+          FunctionType::Method
+        };
         self.resolve_function(method, declaration)?;
       } else {
         return Err(SaturdayResult::runtime_error(
@@ -142,18 +316,48 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
         ));
       }
     }
+    self.end_scope();
+    if stmt.superclass.is_some() {
+      self.end_scope();
+    }
+    self.current_class.replace(enclosing_class);
 
     Ok(())
   }
 
   fn visit_break_stmt(&self, _: Rc<Stmt>, stmt: &BreakStmt) -> Result<(), SaturdayResult> {
-    if !*self.in_while.borrow() {
+    if self.loops.borrow().is_empty() {
       self.error(&stmt.token, "break statement outside of a while/for loop");
     }
 
+    self.check_label(&stmt.label);
     Ok(())
   }
 
+  fn visit_continue_stmt(&self, _: Rc<Stmt>, stmt: &ContinueStmt) -> Result<(), SaturdayResult> {
+    if self.loops.borrow().is_empty() {
+      self.error(
+        &stmt.token,
+        "continue statement outside of a while/for loop",
+      );
+    }
+
+    self.check_label(&stmt.label);
+    Ok(())
+  }
+
+  fn visit_const_stmt(&self, _: Rc<Stmt>, stmt: &ConstStmt) -> Result<(), SaturdayResult> {
+    self.declare(&stmt.name);
+    self.resolve_expr(stmt.initializer.clone())?;
+    self.define_const(&stmt.name);
+    Ok(())
+  }
+
+  fn visit_do_stmt(&self, _: Rc<Stmt>, stmt: &DoStmt) -> Result<(), SaturdayResult> {
+    self.resolve_loop_body(&stmt.label, stmt.body.clone())?;
+    self.resolve_expr(stmt.condition.clone())
+  }
+
   fn visit_expression_stmt(
     &self,
     _: Rc<Stmt>,
@@ -163,6 +367,17 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_foreach_stmt(&self, _: Rc<Stmt>, stmt: &ForEachStmt) -> Result<(), SaturdayResult> {
+    self.resolve_expr(stmt.iterable.clone())?;
+    self.begin_scope();
+    self.declare(&stmt.name);
+    self.define(&stmt.name);
+
+    let result = self.resolve_loop_body(&stmt.label, stmt.body.clone());
+    self.end_scope();
+    result
+  }
+
   fn visit_function_stmt(&self, _: Rc<Stmt>, stmt: &FunctionStmt) -> Result<(), SaturdayResult> {
     self.declare(&stmt.name);
     self.define(&stmt.name);
@@ -186,12 +401,71 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  /// 导入的定义和模块名都是全局变量，所以只能在顶层导入
+  fn visit_import_stmt(&self, _: Rc<Stmt>, stmt: &ImportStmt) -> Result<(), SaturdayResult> {
+    if !self.scopes.borrow().is_empty() {
+      self.error(&stmt.keyword, "Can only import at the top level.");
+    }
+    Ok(())
+  }
+
+  fn visit_assert_stmt(&self, _: Rc<Stmt>, stmt: &AssertStmt) -> Result<(), SaturdayResult> {
+    self.resolve_expr(stmt.condition.clone())
+  }
+
+  /// 在当前作用域中占住这个名字，之后的读取和赋值都直接访问全局变量
+  fn visit_global_stmt(&self, _: Rc<Stmt>, stmt: &GlobalStmt) -> Result<(), SaturdayResult> {
+    if *self.current_function.borrow() == FunctionType::None {
+      self.error(&stmt.keyword, "Can only use 'global' inside a function.");
+      return Ok(());
+    }
+
+    self.declare(&stmt.name);
+    if let Some(scope) = self.scopes.borrow().last() {
+      if let Some(variable) = scope.borrow_mut().get_mut(&stmt.name.symbol()) {
+        variable.defined = true;
+        variable.used = true;
+        variable.global = true;
+      }
+    }
+    Ok(())
+  }
+
+  /// 删除局部变量会让解析好的作用域距离失效，所以只能删除全局变量
+  fn visit_undef_stmt(&self, _: Rc<Stmt>, stmt: &UndefStmt) -> Result<(), SaturdayResult> {
+    if !self.scopes.borrow().is_empty() {
+      self.error(&stmt.keyword, "Can only undef at the top level.");
+    }
+    Ok(())
+  }
+
+  fn visit_throw_stmt(&self, _: Rc<Stmt>, stmt: &ThrowStmt) -> Result<(), SaturdayResult> {
+    self.resolve_expr(stmt.value.clone())
+  }
+
+  fn visit_try_stmt(&self, _: Rc<Stmt>, stmt: &TryStmt) -> Result<(), SaturdayResult> {
+    self.begin_scope();
+    self.resolve(&stmt.body)?;
+    self.end_scope();
+
+    self.begin_scope();
+    self.declare(&stmt.name);
+    self.define(&stmt.name);
+    self.resolve(&stmt.handler)?;
+    self.end_scope();
+    Ok(())
+  }
+
   fn visit_return_stmt(&self, _: Rc<Stmt>, stmt: &ReturnStmt) -> Result<(), SaturdayResult> {
     if *self.current_function.borrow() == FunctionType::None {
       self.error(&stmt.keyword, "Can't return from top-level code.");
     }
 
     if let Some(value) = stmt.value.clone() {
+      if *self.current_function.borrow() == FunctionType::Initializer {
+        self.error(&stmt.keyword, "Can't return a value from an initializer.");
+      }
+
       self.resolve_expr(value)?;
     }
 
@@ -208,11 +482,17 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_repeat_stmt(&self, _: Rc<Stmt>, stmt: &RepeatStmt) -> Result<(), SaturdayResult> {
+    self.resolve_expr(stmt.count.clone())?;
+    self.resolve_loop_body(&stmt.label, stmt.body.clone())
+  }
+
   fn visit_while_stmt(&self, _: Rc<Stmt>, stmt: &WhileStmt) -> Result<(), SaturdayResult> {
-    self.in_while.replace(true);
     self.resolve_expr(stmt.condition.clone())?;
-    self.resolve_stmt(stmt.body.clone())?;
-    self.in_while.replace(false);
+    self.resolve_loop_body(&stmt.label, stmt.body.clone())?;
+    if let Some(increment) = stmt.increment.clone() {
+      self.resolve_expr(increment)?;
+    }
 
     Ok(())
   }
@@ -221,7 +501,19 @@ impl<'a> StmtVisitor<()> for Resolver<'a> {
 impl<'a> ExprVisitor<()> for Resolver<'a> {
   fn visit_assign_expr(&self, wrapper: Rc<Expr>, expr: &AssignExpr) -> Result<(), SaturdayResult> {
     self.resolve_expr(expr.value.clone())?;
-    self.resolve_local(wrapper, &expr.name);
+    self.check_not_constant(&expr.name);
+    self.resolve_local(wrapper, &expr.name, false);
+    Ok(())
+  }
+
+  fn visit_postfix_expr(
+    &self,
+    wrapper: Rc<Expr>,
+    expr: &PostfixExpr,
+  ) -> Result<(), SaturdayResult> {
+    self.check_not_constant(&expr.name);
+    // 和 `x += 1` 一样，修改前先读取了旧值
+    self.resolve_local(wrapper, &expr.name, true);
     Ok(())
   }
 
@@ -250,6 +542,18 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_interpolation_expr(
+    &self,
+    _: Rc<Expr>,
+    expr: &InterpolationExpr,
+  ) -> Result<(), SaturdayResult> {
+    for part in expr.parts.iter() {
+      self.resolve_expr(part.clone())?;
+    }
+
+    Ok(())
+  }
+
   fn visit_literal_expr(&self, _: Rc<Expr>, _expr: &LiteralExpr) -> Result<(), SaturdayResult> {
     Ok(())
   }
@@ -260,9 +564,34 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
     Ok(())
   }
 
+  fn visit_lambda_expr(&self, _: Rc<Expr>, expr: &LambdaExpr) -> Result<(), SaturdayResult> {
+    self.resolve_function(&expr.function, FunctionType::Function)
+  }
+
+  fn visit_super_expr(&self, wrapper: Rc<Expr>, expr: &SuperExpr) -> Result<(), SaturdayResult> {
+    match *self.current_class.borrow() {
+      ClassType::None => self.error(&expr.keyword, "Can't use 'super' outside of a class."),
+      ClassType::Class => self.error(
+        &expr.keyword,
+        "Can't use 'super' in a class with no superclass.",
+      ),
+      ClassType::Subclass => self.resolve_local(wrapper, &expr.keyword, true),
+    }
+    Ok(())
+  }
+
+  fn visit_this_expr(&self, wrapper: Rc<Expr>, expr: &ThisExpr) -> Result<(), SaturdayResult> {
+    if *self.current_class.borrow() == ClassType::None {
+      self.error(&expr.keyword, "Can't use 'this' outside of a class.");
+    } else {
+      self.resolve_local(wrapper, &expr.keyword, true);
+    }
+    Ok(())
+  }
+
   fn visit_set_expr(&self, _: Rc<Expr>, expr: &SetExpr) -> Result<(), SaturdayResult> {
     self.resolve_expr(expr.value.clone())?;
-    self.resolve_expr(expr.value.clone())?;
+    self.resolve_expr(expr.object.clone())?;
     Ok(())
   }
 
@@ -283,17 +612,224 @@ impl<'a> ExprVisitor<()> for Resolver<'a> {
         .last()
         .unwrap()
         .borrow()
-        .get(&expr.name.as_string())
-        .copied()
+        .get(&expr.name.symbol())
+        .map(|variable| variable.defined)
         == Some(false)
     {
-      Err(SaturdayResult::runtime_error(
+      self.error(
         &expr.name,
         "Can't read local variable in its own initializer.",
-      ))
+      );
     } else {
-      self.resolve_local(wrapper, &expr.name);
-      Ok(())
+      self.resolve_local(wrapper, &expr.name, true);
     }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::error::{set_error_format, take_captured_errors, ErrorFormat};
+  use crate::object::Object;
+  use crate::parser::Parser;
+  use crate::scanner::Scanner;
+
+  fn resolve_source<'a>(interpreter: &'a Interpreter, source: &str) -> Resolver<'a> {
+    let mut scanner = Scanner::new(source.to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Parser::new(tokens).parse().ok().unwrap();
+    let resolver = Resolver::new(interpreter);
+    assert!(resolver.resolve(&Rc::new(statements)).is_ok());
+    resolver
+  }
+
+  #[test]
+  fn unused_parameter_is_reported_once() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "fun f(a, b) { print a; }");
+    assert!(resolver.success());
+    assert_eq!(*resolver.warnings.borrow(), 1);
+  }
+
+  #[test]
+  fn assigned_but_never_read_is_reported() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "fun f() { def a = 1; a = 2; }");
+    assert!(resolver.success());
+    assert_eq!(*resolver.warnings.borrow(), 1);
+
+    let resolver = resolve_source(&interpreter, "fun f() { def a = 1; a = 2; print a; }");
+    assert_eq!(*resolver.warnings.borrow(), 0);
+  }
+
+  #[test]
+  fn used_parameter_is_not_reported() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "fun f(a) { print a; }");
+    assert!(resolver.success());
+    assert_eq!(*resolver.warnings.borrow(), 0);
+  }
+
+  #[test]
+  fn return_inside_function_resolves() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "fun f() { return 1; }");
+    assert!(resolver.success());
+  }
+
+  #[test]
+  fn return_at_top_level_is_an_error() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "return 1;");
+    assert!(!resolver.success());
+  }
+
+  #[test]
+  fn break_inside_loop_resolves() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "while true { while true { break; } break; }");
+    assert!(resolver.success());
+  }
+
+  #[test]
+  fn break_at_top_level_is_an_error() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "break;");
+    assert!(!resolver.success());
+  }
+
+  #[test]
+  fn break_inside_function_in_loop_is_an_error() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "while true { fun f() { break; } }");
+    assert!(!resolver.success());
+  }
+
+  #[test]
+  fn assigning_local_constant_is_an_error() {
+    let interpreter = Interpreter::new();
+    set_error_format(ErrorFormat::Captured);
+    let resolver = resolve_source(&interpreter, "{ const a = 1; a = 2; }");
+    set_error_format(ErrorFormat::Human);
+    assert!(!resolver.success());
+    let errors = take_captured_errors();
+    assert!(errors.contains("Can't assign to constant 'a'."), "{errors}");
+
+    let resolver = resolve_source(&interpreter, "{ const a = 1; print a; }");
+    assert!(resolver.success());
+  }
+
+  #[test]
+  fn catch_variable_is_scoped_to_handler() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "{ try { throw 1; } catch (e) { print e; } }");
+    assert!(resolver.success());
+    assert_eq!(*resolver.warnings.borrow(), 0);
+
+    let resolver = resolve_source(&interpreter, "{ try { throw 1; } catch (e) { } }");
+    assert_eq!(*resolver.warnings.borrow(), 1);
+  }
+
+  #[test]
+  fn initializer_cannot_return_a_value() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "class A { init() { return 1; } }");
+    assert!(!resolver.success());
+
+    let resolver = resolve_source(&interpreter, "class A { init() { return; } }");
+    assert!(resolver.success());
+  }
+
+  #[test]
+  fn resolver_and_interpreter_share_the_visitor_traits() {
+    fn expr_visitor<T>(_: &dyn ExprVisitor<T>) {}
+    fn stmt_visitor<T>(_: &dyn StmtVisitor<T>) {}
+
+    let interpreter = Interpreter::new();
+    let resolver = Resolver::new(&interpreter);
+    expr_visitor::<()>(&resolver);
+    stmt_visitor::<()>(&resolver);
+    expr_visitor::<Object>(&interpreter);
+    stmt_visitor::<()>(&interpreter);
+
+    // 两者都从同一个 Rc 包装接收节点，resolver 记录的距离可以被 interpreter 查到
+    let mut scanner = Scanner::new("{ def a = 1; print a; }".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let statements = Rc::new(Parser::new(tokens).parse().ok().unwrap());
+    assert!(resolver.resolve(&statements).is_ok());
+    assert!(interpreter.interpreter(&statements).is_ok());
+  }
+
+  #[test]
+  fn unknown_loop_labels_are_errors() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(&interpreter, "outer: while true { break outer; }");
+    assert!(resolver.success());
+
+    let resolver = resolve_source(&interpreter, "outer: while true { break inner; }");
+    assert!(!resolver.success());
+
+    let resolver = resolve_source(
+      &interpreter,
+      "outer: while true { fun f() { while true { continue outer; } } }",
+    );
+    assert!(!resolver.success());
+
+    let resolver = resolve_source(&interpreter, "continue;");
+    assert!(!resolver.success());
+  }
+
+  #[test]
+  fn this_and_super_only_inside_classes() {
+    let interpreter = Interpreter::new();
+    for source in [
+      "this;",
+      "fun f() { return this; }",
+      "super.method;",
+      "class A { m() { return super.m(); } }",
+      "class A < A {}",
+    ] {
+      assert!(!resolve_source(&interpreter, source).success(), "{source}");
+    }
+    let resolver = resolve_source(
+      &interpreter,
+      "class A { m() { return this; } } class B < A { m() { return super.m(); } }",
+    );
+    assert!(resolver.success());
+  }
+
+  #[test]
+  fn global_declarations() {
+    let interpreter = Interpreter::new();
+    for source in [
+      "global x;",
+      "{ global x; }",
+      "fun f() { global x; def x = 1; }",
+      "fun f(x) { global x; }",
+    ] {
+      assert!(!resolve_source(&interpreter, source).success(), "{source}");
+    }
+
+    let resolver = resolve_source(
+      &interpreter,
+      "const limit = 1; fun f() { global limit; fun g() { limit = 2; } g(); }",
+    );
+    assert!(resolver.success());
+    assert_eq!(*resolver.warnings.borrow(), 0);
+  }
+
+  #[test]
+  fn independent_errors_are_all_reported() {
+    let interpreter = Interpreter::new();
+    let resolver = resolve_source(
+      &interpreter,
+      "fun f() { def a = a; def b = 1; def b = 2; print b; }
+       print this;
+       return 1;",
+    );
+    assert!(!resolver.success());
+    assert_eq!(*resolver.errors.borrow(), 4);
+    assert_eq!(*resolver.warnings.borrow(), 1);
   }
 }
@@ -2,38 +2,88 @@ use crate::callable::SaturdayCallable;
 use crate::error::SaturdayResult;
 use crate::interpreter::Interpreter;
 use crate::object::Object;
+use crate::saturday_function::SaturdayFunction;
 use crate::saturday_instance::SaturdayInstance;
 use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
 use std::rc::Rc;
 
 #[derive(Debug, Clone, PartialEq)]
 pub struct SaturdayClass {
   name: String,
-  methods: HashMap<String, Object>,
+  superclass: Option<Rc<SaturdayClass>>,
+  methods: HashMap<String, Rc<SaturdayFunction>>,
+  getters: HashMap<String, Rc<SaturdayFunction>>,
+  statics: HashMap<String, Object>,
 }
 
 impl SaturdayClass {
-  pub fn new(name: String, methods: HashMap<String, Object>) -> Self {
-    Self { name, methods }
+  pub fn new(
+    name: String,
+    superclass: Option<Rc<SaturdayClass>>,
+    methods: HashMap<String, Rc<SaturdayFunction>>,
+    getters: HashMap<String, Rc<SaturdayFunction>>,
+    statics: HashMap<String, Object>,
+  ) -> Self {
+    Self {
+      name,
+      superclass,
+      methods,
+      getters,
+      statics,
+    }
   }
 
+  /// 创建实例，有 `init` 方法时绑定到新实例上调用
   pub fn instantiate(
-    &self,
-    _interpreter: &Interpreter,
-    _arguments: Vec<Object>,
-    class: Rc<SaturdayClass>,
+    class: &Rc<SaturdayClass>,
+    interpreter: &Interpreter,
+    arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult> {
-    Ok(Object::Instance(Rc::new(SaturdayInstance::new(class))))
+    let instance = Rc::new(SaturdayInstance::new(Rc::clone(class)));
+    if let Some(initializer) = class.find_method("init") {
+      initializer
+        .bind(Rc::clone(&instance))
+        .call(interpreter, arguments)?;
+    }
+
+    Ok(Object::Instance(instance))
   }
 
-  pub fn find_method(&self, name: &str) -> Option<Object> {
-    self.methods.get(name).cloned()
+  /// 自身没有时到父类中查找，下同
+  pub fn find_method(&self, name: &str) -> Option<Rc<SaturdayFunction>> {
+    self.methods.get(name).cloned().or_else(|| {
+      self
+        .superclass
+        .as_ref()
+        .and_then(|superclass| superclass.find_method(name))
+    })
+  }
+
+  pub fn find_getter(&self, name: &str) -> Option<Rc<SaturdayFunction>> {
+    self.getters.get(name).cloned().or_else(|| {
+      self
+        .superclass
+        .as_ref()
+        .and_then(|superclass| superclass.find_getter(name))
+    })
+  }
+
+  /// 通过类本身访问的静态方法
+  pub fn find_static(&self, name: &str) -> Option<Object> {
+    self.statics.get(name).cloned().or_else(|| {
+      self
+        .superclass
+        .as_ref()
+        .and_then(|superclass| superclass.find_static(name))
+    })
   }
 }
 
-impl ToString for SaturdayClass {
-  fn to_string(&self) -> String {
-    self.name.clone()
+impl fmt::Display for SaturdayClass {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.name)
   }
 }
 
@@ -46,7 +96,25 @@ impl SaturdayCallable for SaturdayClass {
     Err(SaturdayResult::system_error("tried to call a class"))
   }
 
+  fn name(&self) -> String {
+    self.name.clone()
+  }
+
+  fn describe(&self) -> String {
+    format!("<Class {}>", self.name)
+  }
+
   fn arity(&self) -> usize {
-    0
+    self.find_method("init").map_or(0, |init| init.arity())
+  }
+
+  fn min_arity(&self) -> usize {
+    self.find_method("init").map_or(0, |init| init.min_arity())
+  }
+
+  fn is_variadic(&self) -> bool {
+    self
+      .find_method("init")
+      .is_some_and(|init| init.is_variadic())
   }
 }
@@ -1,29 +1,118 @@
-use crate::callable::SaturdayCallable;
+use crate::callable::{Callable, SaturdayCallable};
 use crate::environment::Environment;
 use crate::error::SaturdayResult;
+use crate::expr::{CallExpr, Expr};
 use crate::interpreter::Interpreter;
 use crate::object::Object;
+use crate::saturday_instance::SaturdayInstance;
 use crate::stmt::{FunctionStmt, Stmt};
 use crate::token::Token;
 use std::cell::RefCell;
+use std::fmt;
+use std::fmt::Formatter;
 use std::rc::Rc;
 
 pub struct SaturdayFunction {
   name: Token,
   params: Rc<Vec<Token>>,
+  defaults: Rc<Vec<Option<Rc<Expr>>>>,
+  rest: Option<Token>,
   body: Rc<Vec<Rc<Stmt>>>,
   closure: Rc<RefCell<Environment>>,
+  /// 类的 `init` 方法，调用后总是返回 `this`
+  is_initializer: bool,
 }
 
 impl SaturdayFunction {
-  pub fn new(declaration: &FunctionStmt, closure: &Rc<RefCell<Environment>>) -> Self {
+  pub fn new(
+    declaration: &FunctionStmt,
+    closure: &Rc<RefCell<Environment>>,
+    is_initializer: bool,
+  ) -> Self {
     Self {
       name: declaration.name.dup(),
       params: Rc::clone(&declaration.params),
+      defaults: Rc::clone(&declaration.defaults),
+      rest: declaration.rest.as_ref().map(Token::dup),
       body: Rc::clone(&declaration.body),
       closure: Rc::clone(closure),
+      is_initializer,
     }
   }
+
+  /// 声明处的名字，调用不经过调用表达式时用来报告位置
+  pub fn name_token(&self) -> &Token {
+    &self.name
+  }
+
+  /// # 绑定实例
+  /// 返回一个新的方法，其闭包外多一层定义了 `this` 的环境
+  pub fn bind(&self, instance: Rc<SaturdayInstance>) -> SaturdayFunction {
+    let mut environment = Environment::new_with_enclosing(Rc::clone(&self.closure));
+    environment.define("this", Object::Instance(instance));
+    Self {
+      name: self.name.dup(),
+      params: Rc::clone(&self.params),
+      defaults: Rc::clone(&self.defaults),
+      rest: self.rest.as_ref().map(Token::dup),
+      body: Rc::clone(&self.body),
+      closure: Rc::new(RefCell::new(environment)),
+      is_initializer: self.is_initializer,
+    }
+  }
+
+  fn this(&self) -> Result<Object, SaturdayResult> {
+    self.closure.borrow().get_at(0, "this")
+  }
+
+  /// 创建调用使用的环境，绑定参数、默认值和剩余参数
+  fn bind_arguments(
+    &self,
+    interpreter: &Interpreter,
+    arguments: &[Object],
+  ) -> Result<Environment, SaturdayResult> {
+    let mut e = Environment::new_with_enclosing(Rc::clone(&self.closure));
+    for (i, (param, default)) in self.params.iter().zip(self.defaults.iter()).enumerate() {
+      let value = match (arguments.get(i), default) {
+        (Some(arg), _) => arg.clone(),
+        // 缺省的参数在函数定义时的闭包环境中求值
+        (None, Some(default)) => {
+          interpreter.evaluate_in(Rc::clone(default), Rc::clone(&self.closure))?
+        }
+        (None, None) => Object::Nil,
+      };
+      e.define(param.symbol(), value);
+    }
+
+    if let Some(rest) = &self.rest {
+      let extra = arguments.iter().skip(self.params.len()).cloned().collect();
+      e.define(rest.symbol(), Object::list(extra));
+    }
+
+    Ok(e)
+  }
+
+  /// 函数体最后一条语句是 `return name(...)` 时返回该调用
+  fn tail_call(&self) -> Option<&CallExpr> {
+    let Stmt::Return(stmt) = self.body.last()?.as_ref() else {
+      return None;
+    };
+    let Expr::Call(call) = stmt.value.as_deref()? else {
+      return None;
+    };
+
+    match call.callee.as_ref() {
+      Expr::Variable(callee) if callee.name.lexeme == self.name.lexeme => Some(call),
+      _ => None,
+    }
+  }
+
+  fn is_self(&self, callee: &Callable) -> bool {
+    std::ptr::eq(
+      Rc::as_ptr(&callee.func) as *const (),
+      self as *const Self as *const (),
+    )
+  }
 }
 
 impl SaturdayCallable for SaturdayFunction {
@@ -32,25 +121,102 @@ impl SaturdayCallable for SaturdayFunction {
     interpreter: &Interpreter,
     arguments: Vec<Object>,
   ) -> Result<Object, SaturdayResult> {
-    let mut e = Environment::new_with_enclosing(Rc::clone(&self.closure));
-    for (param, arg) in self.params.iter().zip(arguments.iter()) {
-      e.define(&param.as_string(), arg.clone());
-    }
+    let mut arguments = arguments;
+    // 尾部的自调用不再递归，而是用新的参数重新执行函数体
+    loop {
+      let e = self.bind_arguments(interpreter, &arguments)?;
+      let result = match self.tail_call() {
+        Some(tail) => {
+          interpreter.execute_tail_block(&self.body, tail, e, |callee| self.is_self(callee))
+        }
+        None => interpreter.execute_block(&self.body, e).map(|_| None),
+      };
 
-    match interpreter.execute_block(&self.body, e) {
-      Err(SaturdayResult::ReturnValue { value }) => Ok(value),
-      Err(e) => Err(e),
-      Ok(_) => Ok(Object::Nil),
+      match result {
+        Ok(Some(next)) => arguments = next,
+        Ok(None) | Err(SaturdayResult::ReturnValue { .. }) if self.is_initializer => {
+          return self.this();
+        }
+        Ok(None) => return Ok(Object::Nil),
+        Err(SaturdayResult::ReturnValue { value }) => return Ok(value),
+        Err(e) => return Err(e),
+      }
     }
   }
 
+  fn name(&self) -> String {
+    self.name.as_string()
+  }
+
+  fn describe(&self) -> String {
+    self.to_string()
+  }
+
   fn arity(&self) -> usize {
     self.params.len()
   }
+
+  fn min_arity(&self) -> usize {
+    self
+      .defaults
+      .iter()
+      .filter(|default| default.is_none())
+      .count()
+  }
+
+  fn is_variadic(&self) -> bool {
+    self.rest.is_some()
+  }
+}
+
+impl fmt::Display for SaturdayFunction {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "<fn {}>", self.name.as_string())
+  }
+}
+
+impl fmt::Debug for SaturdayFunction {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "<fn {}>", self.name.as_string())
+  }
 }
 
-impl ToString for SaturdayFunction {
-  fn to_string(&self) -> String {
-    format!("<Function {}>", self.name.as_string())
+impl PartialEq for SaturdayFunction {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::saturday_class::SaturdayClass;
+  use crate::token_type::TokenType;
+  use std::collections::HashMap;
+
+  #[test]
+  fn bound_closure_sees_the_instance() {
+    let declaration = FunctionStmt {
+      name: Token::new(TokenType::Identifier, "method".to_string(), None, 1, 1),
+      params: Rc::new(Vec::new()),
+      defaults: Rc::new(Vec::new()),
+      rest: None,
+      body: Rc::new(Vec::new()),
+      getter: false,
+    };
+    let globals = Rc::new(RefCell::new(Environment::new()));
+    let method = SaturdayFunction::new(&declaration, &globals, false);
+
+    let class = Rc::new(SaturdayClass::new(
+      "Thing".to_string(),
+      None,
+      HashMap::new(),
+      HashMap::new(),
+      HashMap::new(),
+    ));
+    let instance = Rc::new(SaturdayInstance::new(class));
+    let bound = method.bind(Rc::clone(&instance));
+
+    assert_eq!(bound.this().ok(), Some(Object::Instance(instance)));
   }
 }
@@ -1,13 +1,18 @@
+use crate::callable::{Callable, SaturdayCallable};
 use crate::error::SaturdayResult;
+use crate::interpreter::Interpreter;
 use crate::object::Object;
 use crate::saturday_class::SaturdayClass;
 use crate::token::Token;
 use std::cell::RefCell;
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
 use std::rc::Rc;
 
-#[derive(Debug, Clone, PartialEq)]
+/// 实例之间的 `==` 比较身份，值相等使用 `equals`
+#[derive(Debug, Clone)]
 pub struct SaturdayInstance {
   pub class: Rc<SaturdayClass>,
   fields: RefCell<HashMap<String, Object>>,
@@ -21,11 +26,25 @@ impl SaturdayInstance {
     }
   }
 
-  pub fn get(&self, name: &Token) -> Result<Object, SaturdayResult> {
+  /// # 读取属性
+  /// 依次查找字段、getter 和方法，getter 会被立即调用
+  pub fn get(
+    self: &Rc<Self>,
+    name: &Token,
+    interpreter: &Interpreter,
+  ) -> Result<Object, SaturdayResult> {
     if let Entry::Occupied(o) = self.fields.borrow_mut().entry(name.as_string()) {
-      Ok(o.get().clone())
+      return Ok(o.get().clone());
+    }
+
+    if let Some(getter) = self.class.find_getter(&name.as_string()) {
+      interpreter.guarded_call(name, || {
+        getter.bind(Rc::clone(self)).call(interpreter, Vec::new())
+      })
     } else if let Some(method) = self.class.find_method(&name.as_string()) {
-      Ok(method)
+      Ok(Object::Func(Callable {
+        func: Rc::new(method.bind(Rc::clone(self))),
+      }))
     } else {
       Err(SaturdayResult::runtime_error(
         name,
@@ -37,10 +56,35 @@ impl SaturdayInstance {
   pub fn set(&self, name: &Token, value: Object) {
     self.fields.borrow_mut().insert(name.as_string(), value);
   }
+
+  /// 所有字段的快照
+  pub fn fields(&self) -> Vec<(String, Object)> {
+    self
+      .fields
+      .borrow()
+      .iter()
+      .map(|(name, value)| (name.clone(), value.clone()))
+      .collect()
+  }
+
+  pub fn set_field(&self, name: String, value: Object) {
+    self.fields.borrow_mut().insert(name, value);
+  }
+
+  /// 值相等：属于同一个类且字段全部相等
+  pub fn equals(&self, other: &SaturdayInstance) -> bool {
+    Rc::ptr_eq(&self.class, &other.class) && *self.fields.borrow() == *other.fields.borrow()
+  }
+}
+
+impl PartialEq for SaturdayInstance {
+  fn eq(&self, other: &Self) -> bool {
+    std::ptr::eq(self, other)
+  }
 }
 
-impl ToString for SaturdayInstance {
-  fn to_string(&self) -> String {
-    format!("<Instance of {}>", self.class.to_string())
+impl fmt::Display for SaturdayInstance {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "<Instance of {}>", self.class)
   }
 }
@@ -10,6 +10,10 @@ pub struct Scanner {
   start: usize,
   current: usize,
   line: usize,
+  /// 当前 token 开始的行，跨行的字符串结束时 `line` 已经前进
+  start_line: usize,
+  /// 每层未闭合的 `${` 内部的花括号深度
+  interpolations: Vec<usize>,
 }
 
 impl Scanner {
@@ -20,31 +24,57 @@ impl Scanner {
       start: 0,
       current: 0,
       line: 1,
+      start_line: 1,
+      interpolations: Vec::new(),
     }
   }
 
+  /// 把文件内容解码成源码，非法的 UTF-8 报告所在行而不是直接崩溃
+  pub fn decode(bytes: Vec<u8>) -> Result<String, SaturdayResult> {
+    String::from_utf8(bytes).map_err(|e| {
+      let valid = e.utf8_error().valid_up_to();
+      let bytes = e.as_bytes();
+      let line = bytes[..valid].iter().filter(|b| **b == b'\n').count() + 1;
+      SaturdayResult::error(line, &format!("Invalid UTF-8 byte 0x{:02X}.", bytes[valid]))
+    })
+  }
+
   /// # 开始解析token
-  /// ```
+  /// ```text
   /// 通过scan_token逐个解析
   /// ```
   pub fn scan_tokens(&mut self) -> Result<&Vec<Token>, SaturdayResult> {
-    let mut had_error: Option<SaturdayResult> = None;
+    let (tokens, mut errors) = self.scan_with_errors();
+    if errors.is_empty() {
+      Ok(tokens)
+    } else {
+      Err(errors.remove(0))
+    }
+  }
+
+  /// # 收集全部词法错误
+  /// 出错的字符被跳过，扫描一直持续到末尾。
+  /// 同时返回已识别的 token 和所有错误，由调用方决定是否继续
+  pub fn scan_with_errors(&mut self) -> (&Vec<Token>, Vec<SaturdayResult>) {
+    let mut errors = Vec::new();
+    self.skip_shebang();
     while !self.is_at_end() {
       self.start = self.current;
-      match self.scan_token() {
-        Ok(()) => {}
-        Err(e) => {
-          had_error = Some(e);
-        }
+      self.start_line = self.line;
+      if let Err(e) = self.scan_token() {
+        errors.push(e);
       }
     }
 
-    self.tokens.push(Token::eof(self.line));
-    if let Some(e) = had_error {
-      Err(e)
-    } else {
-      Ok(&self.tokens)
+    if !self.interpolations.is_empty() {
+      errors.push(SaturdayResult::error(
+        self.line,
+        "Unterminated string interpolation.",
+      ));
     }
+
+    self.tokens.push(Token::eof(self.line));
+    (&self.tokens, errors)
   }
 
   /// 判断内容是否解析完成
@@ -58,14 +88,81 @@ impl Scanner {
     match c {
       '(' => self.add_token(TokenType::LeftParen),
       ')' => self.add_token(TokenType::RightParen),
-      '{' => self.add_token(TokenType::LeftBrace),
-      '}' => self.add_token(TokenType::RightBrace),
+      '{' => {
+        if let Some(depth) = self.interpolations.last_mut() {
+          *depth += 1;
+        }
+        self.add_token(TokenType::LeftBrace);
+      }
+      '}' => match self.interpolations.last_mut() {
+        // 插值表达式结束，继续扫描字符串剩余部分
+        Some(0) => {
+          self.interpolations.pop();
+          self.string()?;
+        }
+        Some(depth) => {
+          *depth -= 1;
+          self.add_token(TokenType::RightBrace);
+        }
+        None => self.add_token(TokenType::RightBrace),
+      },
       ',' => self.add_token(TokenType::Comma),
-      '.' => self.add_token(TokenType::Dot),
-      '-' => self.add_token(TokenType::Minus),
-      '+' => self.add_token(TokenType::Plus),
+      '.' => {
+        if self.peek() == Some('.') && self.peek_next() == Some('.') {
+          self.advance();
+          self.advance();
+          self.add_token(TokenType::Ellipsis);
+        } else {
+          self.add_token(TokenType::Dot);
+        }
+      }
+      '-' => {
+        let tok = if self.r#match('=') {
+          TokenType::MinusAssign
+        } else if self.peek() == Some('-') && self.postfix_position() {
+          self.advance();
+          TokenType::MinusMinus
+        } else {
+          TokenType::Minus
+        };
+
+        self.add_token(tok);
+      }
+      '+' => {
+        let tok = if self.r#match('=') {
+          TokenType::PlusAssign
+        } else if self.peek() == Some('+') && self.postfix_position() {
+          self.advance();
+          TokenType::PlusPlus
+        } else {
+          TokenType::Plus
+        };
+
+        self.add_token(tok);
+      }
       ';' => self.add_token(TokenType::SemiColon),
-      '*' => self.add_token(TokenType::Star),
+      ':' => self.add_token(TokenType::Colon),
+      '&' => self.add_token(TokenType::Ampersand),
+      '|' => self.add_token(TokenType::Pipe),
+      '^' => self.add_token(TokenType::Caret),
+      '*' => {
+        let tok = if self.r#match('*') {
+          TokenType::StarStar
+        } else if self.r#match('=') {
+          TokenType::StarAssign
+        } else {
+          TokenType::Star
+        };
+
+        self.add_token(tok);
+      }
+      '?' => {
+        if !self.r#match('?') {
+          return Err(self.unexpected(c));
+        }
+
+        self.add_token(TokenType::QuestionQuestion);
+      }
       '!' => {
         let tok = if self.r#match('=') {
           TokenType::BangEqual
@@ -87,6 +184,8 @@ impl Scanner {
       '<' => {
         let tok = if self.r#match('=') {
           TokenType::LessEqual
+        } else if self.r#match('<') {
+          TokenType::LessLess
         } else {
           TokenType::Less
         };
@@ -96,6 +195,8 @@ impl Scanner {
       '>' => {
         let tok = if self.r#match('=') {
           TokenType::GreaterEqual
+        } else if self.r#match('>') {
+          TokenType::GreaterGreater
         } else {
           TokenType::Greater
         };
@@ -115,6 +216,8 @@ impl Scanner {
         } else if self.r#match('*') {
           // 匹配块级注释
           self.scan_comment()?;
+        } else if self.r#match('=') {
+          self.add_token(TokenType::SlashAssign);
         } else {
           self.add_token(TokenType::Slash);
         }
@@ -127,13 +230,13 @@ impl Scanner {
         self.string()?;
       }
       '0'..='9' => {
-        self.number();
+        self.number()?;
       }
       _ if c.is_ascii_alphabetic() || c == '_' => {
         self.identifier();
       }
       _ => {
-        return Err(SaturdayResult::error(self.line, "Unexpected character"));
+        return Err(self.unexpected(c));
       }
     }
 
@@ -171,7 +274,7 @@ impl Scanner {
 
   fn is_alpha_numeric(ch: Option<char>) -> bool {
     if let Some(ch) = ch {
-      ch.is_ascii_alphanumeric()
+      ch.is_ascii_alphanumeric() || ch == '_'
     } else {
       false
     }
@@ -190,23 +293,115 @@ impl Scanner {
     }
   }
 
-  fn number(&mut self) {
-    while Scanner::is_digit(self.peek()) {
-      self.advance();
+  /// # 数字字面量
+  /// 支持 `1_000` 形式的分隔符，以及 `0x` `0b` `0o` 前缀的整数
+  fn number(&mut self) -> Result<(), SaturdayResult> {
+    if self.source[self.start] == '0' {
+      let radix = match self.peek() {
+        Some('x') | Some('X') => Some(16),
+        Some('b') | Some('B') => Some(2),
+        Some('o') | Some('O') => Some(8),
+        _ => None,
+      };
+
+      if let Some(radix) = radix {
+        self.advance();
+        return self.radix_number(radix);
+      }
     }
 
+    self.digits(self.start, |ch| ch.is_ascii_digit())?;
     if self.peek() == Some('.') && Scanner::is_digit(self.peek_next()) {
       // consume the "."
       self.advance();
+      self.digits(self.current, |ch| ch.is_ascii_digit())?;
+    }
+
+    let value: String = self.source[self.start..self.current]
+      .iter()
+      .filter(|ch| **ch != '_')
+      .collect();
+    let value: f64 = value.parse().unwrap();
+    self.add_token_object(TokenType::Number, Some(Object::Num(value)));
+    Ok(())
+  }
+
+  fn radix_number(&mut self, radix: u32) -> Result<(), SaturdayResult> {
+    let digits_start = self.current;
+    // 把紧跟的字母数字都当作数字的一部分，以便报告非法的数字
+    self.digits(digits_start, |ch| ch.is_ascii_alphanumeric())?;
+
+    let digits: String = self.source[digits_start..self.current]
+      .iter()
+      .filter(|ch| **ch != '_')
+      .collect();
+    if digits.is_empty() {
+      return Err(SaturdayResult::error(
+        self.line,
+        "Expect digits after number prefix.",
+      ));
+    }
+
+    if let Some(ch) = digits.chars().find(|ch| !ch.is_digit(radix)) {
+      return Err(SaturdayResult::error(
+        self.line,
+        &format!("Invalid digit '{ch}' in base {radix} number."),
+      ));
+    }
 
-      while Scanner::is_digit(self.peek()) {
+    match u64::from_str_radix(&digits, radix) {
+      Ok(value) => {
+        self.add_token_object(TokenType::Number, Some(Object::Num(value as f64)));
+        Ok(())
+      }
+      Err(_) => Err(SaturdayResult::error(
+        self.line,
+        "Number literal is too large.",
+      )),
+    }
+  }
+
+  /// 扫描一组数字（可含 `_` 分隔符），`_` 不能出现在开头或结尾
+  fn digits(
+    &mut self,
+    group_start: usize,
+    is_digit: fn(char) -> bool,
+  ) -> Result<(), SaturdayResult> {
+    while let Some(ch) = self.peek() {
+      if is_digit(ch) || ch == '_' {
         self.advance();
+      } else {
+        break;
       }
     }
 
-    let value: String = self.source[self.start..self.current].iter().collect();
-    let value: f64 = value.parse().unwrap();
-    self.add_token_object(TokenType::Number, Some(Object::Num(value)));
+    let group = &self.source[group_start..self.current];
+    if group.first() == Some(&'_') || group.last() == Some(&'_') {
+      return Err(SaturdayResult::error(
+        self.line,
+        "Underscore must separate digits in a number.",
+      ));
+    }
+
+    Ok(())
+  }
+
+  /// `++`、`--` 只在紧跟操作数、后面不是操作数时才是后缀运算符，
+  /// 所以 `--5`、`- -x` 写成 `--x`、`a--b` 仍然是两个减号
+  fn postfix_position(&self) -> bool {
+    let after_operand = self.tokens.last().is_some_and(|token| {
+      token.is_one_of(&[
+        TokenType::Identifier,
+        TokenType::Number,
+        TokenType::String,
+        TokenType::RightParen,
+        TokenType::This,
+      ]) && token.start + token.len == self.start
+    });
+    let operand_follows = self
+      .peek_next()
+      .is_some_and(|ch| ch.is_alphanumeric() || matches!(ch, '_' | '(' | '"' | '.'));
+    after_operand && !operand_follows
   }
 
   fn peek_next(&self) -> Option<char> {
@@ -222,16 +417,40 @@ impl Scanner {
   }
 
   fn string(&mut self) -> Result<(), SaturdayResult> {
+    let mut value = String::new();
+    let mut had_error = None;
     while let Some(ch) = self.peek() {
       match ch {
         '"' => {
           break;
         }
+        '$' if self.peek_next() == Some('{') => {
+          // `${` 开始插值：输出之前的部分，回到主循环扫描表达式
+          self.advance();
+          self.advance();
+          self.interpolations.push(0);
+          self.add_token_object(TokenType::Interpolation, Some(Object::Str(value.into())));
+          return match had_error {
+            Some(e) => Err(e),
+            None => Ok(()),
+          };
+        }
+        '\\' => {
+          self.advance();
+          match self.escape() {
+            Ok(Some(escaped)) => value.push(escaped),
+            Ok(None) => break,
+            // 记录错误后继续扫描到字符串结尾，避免剩余内容被当成token
+            Err(e) => had_error = Some(e),
+          }
+          continue;
+        }
         '\n' => {
           self.line += 1;
         }
         _ => {}
       }
+      value.push(ch);
       self.advance();
     }
 
@@ -240,14 +459,52 @@ impl Scanner {
     }
 
     self.advance();
-    // TODO: handle escape sequence
-    let value: String = self.source[self.start + 1..self.current - 1]
-      .iter()
-      .collect();
-    self.add_token_object(TokenType::String, Some(Object::Str(value)));
+    if let Some(e) = had_error {
+      return Err(e);
+    }
+
+    self.add_token_object(TokenType::String, Some(Object::Str(value.into())));
     Ok(())
   }
 
+  /// # 转义字符
+  /// 支持 `\n` `\t` `\r` `\\` `\"` `\0` `\$`，到达结尾时返回None
+  fn escape(&mut self) -> Result<Option<char>, SaturdayResult> {
+    let escaped = match self.peek() {
+      Some('n') => '\n',
+      Some('t') => '\t',
+      Some('r') => '\r',
+      Some('\\') => '\\',
+      Some('"') => '"',
+      Some('0') => '\0',
+      Some('$') => '$',
+      Some(ch) => {
+        self.advance();
+        if ch == '\n' {
+          self.line += 1;
+        }
+
+        return Err(SaturdayResult::error(
+          self.line,
+          &format!("Unknown escape sequence '\\{ch}'."),
+        ));
+      }
+      None => return Ok(None),
+    };
+
+    self.advance();
+    Ok(Some(escaped))
+  }
+
+  /// 脚本第一行的 `#!` 交给系统选择解释器，扫描时跳过，换行照常计数
+  fn skip_shebang(&mut self) {
+    if self.current == 0 && self.source.starts_with(&['#', '!']) {
+      while self.peek().is_some_and(|ch| ch != '\n') {
+        self.advance();
+      }
+    }
+  }
+
   fn advance(&mut self) -> char {
     let result = *self.source.get(self.current).unwrap();
     self.current += 1;
@@ -259,10 +516,30 @@ impl Scanner {
   }
 
   fn add_token_object(&mut self, t_type: TokenType, literal: Option<Object>) {
-    let lexeme = self.source[self.start..self.current].iter().collect();
-    self
-      .tokens
-      .push(Token::new(t_type, lexeme, literal, self.line));
+    let lexeme: String = self.source[self.start..self.current].iter().collect();
+    let mut token = Token::new(t_type, lexeme, literal, self.start_line, self.column());
+    token.start = self.start;
+    token.len = self.current - self.start;
+    self.tokens.push(token);
+  }
+
+  /// 报告意外字符，控制字符用码位表示，避免把不可见字符直接打印出来
+  fn unexpected(&self, c: char) -> SaturdayResult {
+    let message = if c.is_control() {
+      format!("Unexpected control character U+{:04X}.", c as u32)
+    } else {
+      format!("Unexpected character '{}'.", c)
+    };
+    SaturdayResult::error(self.line, &message)
+  }
+
+  /// 当前 token 起始位置在所在行中的列号
+  fn column(&self) -> usize {
+    let line_start = self.source[..self.start]
+      .iter()
+      .rposition(|ch| *ch == '\n')
+      .map_or(0, |position| position + 1);
+    self.start - line_start + 1
   }
 
   fn r#match(&mut self, expected: char) -> bool {
@@ -282,24 +559,306 @@ impl Scanner {
   fn keywords(check: &str) -> Option<TokenType> {
     match check {
       "and" => Some(TokenType::And),
+      "as" => Some(TokenType::As),
+      "assert" => Some(TokenType::Assert),
+      "catch" => Some(TokenType::Catch),
       "class" => Some(TokenType::Class),
+      "const" => Some(TokenType::Const),
       "else" => Some(TokenType::Else),
       "false" => Some(TokenType::False),
       "for" => Some(TokenType::For),
       "fun" => Some(TokenType::Fun),
+      "global" => Some(TokenType::Global),
       "if" => Some(TokenType::If),
+      "import" => Some(TokenType::Import),
+      "in" => Some(TokenType::In),
       "nil" => Some(TokenType::Nil),
       "or" => Some(TokenType::Or),
       "print" => Some(TokenType::Print),
+      "repeat" => Some(TokenType::Repeat),
       "return" => Some(TokenType::Return),
+      "static" => Some(TokenType::Static),
       "super" => Some(TokenType::Super),
       "this" => Some(TokenType::This),
+      "throw" => Some(TokenType::Throw),
       "true" => Some(TokenType::True),
+      "try" => Some(TokenType::Try),
+      "undef" => Some(TokenType::Undef),
       "var" => Some(TokenType::Var),
       "while" => Some(TokenType::While),
       "def" => Some(TokenType::Def),
+      "do" => Some(TokenType::Do),
       "break" => Some(TokenType::Break),
+      "continue" => Some(TokenType::Continue),
       _ => None,
     }
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn scan(source: &str) -> Option<Vec<Token>> {
+    let mut scanner = Scanner::new(source.to_string());
+    scanner.scan_tokens().ok().cloned()
+  }
+
+  fn scan_string(source: &str) -> Option<Object> {
+    scan(source)?[0].literal.clone()
+  }
+
+  #[test]
+  fn string_without_escapes_is_unchanged() {
+    assert_eq!(
+      scan_string("\"plain text\""),
+      Some(Object::Str("plain text".into()))
+    );
+  }
+
+  #[test]
+  fn string_escape_sequences() {
+    for (source, expected) in [
+      ("\"a\\nb\"", "a\nb"),
+      ("\"a\\tb\"", "a\tb"),
+      ("\"a\\rb\"", "a\rb"),
+      ("\"a\\\\b\"", "a\\b"),
+      ("\"a\\\"b\"", "a\"b"),
+      ("\"a\\0b\"", "a\0b"),
+    ] {
+      assert_eq!(
+        scan_string(source),
+        Some(Object::Str(expected.into())),
+        "scanning {source}"
+      );
+    }
+  }
+
+  #[test]
+  fn string_unknown_escape_is_an_error() {
+    assert!(scan("\"a\\qb\"").is_none());
+  }
+
+  #[test]
+  fn string_interpolation_tokens() {
+    let tokens = scan("\"a ${b} c\"").unwrap();
+    let types: Vec<TokenType> = tokens.iter().map(|t| t.token_type()).collect();
+    assert_eq!(
+      types,
+      vec![
+        TokenType::Interpolation,
+        TokenType::Identifier,
+        TokenType::String,
+        TokenType::Eof
+      ]
+    );
+    assert_eq!(tokens[0].literal, Some(Object::Str("a ".into())));
+    assert_eq!(tokens[2].literal, Some(Object::Str(" c".into())));
+  }
+
+  #[test]
+  fn string_escaped_interpolation_is_literal() {
+    assert_eq!(
+      scan_string("\"cost \\${x}\""),
+      Some(Object::Str("cost ${x}".into()))
+    );
+  }
+
+  #[test]
+  fn string_unclosed_interpolation_is_an_error() {
+    assert!(scan("\"a ${b").is_none());
+  }
+
+  fn scan_number(source: &str) -> Option<Object> {
+    let tokens = scan(source)?;
+    assert_eq!(tokens.len(), 2, "scanning {source}");
+    tokens[0].literal.clone()
+  }
+
+  #[test]
+  fn number_with_underscores() {
+    assert_eq!(scan_number("1_000_000"), Some(Object::Num(1_000_000.0)));
+    assert_eq!(scan_number("1_0.2_5"), Some(Object::Num(10.25)));
+  }
+
+  #[test]
+  fn number_alternate_bases() {
+    assert_eq!(scan_number("0xFF"), Some(Object::Num(255.0)));
+    assert_eq!(scan_number("0xff_ff"), Some(Object::Num(65535.0)));
+    assert_eq!(scan_number("0b1010"), Some(Object::Num(10.0)));
+    assert_eq!(scan_number("0o17"), Some(Object::Num(15.0)));
+    assert_eq!(scan_number("0"), Some(Object::Num(0.0)));
+  }
+
+  #[test]
+  fn number_underscore_placement_errors() {
+    for source in ["1_", "1_.5", "1.5_", "0x_FF", "0b1010_"] {
+      assert!(scan(source).is_none(), "scanning {source}");
+    }
+  }
+
+  #[test]
+  fn number_malformed_literals() {
+    for source in ["0x", "0b102", "0o8", "0xFG", "0x1_0000_0000_0000_0000"] {
+      assert!(scan(source).is_none(), "scanning {source}");
+    }
+  }
+
+  #[test]
+  fn tokens_carry_columns_and_offsets() {
+    let tokens = scan("def total = 1;\n  total += 20;").unwrap();
+    let positions: Vec<(String, usize, usize, usize, usize)> = tokens
+      .iter()
+      .filter(|token| !token.is(TokenType::Eof))
+      .map(|token| {
+        (
+          token.as_string(),
+          token.line,
+          token.column,
+          token.start,
+          token.len,
+        )
+      })
+      .collect();
+    assert_eq!(
+      positions,
+      vec![
+        ("def".to_string(), 1, 1, 0, 3),
+        ("total".to_string(), 1, 5, 4, 5),
+        ("=".to_string(), 1, 11, 10, 1),
+        ("1".to_string(), 1, 13, 12, 1),
+        (";".to_string(), 1, 14, 13, 1),
+        ("total".to_string(), 2, 3, 17, 5),
+        ("+=".to_string(), 2, 9, 23, 2),
+        ("20".to_string(), 2, 12, 26, 2),
+        (";".to_string(), 2, 14, 28, 1),
+      ]
+    );
+  }
+
+  #[test]
+  fn identifiers_may_contain_underscores() {
+    let tokens = scan("assert_eq _tmp assert").unwrap();
+    let types: Vec<(TokenType, String)> = tokens
+      .iter()
+      .filter(|token| !token.is(TokenType::Eof))
+      .map(|token| (token.token_type(), token.as_string()))
+      .collect();
+    assert_eq!(
+      types,
+      vec![
+        (TokenType::Identifier, "assert_eq".to_string()),
+        (TokenType::Identifier, "_tmp".to_string()),
+        (TokenType::Assert, "assert".to_string()),
+      ]
+    );
+  }
+
+  #[test]
+  fn shebang_on_first_line_is_skipped() {
+    let tokens = scan("#!/usr/bin/env saturday\nprint 1;").unwrap();
+    assert_eq!(tokens[0].token_type(), TokenType::Print);
+    assert_eq!(tokens[0].line, 2);
+    assert!(scan("print 1;\n#!/usr/bin/env saturday").is_none());
+  }
+
+  fn scan_error(source: &str) -> String {
+    let mut scanner = Scanner::new(source.to_string());
+    match scanner.scan_tokens() {
+      Err(SaturdayResult::Error { line, message }) => format!("{}: {}", line, message),
+      _ => panic!("expected a scan error for {:?}", source),
+    }
+  }
+
+  #[test]
+  fn unexpected_characters_are_named() {
+    assert_eq!(
+      scan_error("print 1;\nprint \u{1};"),
+      "2: Unexpected control character U+0001."
+    );
+    assert_eq!(scan_error("def a = @;"), "1: Unexpected character '@'.");
+    assert_eq!(scan_error("a ? b"), "1: Unexpected character '?'.");
+  }
+
+  #[test]
+  fn crlf_line_endings_count_lines() {
+    let tokens = scan("def a = 1;\r\ndef b = 2;\r\nprint a;\r\n").unwrap();
+    let positions: Vec<(String, usize, usize)> = tokens
+      .iter()
+      .filter(|t| t.is_one_of(&[TokenType::Def, TokenType::Print]))
+      .map(|t| (t.lexeme.to_string(), t.line, t.column))
+      .collect();
+    assert_eq!(
+      positions,
+      vec![
+        ("def".to_string(), 1, 1),
+        ("def".to_string(), 2, 1),
+        ("print".to_string(), 3, 1)
+      ]
+    );
+    assert_eq!(tokens.last().unwrap().line, 4);
+  }
+
+  #[test]
+  fn invalid_utf8_reports_its_line() {
+    assert_eq!(
+      Scanner::decode(b"print 1;\r\n".to_vec()).unwrap(),
+      "print 1;\r\n"
+    );
+    match Scanner::decode(b"print 1;\nprint \"\xff\";".to_vec()) {
+      Err(SaturdayResult::Error { line, message }) => {
+        assert_eq!(line, 2);
+        assert_eq!(message, "Invalid UTF-8 byte 0xFF.");
+      }
+      _ => panic!("expected a decode error"),
+    }
+  }
+
+  #[test]
+  fn scanning_continues_after_errors() {
+    let mut scanner = Scanner::new("def a = @;\nprint a # 1;".to_string());
+    let (tokens, errors) = scanner.scan_with_errors();
+    let lexemes: Vec<&str> = tokens.iter().map(|t| &*t.lexeme).collect();
+    assert_eq!(
+      lexemes,
+      vec!["def", "a", "=", ";", "print", "a", "1", ";", ""]
+    );
+    let errors: Vec<String> = errors
+      .iter()
+      .map(|e| match e {
+        SaturdayResult::Error { line, message } => format!("{}: {}", line, message),
+        _ => panic!("unexpected error kind"),
+      })
+      .collect();
+    assert_eq!(
+      errors,
+      vec![
+        "1: Unexpected character '@'.",
+        "2: Unexpected character '#'."
+      ]
+    );
+  }
+
+  #[test]
+  fn increment_tokens_only_follow_an_operand() {
+    let types = |source: &str| -> Vec<TokenType> {
+      scan(source)
+        .unwrap()
+        .iter()
+        .map(|t| t.token_type())
+        .collect()
+    };
+    use TokenType::*;
+    assert_eq!(types("x--;"), vec![Identifier, MinusMinus, SemiColon, Eof]);
+    assert_eq!(
+      types("x++ + 1"),
+      vec![Identifier, PlusPlus, Plus, Number, Eof]
+    );
+    assert_eq!(types("--5"), vec![Minus, Minus, Number, Eof]);
+    assert_eq!(
+      types("a--b"),
+      vec![Identifier, Minus, Minus, Identifier, Eof]
+    );
+    assert_eq!(types("a ++"), vec![Identifier, Plus, Plus, Eof]);
+  }
+}
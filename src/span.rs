@@ -0,0 +1,121 @@
+use crate::expr::Expr;
+use crate::stmt::Stmt;
+use crate::token::Token;
+use std::cmp::Reverse;
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// # 源码范围
+/// 从第一个 token 的开头到最后一个 token 的结尾，行列都从 1 开始，结尾包含在内
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Span {
+  pub start_line: usize,
+  pub start_column: usize,
+  pub end_line: usize,
+  pub end_column: usize,
+}
+
+impl Span {
+  /// 覆盖 `first` 到 `last` 的范围，跨行的字符串按词素中的换行计算结尾
+  pub fn covering(first: &Token, last: &Token) -> Self {
+    let (end_line, end_column) = match last.lexeme.rsplit_once('\n') {
+      Some((before, after)) => (
+        last.line + before.matches('\n').count() + 1,
+        after.chars().count(),
+      ),
+      None => (last.line, last.column + last.len.max(1) - 1),
+    };
+    Self {
+      start_line: first.line,
+      start_column: first.column,
+      end_line,
+      end_column,
+    }
+  }
+
+  pub fn start(&self) -> (usize, usize) {
+    (self.start_line, self.start_column)
+  }
+
+  pub fn end(&self) -> (usize, usize) {
+    (self.end_line, self.end_column)
+  }
+
+  pub fn contains(&self, line: usize, column: usize) -> bool {
+    self.start() <= (line, column) && (line, column) <= self.end()
+  }
+}
+
+/// # 节点的源码范围
+/// 由解析器填写，节点按指针区分，和解释器的 `locals` 表一样
+#[derive(Debug, Default)]
+pub struct Spans {
+  exprs: HashMap<Expr, Span>,
+  stmts: HashMap<Stmt, Span>,
+}
+
+impl Spans {
+  pub fn insert_expr(&mut self, expr: &Expr, span: Span) {
+    self.exprs.insert(expr.clone(), span);
+  }
+
+  pub fn insert_stmt(&mut self, stmt: &Stmt, span: Span) {
+    self.stmts.insert(stmt.clone(), span);
+  }
+
+  pub fn expr(&self, expr: &Expr) -> Option<Span> {
+    self.exprs.get(expr).copied()
+  }
+
+  pub fn stmt(&self, stmt: &Stmt) -> Option<Span> {
+    self.stmts.get(stmt).copied()
+  }
+
+  /// 包含该位置的最内层表达式
+  pub fn expr_at(&self, line: usize, column: usize) -> Option<(&Expr, Span)> {
+    Spans::innermost(&self.exprs, line, column)
+  }
+
+  /// 包含该位置的最内层语句
+  pub fn stmt_at(&self, line: usize, column: usize) -> Option<(&Stmt, Span)> {
+    Spans::innermost(&self.stmts, line, column)
+  }
+
+  /// 范围互相嵌套，开始得最晚、结束得最早的就是最内层
+  fn innermost<T: Eq + Hash>(
+    nodes: &HashMap<T, Span>,
+    line: usize,
+    column: usize,
+  ) -> Option<(&T, Span)> {
+    nodes
+      .iter()
+      .filter(|(_, span)| span.contains(line, column))
+      .max_by_key(|(_, span)| (span.start(), Reverse(span.end())))
+      .map(|(node, span)| (node, *span))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+  use crate::parser::Parser;
+  use crate::scanner::Scanner;
+
+  #[test]
+  fn covering_a_multi_line_string() {
+    let mut scanner = Scanner::new("print \"one\ntwo\nend\";\nprint 1;".to_string());
+    let tokens = scanner.scan_tokens().ok().unwrap();
+    let span = Span::covering(&tokens[0], &tokens[1]);
+    assert_eq!((span.start(), span.end()), ((1, 1), (3, 4)));
+    assert!(span.contains(2, 100));
+    assert!(!span.contains(3, 5));
+
+    let mut parser = Parser::new(tokens);
+    parser.parse().ok().unwrap();
+    let (literal, span) = parser.spans().expr_at(2, 2).unwrap();
+    assert!(matches!(literal, Expr::Literal(_)));
+    assert_eq!((span.start(), span.end()), ((1, 7), (3, 4)));
+    assert!(parser.spans().expr_at(4, 7).is_some());
+    assert!(parser.spans().expr_at(5, 1).is_none());
+  }
+}
@@ -0,0 +1,72 @@
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fmt;
+use std::fmt::Formatter;
+use std::num::NonZeroU32;
+use std::rc::Rc;
+
+/// # 符号
+/// 驻留后的标识符，比较和哈希只需要一个整数。
+/// 同一个线程中相同的名字总是得到相同的符号。
+/// 内部从 1 开始编号，`Option<Symbol>` 不会让 `Token` 变大
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(NonZeroU32);
+
+#[derive(Default)]
+struct Interner {
+  names: Vec<Rc<str>>,
+  symbols: HashMap<Rc<str>, Symbol>,
+}
+
+thread_local! {
+  static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+  pub fn intern(name: &str) -> Symbol {
+    INTERNER.with(|interner| {
+      let mut interner = interner.borrow_mut();
+      if let Some(symbol) = interner.symbols.get(name) {
+        return *symbol;
+      }
+
+      let symbol = Symbol(NonZeroU32::new(interner.names.len() as u32 + 1).unwrap());
+      let name: Rc<str> = Rc::from(name);
+      interner.names.push(Rc::clone(&name));
+      interner.symbols.insert(name, symbol);
+      symbol
+    })
+  }
+
+  pub fn as_str(&self) -> Rc<str> {
+    INTERNER.with(|interner| Rc::clone(&interner.borrow().names[self.0.get() as usize - 1]))
+  }
+}
+
+impl From<&str> for Symbol {
+  fn from(name: &str) -> Self {
+    Symbol::intern(name)
+  }
+}
+
+impl fmt::Display for Symbol {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    write!(f, "{}", self.as_str())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn same_name_gives_same_symbol() {
+    let a = Symbol::intern("counter");
+    let b = Symbol::from("counter");
+    let c = Symbol::intern("Counter");
+    assert_eq!(a, b);
+    assert_ne!(a, c);
+    assert_eq!(&*a.as_str(), "counter");
+    assert_eq!(c.to_string(), "Counter");
+  }
+}
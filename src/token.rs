@@ -1,23 +1,49 @@
 use super::token_type::*;
 use crate::object::Object;
+use crate::symbol::Symbol;
+use serde::{Deserialize, Serialize};
 use std::fmt;
 use std::fmt::Formatter;
+use std::rc::Rc;
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Token {
   pub t_type: TokenType,
-  pub lexeme: String,
+  /// 复制 token 时共享同一份词素
+  pub lexeme: Rc<str>,
   pub literal: Option<Object>,
   pub line: usize,
+  /// 从 1 开始的列号，0 表示未知
+  pub column: usize,
+  /// 在源码中的起始字符偏移
+  pub start: usize,
+  /// 在源码中占用的字符数
+  pub len: usize,
+  /// 标识符驻留后的符号，其余 token 为 `None`
+  #[serde(skip)]
+  pub symbol: Option<Symbol>,
 }
 
 impl Token {
-  pub fn new(t_type: TokenType, lexeme: String, literal: Option<Object>, line: usize) -> Token {
+  pub fn new(
+    t_type: TokenType,
+    lexeme: impl Into<Rc<str>>,
+    literal: Option<Object>,
+    line: usize,
+    column: usize,
+  ) -> Token {
+    let lexeme: Rc<str> = lexeme.into();
+    let len = lexeme.chars().count();
+    let symbol = (t_type == TokenType::Identifier).then(|| Symbol::intern(&lexeme));
     Token {
       t_type,
       lexeme,
       literal,
       line,
+      column,
+      start: 0,
+      len,
+      symbol,
     }
   }
 
@@ -25,20 +51,34 @@ impl Token {
     self.t_type == t_type
   }
 
+  /// 是否是给定类型中的任意一种
+  pub fn is_one_of(&self, types: &[TokenType]) -> bool {
+    types.contains(&self.t_type)
+  }
+
   pub fn token_type(&self) -> TokenType {
     self.t_type
   }
 
   pub fn as_string(&self) -> String {
-    self.lexeme.clone()
+    self.lexeme.to_string()
+  }
+
+  /// 名字对应的符号，从 JSON 读回的 token 没有保存符号，这时才重新驻留
+  pub fn symbol(&self) -> Symbol {
+    self.symbol.unwrap_or_else(|| Symbol::intern(&self.lexeme))
   }
 
   pub fn dup(&self) -> Self {
     Token {
       t_type: self.t_type,
-      lexeme: self.lexeme.to_string(),
+      lexeme: Rc::clone(&self.lexeme),
       literal: self.literal.clone(),
       line: self.line,
+      column: self.column,
+      start: self.start,
+      len: self.len,
+      symbol: self.symbol,
     }
   }
 
@@ -47,9 +87,13 @@ impl Token {
   pub fn eof(line: usize) -> Token {
     Token {
       t_type: TokenType::Eof,
-      lexeme: "".to_string(),
+      lexeme: Rc::from(""),
       literal: None,
       line,
+      column: 0,
+      start: 0,
+      len: 0,
+      symbol: None,
     }
   }
 }
@@ -69,3 +113,27 @@ impl fmt::Display for Token {
     )
   }
 }
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn dup_shares_the_lexeme() {
+    let token = Token::new(TokenType::Identifier, "count", None, 1, 1);
+    let copy = token.dup();
+    assert!(Rc::ptr_eq(&token.lexeme, &copy.lexeme));
+    assert_eq!(copy, token);
+    assert_eq!(copy.as_string(), "count");
+    assert_eq!(copy.to_string(), "Identifier count None");
+  }
+
+  #[test]
+  fn is_one_of_any_type() {
+    let token = Token::new(TokenType::Plus, "+", None, 1, 1);
+    assert!(token.is_one_of(&[TokenType::Minus, TokenType::Plus]));
+    assert!(token.is_one_of(&[TokenType::Plus]));
+    assert!(!token.is_one_of(&[TokenType::Minus, TokenType::Star]));
+    assert!(!token.is_one_of(&[]));
+  }
+}
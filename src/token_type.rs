@@ -1,44 +1,173 @@
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::fmt::Formatter;
+
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum TokenType {
   LeftParen,  // (
   RightParen, // )
   LeftBrace,  // {
   RightBrace, // }
+  Colon,
   Comma,
   Dot,
+  Ellipsis, // ...
   Minus,
   Plus,
   SemiColon,
   Slash,
   Star,
-  Bang,      // !
-  BangEqual, // !=
-  Assign,    // Assign ('=')
-  Equal,     // Equal ('==')
+  StarStar,    // **
+  PlusPlus,    // ++
+  MinusMinus,  // --
+  PlusAssign,  // +=
+  MinusAssign, // -=
+  StarAssign,  // *=
+  SlashAssign, // /=
+  Bang,        // !
+  BangEqual,   // !=
+  Assign,      // Assign ('=')
+  Equal,       // Equal ('==')
   Greater,
   GreaterEqual,
   Less,
   LessEqual,
+  QuestionQuestion, // ??
+  Ampersand,        // &
+  Pipe,             // |
+  Caret,            // ^
+  LessLess,         // <<
+  GreaterGreater,   // >>
   Identifier,
   String,
+  Interpolation, // 字符串中 `${` 之前的部分
   Number,
   And,
+  As,
+  Assert,
+  Catch,
   Class,
+  Const,
   Else,
   False,
   Fun,
+  Global,
   For,
   If,
+  Import,
+  In,
   Nil,
   Or,
   Print,
+  Repeat,
   Return,
+  Static,
   Super,
   This,
+  Throw,
   True,
+  Try,
+  Undef,
   Var,
   Def,
+  Do,
   While,
   Eof,
   Break,
+  Continue,
+}
+
+/// 给用户看的名称，符号和关键字显示为源码中的写法
+impl fmt::Display for TokenType {
+  fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+    let name = match self {
+      TokenType::LeftParen => "(",
+      TokenType::RightParen => ")",
+      TokenType::LeftBrace => "{",
+      TokenType::RightBrace => "}",
+      TokenType::Colon => ":",
+      TokenType::Comma => ",",
+      TokenType::Dot => ".",
+      TokenType::Ellipsis => "...",
+      TokenType::Minus => "-",
+      TokenType::Plus => "+",
+      TokenType::SemiColon => ";",
+      TokenType::Slash => "/",
+      TokenType::Star => "*",
+      TokenType::StarStar => "**",
+      TokenType::PlusPlus => "++",
+      TokenType::MinusMinus => "--",
+      TokenType::PlusAssign => "+=",
+      TokenType::MinusAssign => "-=",
+      TokenType::StarAssign => "*=",
+      TokenType::SlashAssign => "/=",
+      TokenType::Bang => "!",
+      TokenType::BangEqual => "!=",
+      TokenType::Assign => "=",
+      TokenType::Equal => "==",
+      TokenType::Greater => ">",
+      TokenType::GreaterEqual => ">=",
+      TokenType::Less => "<",
+      TokenType::LessEqual => "<=",
+      TokenType::QuestionQuestion => "??",
+      TokenType::Ampersand => "&",
+      TokenType::Pipe => "|",
+      TokenType::Caret => "^",
+      TokenType::LessLess => "<<",
+      TokenType::GreaterGreater => ">>",
+      TokenType::Identifier => "identifier",
+      TokenType::String | TokenType::Interpolation => "string",
+      TokenType::Number => "number",
+      TokenType::And => "and",
+      TokenType::As => "as",
+      TokenType::Assert => "assert",
+      TokenType::Catch => "catch",
+      TokenType::Class => "class",
+      TokenType::Const => "const",
+      TokenType::Else => "else",
+      TokenType::False => "false",
+      TokenType::Fun => "fun",
+      TokenType::Global => "global",
+      TokenType::For => "for",
+      TokenType::If => "if",
+      TokenType::Import => "import",
+      TokenType::In => "in",
+      TokenType::Nil => "nil",
+      TokenType::Or => "or",
+      TokenType::Print => "print",
+      TokenType::Repeat => "repeat",
+      TokenType::Return => "return",
+      TokenType::Static => "static",
+      TokenType::Super => "super",
+      TokenType::This => "this",
+      TokenType::Throw => "throw",
+      TokenType::True => "true",
+      TokenType::Try => "try",
+      TokenType::Undef => "undef",
+      TokenType::Var => "var",
+      TokenType::Def => "def",
+      TokenType::Do => "do",
+      TokenType::While => "while",
+      TokenType::Eof => "end of file",
+      TokenType::Break => "break",
+      TokenType::Continue => "continue",
+    };
+    write!(f, "{name}")
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn display_names() {
+    assert_eq!(TokenType::Plus.to_string(), "+");
+    assert_eq!(TokenType::SemiColon.to_string(), ";");
+    assert_eq!(TokenType::GreaterGreater.to_string(), ">>");
+    assert_eq!(TokenType::Identifier.to_string(), "identifier");
+    assert_eq!(TokenType::Number.to_string(), "number");
+    assert_eq!(TokenType::While.to_string(), "while");
+    assert_eq!(TokenType::Eof.to_string(), "end of file");
+  }
 }
@@ -0,0 +1,68 @@
+use crate::error::{error_format, set_error_format, set_source, take_captured_errors, ErrorFormat};
+use crate::interpreter::{Interpreter, SharedBuffer};
+use crate::parser::Parser;
+use crate::resolver::Resolver;
+use crate::scanner::Scanner;
+use std::rc::Rc;
+use wasm_bindgen::prelude::wasm_bindgen;
+
+/// 浏览器中 wasm 的栈通常只有 1MB，调用深度比默认值更低
+const WASM_MAX_CALL_DEPTH: usize = 50;
+
+/// # 在浏览器中运行
+/// 执行一段程序，返回程序的输出，随后是报告的错误。
+/// 不会调用 `std::process::exit`，出错时只是提前结束。
+#[wasm_bindgen]
+pub fn run_to_string(source: &str) -> String {
+  let capture = SharedBuffer::default();
+  let interpreter = Interpreter::with_writer(Box::new(capture.clone()));
+  interpreter.set_max_call_depth(WASM_MAX_CALL_DEPTH);
+
+  let format = error_format();
+  set_error_format(ErrorFormat::Captured);
+  run(&interpreter, source);
+  set_error_format(format);
+
+  let mut result = capture.contents();
+  result.push_str(&take_captured_errors());
+  result
+}
+
+/// 错误在创建时已经被收集，这里只需在出错时停止
+fn run(interpreter: &Interpreter, source: &str) {
+  set_source(source);
+  let mut scanner = Scanner::new(source.to_string());
+  let Ok(tokens) = scanner.scan_tokens() else {
+    return;
+  };
+
+  let mut parser = Parser::new(tokens);
+  let Ok(statements) = parser.parse() else {
+    return;
+  };
+  if !parser.success() {
+    return;
+  }
+
+  let resolver = Resolver::new(interpreter);
+  let statements = Rc::new(statements);
+  if resolver.resolve(&statements).is_ok() && resolver.success() {
+    let _ = interpreter.interpreter(&statements);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn returns_captured_output() {
+    assert_eq!(run_to_string("print 1 + 2;\nprint \"hi\";"), "3\nhi\n");
+  }
+
+  #[test]
+  fn returns_errors_after_output() {
+    let result = run_to_string("print 1;\nprint nope;");
+    assert!(result.starts_with("1\nline 2:7 at 'nope' Undefined variable 'nope'."));
+  }
+}